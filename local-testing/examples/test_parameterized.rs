@@ -1,5 +1,9 @@
 use std::fs;
 
+// The exact code behind windmill-scripts/03-parameterized/salary_calculator.rs
+#[path = "../src/parameterized.rs"]
+mod parameterized;
+
 fn main() {
     // Read test CSV
     let csv_data = fs::read_to_string("test_employees.csv")
@@ -22,65 +26,7 @@ fn main() {
     test_salary_raise(csv_data, 5.0, None);
 }
 
-// Copy the main function from parameterized.rs
 fn test_salary_raise(csv_data: String, raise_percent: f64, min_age: Option<i32>) {
-    use polars::prelude::*;
-    use std::io::Cursor;
-
-    // Parse CSV
-    let df = CsvReadOptions::default()
-        .with_has_header(true)
-        .into_reader_with_file_handle(Cursor::new(csv_data.as_bytes()))
-        .finish()
-        .unwrap();
-
-    println!("Original Data ({} rows):", df.height());
-    println!("{}", df);
-    println!();
-
-    // Build transformation
-    let mut lazy_df = df.lazy();
-
-    if let Some(min) = min_age {
-        println!("Filtering: age > {}", min);
-        lazy_df = lazy_df.filter(col("age").gt(lit(min)));
-    }
-
-    let raise_multiplier = 1.0 + (raise_percent / 100.0);
-
-    let result = lazy_df
-        .with_columns([col("salary").alias("old_salary")])
-        .with_columns([
-            (col("salary") * lit(raise_multiplier)).alias("new_salary"),
-            (col("salary") * lit(raise_percent / 100.0)).alias("raise_amount"),
-        ])
-        .collect()
-        .unwrap();
-
-    println!("Transformed Data ({} rows):", result.height());
-    println!("{}", result);
-    println!();
-
-    let total_old: f64 = result
-        .column("old_salary")
-        .unwrap()
-        .as_materialized_series()
-        .cast(&DataType::Float64)
-        .unwrap()
-        .sum()
+    parameterized::main(Some(csv_data), raise_percent, min_age, None, None, None, None, None)
         .unwrap();
-
-    let total_new: f64 = result
-        .column("new_salary")
-        .unwrap()
-        .as_materialized_series()
-        .cast(&DataType::Float64)
-        .unwrap()
-        .sum()
-        .unwrap();
-
-    println!("Summary:");
-    println!("  Total Old Salary: ${:.2}", total_old);
-    println!("  Total New Salary: ${:.2}", total_new);
-    println!("  Total Raise Cost: ${:.2}", total_new - total_old);
 }
@@ -1,5 +1,5 @@
 use polars::prelude::*;
-use serde_json::json;
+use rustetl_core::PipelineResult;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a DataFrame
@@ -12,6 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Original DataFrame:");
     println!("{}", df);
     println!();
+    let rows_in = df.height();
 
     // Transform
     let result = df
@@ -25,17 +26,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!();
 
     // Show as JSON (like Windmill output)
-    let columns: Vec<String> = result
-        .get_column_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-
-    let output = json!({
-        "rows": result.height(),
-        "columns": columns,
-        "preview": format!("{}", result)
-    });
+    let mut output = PipelineResult::new("local_demo");
+    output.rows_in = rows_in;
+    output.output_with_rows(&result);
+    let output = output.finish();
 
     println!("JSON Output:");
     println!("{}", serde_json::to_string_pretty(&output)?);
@@ -1,98 +1,134 @@
-use polars::prelude::*;
-use serde_json::{json, Value};
-use std::io::Cursor;
+use rustetl_core::{Contract, PipelineResult};
+use rustetl_core::ingest::{self, BadRows};
+use rustetl_core::io::{self, FileOptions};
+use rustetl_core::{records, salary};
+use serde_json::json;
 
 /// Process employee data with salary adjustment
 ///
 /// # Arguments
-/// * `csv_data` - CSV string with employee data (columns: name, age, department, salary)
+/// * `csv_data` - Optional: CSV string with employee data (columns: name, age, department, salary)
 /// * `raise_percent` - Percentage to increase salary (e.g., 10 for 10% raise)
 /// * `min_age` - Optional: Only apply raise to employees older than this age
-fn main(
-    csv_data: String,
+/// * `input_path` - Optional: CSV, Parquet or Arrow IPC file to read instead of `csv_data`
+/// * `output_path` - Optional: File to write the result to; the extension picks the format
+/// * `compression` - Optional: uncompressed, snappy, gzip, lz4 or zstd
+/// * `row_group_size` - Optional: Rows per Parquet row group
+/// * `bad_rows` - Optional: `fail` (default) or `quarantine` to load the rows that parse
+///   and return the rest in `details.quarantine`
+///
+/// The input is checked against `Contract::employees()` first; violations
+/// come back in `details.contract` with `status: "error"`.
+#[allow(clippy::too_many_arguments)]
+pub fn main(
+    csv_data: Option<String>,
     raise_percent: f64,
     min_age: Option<i32>,
-) -> anyhow::Result<Value> {
+    input_path: Option<String>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    bad_rows: Option<String>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("salary_calculator");
+    let file_options = FileOptions::parse(compression.as_deref(), row_group_size)?;
+    let bad_rows: BadRows = bad_rows.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+
     println!("Parameters:");
     println!("  Raise: {}%", raise_percent);
     println!("  Min Age Filter: {:?}", min_age.unwrap_or(0));
     println!();
 
-    // Parse CSV from string
-    let df = CsvReadOptions::default()
-        .with_has_header(true)
-        .try_into_reader_with_file_handle(Cursor::new(csv_data.as_bytes()))?
-        .finish()?;
-
+    // Parse pasted CSV or read the input file
+    run.stage("read");
+    let contract = Contract::employees();
+    let ingested = ingest::read_input(
+        csv_data.as_deref(),
+        input_path.as_deref(),
+        Some(&contract),
+        bad_rows,
+    )?;
+    let df = ingested.data;
+
+    run.rows_in = ingested.report.rows_read;
+    if bad_rows == BadRows::Quarantine {
+        let quarantined = ingested.report.rows_quarantined;
+        if quarantined > 0 {
+            println!("Quarantined {} of {} rows:", quarantined, run.rows_in);
+            println!("{}", ingested.quarantine);
+            println!();
+            run.warn(format!("{} rows quarantined", quarantined));
+        }
+        run.detail("ingest", &ingested.report)?;
+        run.detail("quarantine", records::records(&ingested.quarantine))?;
+    }
     println!("Original Data ({} rows):", df.height());
     println!("{}", df);
     println!();
 
-    // Build the transformation
-    let mut lazy_df = df.lazy();
+    // Check columns, types and ranges before transforming
+    run.stage("validate");
+    let report = contract.check(&df)?;
+    run.detail("contract", &report)?;
+    if let Err(err) = report.ensure() {
+        println!("Input does not match the employee contract:");
+        for violation in &report.violations {
+            match violation.row {
+                Some(row) => println!("  - row {}, {}: {}", row, violation.column, violation.message),
+                None => println!("  - {}", violation.message),
+            }
+        }
+        run.fail(err.to_string());
+        return Ok(run.finish());
+    }
 
-    // Apply age filter if specified
     if let Some(min) = min_age {
         println!("Filtering: age > {}", min);
-        lazy_df = lazy_df.filter(col("age").gt(lit(min)));
     }
 
-    // Calculate raise
-    let raise_multiplier = 1.0 + (raise_percent / 100.0);
-
-    // Apply transformations
-    let result = lazy_df
-        .with_columns([
-            col("salary").alias("old_salary"),
-        ])
-        .with_columns([
-            (col("salary") * lit(raise_multiplier)).alias("new_salary"),
-            (col("salary") * lit(raise_percent / 100.0)).alias("raise_amount"),
-        ])
-        .collect()?;
+    run.stage("transform");
+    let mut result = salary::apply_raise(df, raise_percent, min_age)?;
 
     println!("Transformed Data ({} rows):", result.height());
     println!("{}", result);
     println!();
 
     // Calculate summary statistics
-    let total_old = result
-        .column("old_salary")?
-        .cast(&DataType::Float64)?
-        .sum::<f64>()
-        .unwrap_or(0.0);
-
-    let total_new = result
-        .column("new_salary")?
-        .cast(&DataType::Float64)?
-        .sum::<f64>()
-        .unwrap_or(0.0);
-
-    let total_raise = total_new - total_old;
+    let summary = salary::summarize(&result)?;
 
     println!("Summary:");
-    println!("  Total Old Salary: ${:.2}", total_old);
-    println!("  Total New Salary: ${:.2}", total_new);
-    println!("  Total Raise Cost: ${:.2}", total_raise);
-
-    // Convert to JSON for Windmill output
-    let columns: Vec<String> = result
-        .get_column_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-
-    Ok(json!({
-        "summary": {
+    println!("  Total Old Salary: ${:.2}", summary.total_old);
+    println!("  Total New Salary: ${:.2}", summary.total_new);
+    println!("  Total Raise Cost: ${:.2}", summary.total_raise);
+
+    if let Some(path) = &output_path {
+        run.stage("write");
+        let written = io::write_file(&mut result, path, &file_options)?;
+        println!();
+        println!(
+            "Wrote {} rows to {} ({} bytes)",
+            written.rows, written.path, written.bytes
+        );
+        run.artifact(written);
+    }
+    if result.height() == 0 {
+        run.warn("no employees matched the age filter");
+    }
+
+    // Typed rows and totals for Windmill output
+    run.output_with_rows(&result);
+    run.detail(
+        "summary",
+        json!({
             "total_employees": result.height(),
             "raise_percent": raise_percent,
-            "total_old_salary": total_old,
-            "total_new_salary": total_new,
-            "total_raise_cost": total_raise,
-        },
-        "columns": columns,
-        "data": format!("{}", result),
-    }))
+            "total_old_salary": summary.total_old,
+            "total_new_salary": summary.total_new,
+            "total_raise_cost": summary.total_raise,
+        }),
+    )?;
+
+    Ok(run.finish())
 }
 
 #[cfg(test)]
@@ -106,7 +142,107 @@ Alice,28,Engineering,75000
 Bob,34,Sales,65000
 Charlie,29,Engineering,82000"#;
 
-        let result = main(csv.to_string(), 10.0, None).unwrap();
+        let result = main(Some(csv.to_string()), 10.0, None, None, None, None, None, None).unwrap();
         println!("{}", serde_json::to_string_pretty(&result).unwrap());
+
+        // Rows come back typed, ready for the next flow step
+        assert_eq!((result.rows_in, result.rows_out), (3, 3));
+        assert_eq!(result.schema[4].name, "old_salary");
+        let data = result.data.as_ref().unwrap();
+        assert!(!data.truncated);
+        assert_eq!(data.data[1]["name"], "Bob");
+        assert_eq!(data.data[1]["new_salary"], 71500.0);
+        let stages: Vec<_> = result.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(stages, ["read", "validate", "transform"]);
+    }
+
+    #[test]
+    fn test_contract_violations() {
+        let csv = r#"name,age,salary
+Alice,28,75000
+Bob,thirty,65000"#;
+
+        let result = main(Some(csv.to_string()), 10.0, None, None, None, None, None, None).unwrap();
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(value["status"], "error");
+        assert_eq!(result.rows_out, 0);
+        let contract = &result.details["contract"];
+        assert_eq!(contract["violation_count"], 2);
+        assert_eq!(contract["violations"][0]["row"], 1);
+        assert_eq!(contract["violations"][0]["value"], "thirty");
+        assert_eq!(
+            contract["violations"][1]["message"],
+            "required column `department` is missing"
+        );
+        assert!(result.errors[0].contains("2 violations in 1 of 2 rows"));
+    }
+
+    #[test]
+    fn test_quarantine_bad_rows() {
+        let csv = r#"name,age,department,salary
+Alice,28,Engineering,75000
+Bob,34,Sales,65000,extra
+Charlie,twenty,Engineering,82000
+Diana,41,HR,-1
+Eve,39,Sales,70000"#;
+
+        // One bad line no longer fails the whole load
+        let strict = main(Some(csv.to_string()), 10.0, None, None, None, None, None, None);
+        assert!(strict.is_err());
+        let result = main(
+            Some(csv.to_string()),
+            10.0,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("quarantine".to_string()),
+        )
+        .unwrap();
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(value["status"], "success");
+        assert_eq!((result.rows_in, result.rows_out), (5, 2));
+        assert_eq!(result.warnings, ["3 rows quarantined"]);
+        let ingest = &result.details["ingest"];
+        assert_eq!(ingest["rows_loaded"], 2);
+        assert_eq!(ingest["rows_quarantined"], 3);
+        let quarantined = &result.details["quarantine"]["data"];
+        assert_eq!(quarantined[0]["line"], 3);
+        assert_eq!(quarantined[0]["reason"], "field_count");
+        assert_eq!(quarantined[1]["message"], "age: `twenty` is not an integer");
+        assert_eq!(quarantined[2]["reason"], "contract");
+        assert_eq!(quarantined[2]["raw"], "Diana,41,HR,-1");
+    }
+
+    #[test]
+    fn test_parquet_round_trip() {
+        let csv = r#"name,age,department,salary
+Alice,28,Engineering,75000
+Bob,34,Sales,65000"#;
+        let dir = std::env::temp_dir().join(format!("polars-test-{}", std::process::id()));
+        let raised = dir.join("raised.parquet").display().to_string();
+
+        let first = main(
+            Some(csv.to_string()),
+            10.0,
+            None,
+            None,
+            Some(raised.clone()),
+            Some("snappy".to_string()),
+            Some(1),
+            None,
+        )
+        .unwrap();
+        let artifact = serde_json::to_value(&first.artifacts[0]).unwrap();
+        assert_eq!(artifact["kind"], "file");
+        assert_eq!(artifact["format"], "parquet");
+        assert_eq!(artifact["rows"], 2);
+
+        // Feed the Parquet output back in as input
+        let second = main(None, 0.0, Some(30), Some(raised), None, None, None, None).unwrap();
+        assert_eq!((second.rows_in, second.rows_out), (2, 1));
+        assert_eq!(second.details["summary"]["total_old_salary"], 65000.0);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
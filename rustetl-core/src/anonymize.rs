@@ -0,0 +1,131 @@
+//! Field-level anonymization used by the `customers` → `customers_anonymized`
+//! pipelines.
+
+use crate::policy::Policy;
+use crate::pseudonym::Pseudonymizer;
+use polars::prelude::*;
+
+/// Masked value written in place of every phone number.
+pub const MASKED_PHONE: &str = "***-***-****";
+/// Masked value written in place of every SSN.
+pub const MASKED_SSN: &str = "***-**-****";
+/// Value written in place of every address.
+pub const REDACTED: &str = "REDACTED";
+
+/// Salary range boundaries of the customers policy.
+pub const SALARY_BREAKS: [f64; 4] = [50000.0, 75000.0, 100000.0, 125000.0];
+pub const SALARY_LABELS: [&str; 5] = [
+    "< $50k",
+    "$50k-$75k",
+    "$75k-$100k",
+    "$100k-$125k",
+    "> $125k",
+];
+
+/// Age ranges of the customers policy; exact ages are a quasi-identifier.
+pub const AGE_BREAKS: [f64; 5] = [25.0, 35.0, 45.0, 55.0, 65.0];
+pub const AGE_LABELS: [&str; 6] = ["18-24", "25-34", "35-44", "45-54", "55-64", "65+"];
+
+/// Anonymize a frame with the `customers` columns
+/// (`id, name, email, phone, address, age, salary, ssn`) using
+/// [`Policy::customers`].
+///
+/// Returns the `customers_anonymized` columns
+/// (`id, name_hash, email_hash, phone, address, age, salary_bucket, ssn,
+/// pseudonym_key_id`), with `age` as a range label. Nulls stay null.
+pub fn anonymize_customers(
+    df: &DataFrame,
+    pseudonymizer: &Pseudonymizer,
+) -> PolarsResult<DataFrame> {
+    Policy::customers().apply(df, pseudonymizer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_salary_bucket_boundaries() {
+        let df = df! {
+            "id" => &[1, 2, 3, 4, 5],
+            "name" => &["A", "B", "C", "D", "E"],
+            "email" => &["a@x.com", "b@x.com", "c@x.com", "d@x.com", "e@x.com"],
+            "phone" => &["555-000-0001"; 5],
+            "address" => &["1 Main St"; 5],
+            "age" => &[18, 24, 25, 64, 65],
+            "salary" => &[49999, 50000, 99999, 100000, 125000],
+            "ssn" => &["123-45-6789"; 5],
+        }
+        .unwrap();
+
+        let pseudonymizer = Pseudonymizer::new(b"customers-test-key", Some("k1")).unwrap();
+        let out = Policy::customers().apply(&df, &pseudonymizer).unwrap();
+        let buckets = out.column("salary_bucket").unwrap().str().unwrap();
+        assert_eq!(
+            buckets.into_iter().collect::<Vec<_>>(),
+            [
+                Some("< $50k"),
+                Some("$50k-$75k"),
+                Some("$75k-$100k"),
+                Some("$100k-$125k"),
+                Some("> $125k")
+            ]
+        );
+        let ages = out.column("age").unwrap().str().unwrap();
+        assert_eq!(
+            ages.into_iter().collect::<Vec<_>>(),
+            [
+                Some("18-24"),
+                Some("18-24"),
+                Some("25-34"),
+                Some("55-64"),
+                Some("65+")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anonymize_customers() {
+        let df = df! {
+            "id" => &[1, 2],
+            "name" => &["Alice", "Bob"],
+            "email" => &["alice@example.com", "bob@example.com"],
+            "phone" => &[Some("555-123-4567"), None],
+            "address" => &["1 Main St", "2 High St"],
+            "age" => &[30, 45],
+            "salary" => &[60000, 130000],
+            "ssn" => &["123-45-6789", "987-65-4321"],
+        }
+        .unwrap();
+
+        let pseudonymizer = Pseudonymizer::new(b"customers-test-key", Some("k1")).unwrap();
+        let out = anonymize_customers(&df, &pseudonymizer).unwrap();
+        assert_eq!(
+            out.get_column_names_str(),
+            [
+                "id",
+                "name_hash",
+                "email_hash",
+                "phone",
+                "address",
+                "age",
+                "salary_bucket",
+                "ssn",
+                "pseudonym_key_id"
+            ]
+        );
+        let names = out.column("name_hash").unwrap().str().unwrap();
+        assert_eq!(
+            names.get(0),
+            Some(format!("Customer_{}", pseudonymizer.pseudonymize("Alice")).as_str())
+        );
+        let phones = out.column("phone").unwrap().str().unwrap();
+        assert_eq!(phones.get(0), Some(MASKED_PHONE));
+        assert_eq!(phones.get(1), None);
+        let buckets = out.column("salary_bucket").unwrap().str().unwrap();
+        assert_eq!(buckets.get(1), Some("> $125k"));
+        let ages = out.column("age").unwrap().str().unwrap();
+        assert_eq!(ages.get(0), Some("25-34"));
+        assert_eq!(ages.get(1), Some("45-54"));
+    }
+}
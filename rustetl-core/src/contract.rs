@@ -0,0 +1,645 @@
+//! Input contracts checked before a script transforms its input.
+//!
+//! A [`Contract`] lists the columns an input must have and what their
+//! values may be: a type, whether nulls are allowed, a numeric range, a set
+//! of allowed values and uniqueness. Like policies, contracts are TOML or
+//! JSON so Windmill scripts can take them as a parameter:
+//!
+//! ```toml
+//! [[columns]]
+//! name = "age"
+//! type = "integer"
+//! nullable = false
+//! min = 0
+//! max = 130
+//!
+//! [[columns]]
+//! name = "department"
+//! allowed = ["Engineering", "Sales", "HR"]
+//!
+//! [[columns]]
+//! name = "email"
+//! required = false        # checked only if present
+//! unique = true
+//! ```
+//!
+//! [`Contract::check`] reports every broken rule with the offending row and
+//! value instead of failing on the first, so one run shows everything that
+//! needs fixing. Columns the contract does not mention pass through.
+//!
+//! CSV columns with a single unparsable value are read as strings, so a
+//! typed column that arrives as text is checked value by value: `abc` in an
+//! `integer` column is reported for its row, not as a type mismatch of the
+//! whole column.
+
+use anyhow::{Context, bail, ensure};
+use chrono::NaiveDate;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Violations listed in a [`ContractReport`]; the counts cover all.
+pub const MAX_REPORTED_VIOLATIONS: usize = 100;
+
+/// Type a column's values must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    #[serde(alias = "int")]
+    Integer,
+    /// Any number; integer columns pass too.
+    #[serde(alias = "number")]
+    Float,
+    #[serde(alias = "str", alias = "text")]
+    String,
+    #[serde(alias = "bool")]
+    Boolean,
+    /// `YYYY-MM-DD`.
+    Date,
+}
+
+impl ValueType {
+    fn dtype(self) -> DataType {
+        match self {
+            ValueType::Integer => DataType::Int64,
+            ValueType::Float => DataType::Float64,
+            ValueType::String => DataType::String,
+            ValueType::Boolean => DataType::Boolean,
+            ValueType::Date => DataType::Date,
+        }
+    }
+
+    /// Whether a column read as `dtype` already has this type. Any column
+    /// can be read as text, since CSV readers infer all-digit columns such
+    /// as zip codes as numbers.
+    fn accepts(self, dtype: &DataType) -> bool {
+        match self {
+            ValueType::Integer => dtype.is_integer(),
+            ValueType::Float => dtype.is_numeric(),
+            ValueType::String => true,
+            ValueType::Boolean => dtype == &DataType::Boolean,
+            ValueType::Date => dtype == &DataType::Date,
+        }
+    }
+
+    /// Parse text values; values that do not parse become null.
+    pub(crate) fn parse(self, text: &StringChunked) -> PolarsResult<Series> {
+        let name = text.name().clone();
+        match self {
+            ValueType::Boolean => Ok(text
+                .into_iter()
+                .map(|v| v.and_then(parse_bool))
+                .collect::<BooleanChunked>()
+                .with_name(name)
+                .into_series()),
+            ValueType::Date => {
+                let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+                text.into_iter()
+                    .map(|v| {
+                        let date = NaiveDate::parse_from_str(v?.trim(), "%Y-%m-%d").ok()?;
+                        Some((date - epoch).num_days() as i32)
+                    })
+                    .collect::<Int32Chunked>()
+                    .with_name(name)
+                    .into_series()
+                    .cast(&DataType::Date)
+            }
+            _ => text.clone().into_series().cast(&self.dtype()),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "t" | "yes" | "1" => Some(true),
+        "false" | "f" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Rules for one column. Only `name` is mandatory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnContract {
+    pub name: String,
+    /// A missing column is a violation unless this is `false`.
+    #[serde(default = "default_true")]
+    pub required: bool,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<ValueType>,
+    #[serde(default = "default_true")]
+    pub nullable: bool,
+    /// Inclusive bounds for `integer` and `float` columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Values the column may hold, compared as text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    /// No two non-null values are equal.
+    #[serde(default)]
+    pub unique: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ColumnContract {
+    /// A required, nullable column of any type.
+    pub fn new(name: &str) -> Self {
+        ColumnContract {
+            name: name.to_string(),
+            required: true,
+            value_type: None,
+            nullable: true,
+            min: None,
+            max: None,
+            allowed: None,
+            unique: false,
+        }
+    }
+
+    pub fn with_type(mut self, value_type: ValueType) -> Self {
+        self.value_type = Some(value_type);
+        self
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.nullable = false;
+        self
+    }
+
+    pub fn with_range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn with_allowed(mut self, values: &[&str]) -> Self {
+        self.allowed = Some(values.iter().map(|v| v.to_string()).collect());
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Contract {
+    pub columns: Vec<ColumnContract>,
+}
+
+/// Which rule a [`Violation`] broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Missing,
+    Type,
+    Null,
+    Range,
+    Allowed,
+    Unique,
+}
+
+/// One broken rule. Column-level problems (a missing column, a column of
+/// the wrong type) have no `row`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub column: String,
+    pub rule: Rule,
+    /// 0-based row of the frame; for CSV with a header, line `row + 2`.
+    pub row: Option<usize>,
+    pub value: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContractReport {
+    pub rows: usize,
+    pub passed: bool,
+    pub violation_count: usize,
+    /// Rows with at least one violation.
+    pub violating_rows: usize,
+    /// Violation count per column.
+    pub columns: BTreeMap<String, usize>,
+    pub violations: Vec<Violation>,
+}
+
+impl Contract {
+    pub fn new(columns: Vec<ColumnContract>) -> Self {
+        Contract { columns }
+    }
+
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let contract: Contract = toml::from_str(text).context("invalid TOML contract")?;
+        contract.validate()?;
+        Ok(contract)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let contract: Contract = serde_json::from_str(text).context("invalid JSON contract")?;
+        contract.validate()?;
+        Ok(contract)
+    }
+
+    /// Parse a contract given as a Windmill parameter: JSON if it starts
+    /// with `{`, TOML otherwise.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut names = HashSet::new();
+        for column in &self.columns {
+            let name = &column.name;
+            ensure!(
+                !name.trim().is_empty(),
+                "contract has a column without a name"
+            );
+            ensure!(
+                names.insert(name.as_str()),
+                "column `{name}` is listed twice"
+            );
+            if column.min.is_some() || column.max.is_some() {
+                ensure!(
+                    matches!(
+                        column.value_type,
+                        Some(ValueType::Integer | ValueType::Float)
+                    ),
+                    "column `{name}`: min and max need type integer or float"
+                );
+            }
+            if let (Some(min), Some(max)) = (column.min, column.max) {
+                ensure!(
+                    min <= max,
+                    "column `{name}`: min {min} is greater than max {max}"
+                );
+            }
+            if let Some(allowed) = &column.allowed
+                && allowed.is_empty()
+            {
+                bail!("column `{name}`: allowed values cannot be empty");
+            }
+        }
+        Ok(())
+    }
+
+    /// The input of `salary_calculator`: `name, age, department, salary`.
+    pub fn employees() -> Self {
+        Contract::new(vec![
+            ColumnContract::new("name")
+                .with_type(ValueType::String)
+                .not_null(),
+            ColumnContract::new("age")
+                .with_type(ValueType::Integer)
+                .not_null()
+                .with_range(Some(0.0), Some(130.0)),
+            ColumnContract::new("department").with_type(ValueType::String),
+            ColumnContract::new("salary")
+                .with_type(ValueType::Float)
+                .not_null()
+                .with_range(Some(0.0), None),
+        ])
+    }
+
+    /// Every violation in `df`, column by column.
+    pub fn violations(&self, df: &DataFrame) -> PolarsResult<Vec<Violation>> {
+        if let Err(err) = self.validate() {
+            polars_bail!(InvalidOperation: "{err}");
+        }
+        let mut violations = Vec::new();
+        for rule in &self.columns {
+            match df.column(&rule.name) {
+                Ok(column) => check_column(rule, column, &mut violations)?,
+                Err(_) if rule.required => violations.push(Violation {
+                    column: rule.name.clone(),
+                    rule: Rule::Missing,
+                    row: None,
+                    value: None,
+                    message: format!("required column `{}` is missing", rule.name),
+                }),
+                Err(_) => {}
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Check `df` and summarize the violations.
+    pub fn check(&self, df: &DataFrame) -> PolarsResult<ContractReport> {
+        let violations = self.violations(df)?;
+        let mut columns = BTreeMap::new();
+        for violation in &violations {
+            *columns.entry(violation.column.clone()).or_insert(0) += 1;
+        }
+        let rows: BTreeSet<usize> = violations.iter().filter_map(|v| v.row).collect();
+        Ok(ContractReport {
+            rows: df.height(),
+            passed: violations.is_empty(),
+            violation_count: violations.len(),
+            violating_rows: rows.len(),
+            columns,
+            violations: violations
+                .into_iter()
+                .take(MAX_REPORTED_VIOLATIONS)
+                .collect(),
+        })
+    }
+}
+
+fn check_column(
+    rule: &ColumnContract,
+    column: &Column,
+    out: &mut Vec<Violation>,
+) -> PolarsResult<()> {
+    let name = &rule.name;
+    let series = column.as_materialized_series().rechunk();
+    let text = series.cast(&DataType::String)?;
+    let text = text.str()?;
+    let violation = |rule: Rule, row: usize, value: Option<&str>, message: String| Violation {
+        column: name.clone(),
+        rule,
+        row: Some(row),
+        value: value.map(str::to_string),
+        message,
+    };
+
+    let typed = match rule.value_type {
+        None => Some(series.clone()),
+        Some(value_type) if value_type.accepts(series.dtype()) => {
+            Some(series.cast(&value_type.dtype())?)
+        }
+        Some(value_type) if matches!(series.dtype(), DataType::String | DataType::Null) => {
+            let parsed = value_type.parse(text)?;
+            let unparsed = text.is_not_null() & parsed.is_null();
+            for (row, bad) in unparsed.into_iter().enumerate() {
+                if bad == Some(true) {
+                    let value = text.get(row);
+                    out.push(violation(
+                        Rule::Type,
+                        row,
+                        value,
+                        format!(
+                            "`{}` is not {}",
+                            value.unwrap_or_default(),
+                            article(value_type)
+                        ),
+                    ));
+                }
+            }
+            Some(parsed)
+        }
+        Some(value_type) => {
+            out.push(Violation {
+                column: name.clone(),
+                rule: Rule::Type,
+                row: None,
+                value: None,
+                message: format!("expected {}, found {}", article(value_type), series.dtype()),
+            });
+            None
+        }
+    };
+
+    if !rule.nullable {
+        for (row, value) in text.iter().enumerate() {
+            if value.is_none() {
+                out.push(violation(
+                    Rule::Null,
+                    row,
+                    None,
+                    "value is missing".to_string(),
+                ));
+            }
+        }
+    }
+
+    if let Some(typed) = &typed
+        && (rule.min.is_some() || rule.max.is_some())
+    {
+        let numbers = typed.cast(&DataType::Float64)?;
+        for (row, number) in numbers.f64()?.iter().enumerate() {
+            let Some(number) = number else { continue };
+            let message = match (rule.min, rule.max) {
+                (Some(min), _) if number < min => format!("{number} is below the minimum {min}"),
+                (_, Some(max)) if number > max => format!("{number} is above the maximum {max}"),
+                _ => continue,
+            };
+            out.push(violation(Rule::Range, row, text.get(row), message));
+        }
+    }
+
+    if let Some(allowed) = &rule.allowed {
+        let allowed: HashSet<&str> = allowed.iter().map(String::as_str).collect();
+        for (row, value) in text.iter().enumerate() {
+            if let Some(value) = value
+                && !allowed.contains(value)
+            {
+                out.push(violation(
+                    Rule::Allowed,
+                    row,
+                    Some(value),
+                    format!("`{value}` is not an allowed value"),
+                ));
+            }
+        }
+    }
+
+    if rule.unique {
+        let mut seen = HashMap::new();
+        for (row, value) in text.iter().enumerate() {
+            let Some(value) = value else { continue };
+            if let Some(first) = seen.insert(value, row) {
+                seen.insert(value, first);
+                out.push(violation(
+                    Rule::Unique,
+                    row,
+                    Some(value),
+                    format!("`{value}` already appears in row {first}"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn article(value_type: ValueType) -> &'static str {
+    match value_type {
+        ValueType::Integer => "an integer",
+        ValueType::Float => "a number",
+        ValueType::String => "a string",
+        ValueType::Boolean => "a boolean",
+        ValueType::Date => "a YYYY-MM-DD date",
+    }
+}
+
+impl ContractReport {
+    /// Error out unless the input met the contract.
+    pub fn ensure(&self) -> anyhow::Result<()> {
+        if self.passed {
+            return Ok(());
+        }
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(column, count)| format!("{column}: {count}"))
+            .collect();
+        let first = self
+            .violations
+            .first()
+            .map(|v| match v.row {
+                Some(row) => format!("; first: row {row}, `{}`: {}", v.column, v.message),
+                None => format!("; first: {}", v.message),
+            })
+            .unwrap_or_default();
+        bail!(
+            "input contract failed: {} violations in {} of {} rows ({}){first}",
+            self.violation_count,
+            self.violating_rows,
+            self.rows,
+            columns.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salary::read_csv;
+
+    const CSV: &str = "name,age,department,salary
+Alice,28,Engineering,75000
+Bob,abc,Sales,65000
+,29,Marketing,-5
+Dan,200,Sales,82000.5";
+
+    fn rules(report: &ContractReport) -> Vec<(String, Rule, Option<usize>)> {
+        report
+            .violations
+            .iter()
+            .map(|v| (v.column.clone(), v.rule, v.row))
+            .collect()
+    }
+
+    #[test]
+    fn test_employees_contract() {
+        let contract = Contract::employees();
+        let good = read_csv("name,age,department,salary\nAlice,28,Engineering,75000").unwrap();
+        let report = contract.check(&good).unwrap();
+        assert!(report.passed);
+        assert!(report.ensure().is_ok());
+
+        let report = contract.check(&read_csv(CSV).unwrap()).unwrap();
+        assert!(!report.passed);
+        let s = |c: &str| c.to_string();
+        assert_eq!(
+            rules(&report),
+            [
+                (s("name"), Rule::Null, Some(2)),
+                (s("age"), Rule::Type, Some(1)),
+                (s("age"), Rule::Range, Some(3)),
+                (s("salary"), Rule::Range, Some(2)),
+            ]
+        );
+        assert_eq!(report.violations[1].value.as_deref(), Some("abc"));
+        assert_eq!(report.violations[3].message, "-5 is below the minimum 0");
+        assert_eq!(report.violating_rows, 3);
+        assert_eq!(report.columns["age"], 2);
+        let err = report.ensure().unwrap_err().to_string();
+        assert!(err.contains("4 violations in 3 of 4 rows"), "{err}");
+
+        let missing = read_csv("name,age\nAlice,28").unwrap();
+        let report = contract.check(&missing).unwrap();
+        assert_eq!(report.violation_count, 2);
+        assert_eq!(
+            report.violations[0].message,
+            "required column `department` is missing"
+        );
+    }
+
+    #[test]
+    fn test_parsed_contract() {
+        let contract = Contract::parse(
+            r#"
+            [[columns]]
+            name = "id"
+            type = "int"
+            unique = true
+
+            [[columns]]
+            name = "age"
+            type = "float"
+
+            [[columns]]
+            name = "active"
+            type = "bool"
+
+            [[columns]]
+            name = "joined"
+            type = "date"
+
+            [[columns]]
+            name = "plan"
+            allowed = ["free", "pro"]
+
+            [[columns]]
+            name = "email"
+            required = false
+            "#,
+        )
+        .unwrap();
+        let df = read_csv(
+            "id,age,active,joined,plan
+1,28.5,yes,2024-01-31,free
+2,x,no,2024-02-30,pro
+2,40,maybe,,team",
+        )
+        .unwrap();
+        let report = contract.check(&df).unwrap();
+        let s = |c: &str| c.to_string();
+        assert_eq!(
+            rules(&report),
+            [
+                (s("id"), Rule::Unique, Some(2)),
+                (s("age"), Rule::Type, Some(1)),
+                (s("active"), Rule::Type, Some(2)),
+                (s("joined"), Rule::Type, Some(1)),
+                (s("plan"), Rule::Allowed, Some(2)),
+            ]
+        );
+        assert_eq!(report.violations[0].message, "`2` already appears in row 1");
+
+        // A numeric column of the wrong kind fails as a whole
+        let df = read_csv("id,age,active,joined,plan\n1.5,1,true,2024-01-01,pro").unwrap();
+        let report = contract.check(&df).unwrap();
+        assert_eq!(rules(&report), [(s("id"), Rule::Type, None)]);
+        assert_eq!(
+            report.violations[0].message,
+            "expected an integer, found f64"
+        );
+
+        // Digits-only text is inferred as a number but still passes as a string
+        let zip = Contract::parse("[[columns]]\nname = \"zip\"\ntype = \"string\"").unwrap();
+        assert!(zip.check(&read_csv("zip\n12345").unwrap()).unwrap().passed);
+
+        assert!(Contract::parse(r#"{"columns": [{"name": "a", "min": 1}]}"#).is_err());
+        assert!(Contract::parse("[[columns]]\nname = \"a\"\n[[columns]]\nname = \"a\"").is_err());
+        assert!(
+            Contract::parse(
+                r#"{"columns": [{"name": "a", "type": "integer", "min": 5, "max": 1}]}"#
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! The `customers` record and its column-oriented form.
+
+use crate::faker::FakeKind;
+use polars::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// One generated customer, as inserted into `customers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Customer {
+    pub name: String,
+    pub email: String,
+    pub phone: String,
+    pub address: String,
+    pub age: i32,
+    pub salary: i32,
+    pub ssn: String,
+}
+
+impl Customer {
+    /// Generate a customer with realistic fake values.
+    pub fn fake() -> Self {
+        Self::fake_with(&mut rand::thread_rng())
+    }
+
+    /// Like [`fake`](Self::fake), drawing every field from `rng`.
+    pub fn fake_with<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Customer {
+            name: FakeKind::Name.generate_with(rng),
+            email: FakeKind::Email.generate_with(rng),
+            phone: FakeKind::Phone.generate_with(rng),
+            address: FakeKind::Address.generate_with(rng),
+            age: rng.gen_range(25..65),
+            salary: rng.gen_range(30000..150000),
+            ssn: FakeKind::Ssn.generate_with(rng),
+        }
+    }
+}
+
+/// `count` fake customers. The same `seed` always gives the same customers;
+/// without one they differ on every call.
+pub fn generate(count: usize, seed: Option<u64>) -> Vec<Customer> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    (0..count).map(|_| Customer::fake_with(&mut rng)).collect()
+}
+
+/// Frame of `customers` for bulk loading: `name, email, phone, address, age,
+/// salary, ssn`. `id` is left to the table's sequence.
+pub fn to_dataframe(customers: &[Customer]) -> PolarsResult<DataFrame> {
+    df! {
+        "name" => customers.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        "email" => customers.iter().map(|c| c.email.as_str()).collect::<Vec<_>>(),
+        "phone" => customers.iter().map(|c| c.phone.as_str()).collect::<Vec<_>>(),
+        "address" => customers.iter().map(|c| c.address.as_str()).collect::<Vec<_>>(),
+        "age" => customers.iter().map(|c| c.age).collect::<Vec<_>>(),
+        "salary" => customers.iter().map(|c| c.salary).collect::<Vec<_>>(),
+        "ssn" => customers.iter().map(|c| c.ssn.as_str()).collect::<Vec<_>>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_customer_ranges() {
+        for _ in 0..50 {
+            let c = Customer::fake();
+            assert!((25..65).contains(&c.age));
+            assert!((30000..150000).contains(&c.salary));
+            assert_eq!(c.ssn.len(), 11);
+        }
+    }
+
+    #[test]
+    fn test_seeded_customers_repeat() {
+        assert_eq!(generate(20, Some(42)), generate(20, Some(42)));
+        assert_ne!(generate(20, Some(42)), generate(20, Some(43)));
+        assert_eq!(generate(0, None).len(), 0);
+    }
+
+    #[test]
+    fn test_customers_to_dataframe() {
+        let customers: Vec<Customer> = (0..3).map(|_| Customer::fake()).collect();
+        let df = to_dataframe(&customers).unwrap();
+        assert_eq!(df.shape(), (3, 7));
+        assert_eq!(
+            df.column("email").unwrap().str().unwrap().get(2),
+            Some(customers[2].email.as_str())
+        );
+    }
+}
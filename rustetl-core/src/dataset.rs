@@ -0,0 +1,483 @@
+//! Anonymizing several related tables so foreign keys still join.
+//!
+//! A [`DatasetPolicy`] holds one [`Policy`] per table plus the foreign keys
+//! between them. Columns linked by foreign keys form a key domain (say
+//! `customers.id` and `orders.customer_id`), and every value in a domain is
+//! replaced by the same keyed pseudonym, `HMAC(key, domain || value)`,
+//! wherever it occurs. Joins on the outputs therefore match exactly the rows
+//! they matched before, while the pseudonyms of different domains (customer
+//! 5 and product 5) stay unrelated.
+//!
+//! ```toml
+//! [[foreign_keys]]
+//! from = "orders.customer_id"
+//! to = "customers.id"
+//!
+//! [tables.customers.columns.name]
+//! strategy = "hash"
+//!
+//! [tables.orders]
+//! unlisted = "keep"
+//! ```
+//!
+//! Key columns are always kept (under their `rename`, if any), even when
+//! the table's policy does not list them. Their rule may only be `keep` or
+//! `hash`, and either way the value becomes the key pseudonym; anything
+//! else would break the join.
+//! [`DatasetPolicy::check_joins`] compares join row counts before and after.
+
+use crate::policy::{ColumnRule, Policy, Strategy};
+use crate::pseudonym::Pseudonymizer;
+use anyhow::{Context, bail, ensure};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// `from` (`table.column`) references `to` (`table.column`). Table names
+/// may be schema-qualified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub from: String,
+    pub to: String,
+}
+
+impl ForeignKey {
+    pub fn new(from: &str, to: &str) -> Self {
+        ForeignKey {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+}
+
+/// Split `table.column` at the last dot.
+fn split_column(reference: &str) -> anyhow::Result<(&str, &str)> {
+    match reference.rsplit_once('.') {
+        Some((table, column)) if !table.is_empty() && !column.is_empty() => Ok((table, column)),
+        _ => bail!("`{reference}` is not a `table.column` reference"),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DatasetPolicy {
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    #[serde(default)]
+    pub tables: BTreeMap<String, Policy>,
+}
+
+/// Inner-join row counts of one foreign key before and after anonymization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JoinCheck {
+    pub from: String,
+    pub to: String,
+    pub original_rows: usize,
+    pub anonymized_rows: usize,
+}
+
+impl JoinCheck {
+    pub fn matches(&self) -> bool {
+        self.original_rows == self.anonymized_rows
+    }
+}
+
+impl DatasetPolicy {
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let policy: DatasetPolicy = toml::from_str(text).context("invalid TOML dataset policy")?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let policy: DatasetPolicy =
+            serde_json::from_str(text).context("invalid JSON dataset policy")?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Parse a policy given as a Windmill parameter: JSON if it starts with
+    /// `{`, TOML otherwise.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (table, policy) in &self.tables {
+            policy
+                .validate()
+                .with_context(|| format!("table `{table}`"))?;
+        }
+        for fk in &self.foreign_keys {
+            for reference in [&fk.from, &fk.to] {
+                let (table, column) = split_column(reference)?;
+                let Some(policy) = self.tables.get(table) else {
+                    bail!(
+                        "foreign key {} -> {}: table `{table}` has no policy",
+                        fk.from,
+                        fk.to
+                    );
+                };
+                if let Some(rule) = policy.columns.get(column)
+                    && !matches!(rule.strategy, Strategy::Keep | Strategy::Hash { .. })
+                {
+                    bail!("column `{reference}` is a key; its rule must be `keep` or `hash`");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The shopping dataset: the built-in customers policy, plus products,
+    /// orders and order items kept as they are apart from their keys.
+    pub fn shop() -> Self {
+        let keep_all = || Policy {
+            unlisted: crate::policy::Unlisted::Keep,
+            ..Policy::default()
+        };
+        DatasetPolicy {
+            foreign_keys: vec![
+                ForeignKey::new("orders.customer_id", "customers.id"),
+                ForeignKey::new("order_items.order_id", "orders.id"),
+                ForeignKey::new("order_items.product_id", "products.id"),
+            ],
+            tables: BTreeMap::from([
+                ("customers".to_string(), Policy::customers()),
+                ("products".to_string(), keep_all()),
+                ("orders".to_string(), keep_all()),
+                ("order_items".to_string(), keep_all()),
+            ]),
+        }
+    }
+
+    /// Key domains: sets of `table.column` linked by foreign keys, each
+    /// named after the smallest column some key references.
+    pub fn key_domains(&self) -> BTreeMap<String, BTreeSet<String>> {
+        let mut domains: Vec<(BTreeSet<String>, BTreeSet<String>)> = Vec::new();
+        for fk in &self.foreign_keys {
+            let linked: Vec<usize> = domains
+                .iter()
+                .enumerate()
+                .filter(|(_, (members, _))| members.contains(&fk.from) || members.contains(&fk.to))
+                .map(|(i, _)| i)
+                .collect();
+            let mut merged = (
+                BTreeSet::from([fk.from.clone(), fk.to.clone()]),
+                BTreeSet::from([fk.to.clone()]),
+            );
+            for i in linked.into_iter().rev() {
+                let (members, targets) = domains.swap_remove(i);
+                merged.0.extend(members);
+                merged.1.extend(targets);
+            }
+            domains.push(merged);
+        }
+        domains
+            .into_iter()
+            .map(|(members, targets)| (targets.into_iter().next().unwrap_or_default(), members))
+            .collect()
+    }
+
+    /// Key columns of `table` and the domain each belongs to.
+    fn key_columns(&self, table: &str) -> BTreeMap<String, String> {
+        let mut keys = BTreeMap::new();
+        for (domain, members) in self.key_domains() {
+            for member in members {
+                if let Ok((t, column)) = split_column(&member)
+                    && t == table
+                {
+                    keys.insert(column.to_string(), domain.clone());
+                }
+            }
+        }
+        keys
+    }
+
+    /// Output name of `table.column`.
+    fn output_column(&self, reference: &str) -> anyhow::Result<String> {
+        let (table, column) = split_column(reference)?;
+        let renamed = self
+            .tables
+            .get(table)
+            .and_then(|policy| policy.columns.get(column))
+            .and_then(|rule| rule.rename.clone());
+        Ok(renamed.unwrap_or_else(|| column.to_string()))
+    }
+
+    /// Anonymize every table in `frames` with its policy and the key
+    /// pseudonyms. Every frame needs a policy; a table that only some key
+    /// references may be missing from `frames`.
+    pub fn apply(
+        &self,
+        frames: &BTreeMap<String, DataFrame>,
+        pseudonymizer: &Pseudonymizer,
+    ) -> anyhow::Result<BTreeMap<String, DataFrame>> {
+        frames
+            .iter()
+            .map(|(table, df)| {
+                let Some(policy) = self.tables.get(table) else {
+                    bail!("table `{table}` has no policy");
+                };
+                let out = self
+                    .apply_table(table, policy, df, pseudonymizer)
+                    .with_context(|| format!("table `{table}`"))?;
+                Ok((table.clone(), out))
+            })
+            .collect()
+    }
+
+    fn apply_table(
+        &self,
+        table: &str,
+        policy: &Policy,
+        df: &DataFrame,
+        pseudonymizer: &Pseudonymizer,
+    ) -> anyhow::Result<DataFrame> {
+        let keys = self.key_columns(table);
+        let mut policy = policy.clone();
+        let mut exprs = Vec::with_capacity(keys.len());
+        for (column, domain) in &keys {
+            df.column(column)?;
+            let rename = policy
+                .columns
+                .get(column)
+                .and_then(|rule| rule.rename.clone());
+            policy.columns.insert(
+                column.clone(),
+                ColumnRule {
+                    strategy: Strategy::Keep,
+                    rename,
+                },
+            );
+            exprs.push(key_expr(column, domain, pseudonymizer));
+        }
+
+        let keyed = df.clone().lazy().with_columns(exprs).collect()?;
+        let mut out = policy.apply(&keyed, pseudonymizer)?;
+        if !keys.is_empty() && !policy.uses_key() {
+            out.with_column(Series::new(
+                policy.key_id_column.as_str().into(),
+                vec![pseudonymizer.key_id(); out.height()],
+            ))?;
+        }
+        Ok(out)
+    }
+
+    /// Join every foreign key in `original` and in `anonymized` (the output
+    /// of [`apply`](Self::apply)) and count the rows. Keys whose tables are
+    /// not both present are skipped.
+    pub fn check_joins(
+        &self,
+        original: &BTreeMap<String, DataFrame>,
+        anonymized: &BTreeMap<String, DataFrame>,
+    ) -> anyhow::Result<Vec<JoinCheck>> {
+        let mut checks = Vec::new();
+        for fk in &self.foreign_keys {
+            let (from_table, from_column) = split_column(&fk.from)?;
+            let (to_table, to_column) = split_column(&fk.to)?;
+            let (Some(from), Some(to)) = (original.get(from_table), original.get(to_table)) else {
+                continue;
+            };
+            let original_rows = join_rows(from, from_column, to, to_column)?;
+
+            let from_output = self.output_column(&fk.from)?;
+            let to_output = self.output_column(&fk.to)?;
+            let (Some(from), Some(to)) = (anonymized.get(from_table), anonymized.get(to_table))
+            else {
+                bail!(
+                    "foreign key {} -> {}: anonymized table missing",
+                    fk.from,
+                    fk.to
+                );
+            };
+            checks.push(JoinCheck {
+                from: fk.from.clone(),
+                to: fk.to.clone(),
+                original_rows,
+                anonymized_rows: join_rows(from, &from_output, to, &to_output)?,
+            });
+        }
+        Ok(checks)
+    }
+}
+
+/// Keyed pseudonym of a key column within `domain`; nulls stay null.
+fn key_expr(column: &str, domain: &str, pseudonymizer: &Pseudonymizer) -> Expr {
+    let domain = domain.to_string();
+    let pseudonymizer = pseudonymizer.clone();
+    col(column).cast(DataType::String).map(
+        move |column| {
+            let out: StringChunked = column
+                .str()?
+                .into_iter()
+                .map(|v| v.map(|v| pseudonymizer.pseudonymize(&format!("{domain}\0{v}"))))
+                .collect();
+            Ok(Some(out.with_name(column.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::String),
+    )
+}
+
+/// Rows of `left INNER JOIN right ON left.left_on = right.right_on`.
+fn join_rows(
+    left: &DataFrame,
+    left_on: &str,
+    right: &DataFrame,
+    right_on: &str,
+) -> anyhow::Result<usize> {
+    let key = |df: &DataFrame, name: &str| -> anyhow::Result<LazyFrame> {
+        Ok(df
+            .column(name)?
+            .cast(&DataType::String)?
+            .with_name("key".into())
+            .into_frame()
+            .lazy())
+    };
+    let joined = key(left, left_on)?
+        .join(
+            key(right, right_on)?,
+            [col("key")],
+            [col("key")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .collect()?;
+    Ok(joined.height())
+}
+
+/// Fail unless every check matches.
+pub fn ensure_joins_match(checks: &[JoinCheck]) -> anyhow::Result<()> {
+    for check in checks {
+        ensure!(
+            check.matches(),
+            "join {} -> {} returned {} rows before anonymization and {} after",
+            check.from,
+            check.to,
+            check.original_rows,
+            check.anonymized_rows
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::customers;
+    use crate::shop::{self, ShopOptions};
+
+    fn pseudonymizer() -> Pseudonymizer {
+        Pseudonymizer::new(b"dataset-test-key-000", Some("test")).unwrap()
+    }
+
+    fn shop_frames() -> BTreeMap<String, DataFrame> {
+        let mut customers = customers::to_dataframe(&customers::generate(50, Some(1))).unwrap();
+        customers
+            .insert_column(0, Series::new("id".into(), (1..=50).collect::<Vec<i32>>()))
+            .unwrap();
+        // An order whose customer is missing still joins nothing afterwards.
+        let ids: Vec<i32> = (1..=50).chain([999]).collect();
+        let data = shop::generate(&ids, &ShopOptions::default(), Some(1)).unwrap();
+        BTreeMap::from([
+            ("customers".to_string(), customers),
+            (
+                "products".to_string(),
+                shop::products_frame(&data.products).unwrap(),
+            ),
+            (
+                "orders".to_string(),
+                shop::orders_frame(&data.orders).unwrap(),
+            ),
+            (
+                "order_items".to_string(),
+                shop::order_items_frame(&data.order_items).unwrap(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_joins_survive_anonymization() {
+        let policy = DatasetPolicy::shop();
+        let frames = shop_frames();
+        let out = policy.apply(&frames, &pseudonymizer()).unwrap();
+
+        let checks = policy.check_joins(&frames, &out).unwrap();
+        assert_eq!(checks.len(), 3);
+        assert!(checks.iter().all(|c| c.original_rows > 0), "{checks:?}");
+        ensure_joins_match(&checks).unwrap();
+
+        let customers = &out["customers"];
+        assert!(customers.column("name_hash").is_ok());
+        let ids = customers.column("id").unwrap().str().unwrap();
+        assert_ne!(ids.get(0), Some("1"));
+        // Keys are pseudonymized per domain: order 1 and customer 1 differ.
+        let orders = out["orders"].column("id").unwrap().str().unwrap();
+        assert_ne!(ids.get(0), orders.get(0));
+        assert!(out["orders"].column("pseudonym_key_id").is_ok());
+        assert!(out["products"].column("name").is_ok());
+    }
+
+    #[test]
+    fn test_renamed_keys_and_chained_domains() {
+        let policy = DatasetPolicy::parse(
+            r#"
+            [[foreign_keys]]
+            from = "b.a_id"
+            to = "a.id"
+
+            [[foreign_keys]]
+            from = "c.a_ref"
+            to = "b.a_id"
+
+            [tables.a.columns.id]
+            strategy = "keep"
+            rename = "key"
+
+            [tables.b]
+            [tables.c]
+            "#,
+        )
+        .unwrap();
+        let domains = policy.key_domains();
+        assert_eq!(domains.len(), 1);
+        assert_eq!(domains["a.id"].len(), 3);
+
+        let frames = BTreeMap::from([
+            ("a".to_string(), df!("id" => [1, 2, 3]).unwrap()),
+            (
+                "b".to_string(),
+                df!("a_id" => [Some(1), Some(1), None]).unwrap(),
+            ),
+            ("c".to_string(), df!("a_ref" => [1, 3]).unwrap()),
+        ]);
+        let out = policy.apply(&frames, &pseudonymizer()).unwrap();
+        assert_eq!(out["a"].get_column_names_str(), ["key", "pseudonym_key_id"]);
+        assert_eq!(out["b"].column("a_id").unwrap().null_count(), 1);
+        let a = out["a"].column("key").unwrap().str().unwrap();
+        let c = out["c"].column("a_ref").unwrap().str().unwrap();
+        assert_eq!((a.get(0), a.get(2)), (c.get(0), c.get(1)));
+
+        let checks = policy.check_joins(&frames, &out).unwrap();
+        assert_eq!(checks[0].original_rows, 2);
+        ensure_joins_match(&checks).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_dataset_policies() {
+        let fk = "[[foreign_keys]]\nfrom = \"orders.customer_id\"\nto = \"customers.id\"\n";
+        assert!(DatasetPolicy::parse(fk).is_err());
+        let redacted =
+            format!("{fk}[tables.orders]\n[tables.customers.columns.id]\nstrategy = \"redact\"\n");
+        assert!(DatasetPolicy::parse(&redacted).is_err());
+        let bad_ref = "[[foreign_keys]]\nfrom = \"customer_id\"\nto = \"customers.id\"\n";
+        assert!(DatasetPolicy::parse(bad_ref).is_err());
+    }
+
+    #[test]
+    fn test_bundled_shop_policy_matches_builtin() {
+        let text = include_str!("../../windmill-scripts/04-database/policies/shop.toml");
+        assert_eq!(DatasetPolicy::parse(text).unwrap(), DatasetPolicy::shop());
+    }
+}
@@ -0,0 +1,146 @@
+//! Epsilon ledgers for differentially private releases, kept in
+//! [`PRIVACY_BUDGETS_TABLE`].
+//!
+//! Every [`DpQuery`](crate::DpQuery) release spends its `epsilon` from the
+//! budget of the dataset it reads, and the privacy loss of all releases
+//! adds up. Once the total is spent, further releases are refused. The row
+//! is locked while spending, so concurrent releases queue behind each
+//! other; spend on the transaction that reads the data and a failed release
+//! costs nothing.
+//!
+//! The total is fixed when a dataset is first charged. Raising it later
+//! means updating the row by hand, which should be a deliberate decision.
+
+use crate::schema::quote_literal;
+use anyhow::bail;
+use serde::Serialize;
+
+pub const PRIVACY_BUDGETS_TABLE: &str = "rustetl_privacy_budgets";
+
+/// The epsilon one dataset may spend over all its releases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivacyBudget {
+    dataset: String,
+    total: f64,
+}
+
+/// Ledger state after a release.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetUsage {
+    pub dataset: String,
+    pub total: f64,
+    pub spent: f64,
+    pub remaining: f64,
+}
+
+impl PrivacyBudget {
+    /// `dataset` names the ledger row, usually the source table.
+    pub fn new(dataset: &str, total: f64) -> anyhow::Result<Self> {
+        if !(total.is_finite() && total > 0.0) {
+            bail!("the privacy budget must be positive");
+        }
+        Ok(PrivacyBudget {
+            dataset: dataset.to_string(),
+            total,
+        })
+    }
+
+    pub fn dataset(&self) -> &str {
+        &self.dataset
+    }
+
+    fn key(&self) -> String {
+        quote_literal(&self.dataset)
+    }
+
+    /// Create the ledger row if needed.
+    pub(crate) fn claim(&self) -> Vec<String> {
+        vec![
+            create_budgets(),
+            format!(
+                "INSERT INTO {PRIVACY_BUDGETS_TABLE} (dataset, epsilon_total) VALUES ({}, {}) \
+                 ON CONFLICT (dataset) DO NOTHING",
+                self.key(),
+                self.total
+            ),
+        ]
+    }
+
+    /// Lock the row and read `(epsilon_total, epsilon_spent)`.
+    pub(crate) fn lock(&self) -> String {
+        format!(
+            "SELECT epsilon_total, epsilon_spent FROM {PRIVACY_BUDGETS_TABLE} \
+             WHERE dataset = {} FOR UPDATE",
+            self.key()
+        )
+    }
+
+    pub(crate) fn spend(&self, epsilon: f64) -> String {
+        format!(
+            "UPDATE {PRIVACY_BUDGETS_TABLE} SET \
+             epsilon_spent = epsilon_spent + {epsilon}, \
+             releases = releases + 1, \
+             updated_at = CURRENT_TIMESTAMP \
+             WHERE dataset = {}",
+            self.key()
+        )
+    }
+
+    /// Check `epsilon` fits in what the locked row has left.
+    pub(crate) fn usage(
+        &self,
+        total: f64,
+        spent: f64,
+        epsilon: f64,
+    ) -> anyhow::Result<BudgetUsage> {
+        // Tolerate rounding so a budget of 1.0 fits ten releases of 0.1
+        if spent + epsilon > total + 1e-9 {
+            bail!(
+                "privacy budget of `{}` exhausted: {spent} of {total} spent, this release needs {epsilon}",
+                self.dataset
+            );
+        }
+        Ok(BudgetUsage {
+            dataset: self.dataset.clone(),
+            total,
+            spent: spent + epsilon,
+            remaining: (total - spent - epsilon).max(0.0),
+        })
+    }
+}
+
+fn create_budgets() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {PRIVACY_BUDGETS_TABLE} (
+            dataset TEXT PRIMARY KEY,
+            epsilon_total DOUBLE PRECISION NOT NULL,
+            epsilon_spent DOUBLE PRECISION NOT NULL DEFAULT 0,
+            releases INTEGER NOT NULL DEFAULT 0,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_statements() {
+        let budget = PrivacyBudget::new("customers", 1.0).unwrap();
+        assert!(budget.claim()[1].contains("VALUES ('customers', 1)"));
+        assert!(
+            budget
+                .spend(0.25)
+                .contains("epsilon_spent = epsilon_spent + 0.25")
+        );
+
+        let mut spent = 0.0;
+        for _ in 0..10 {
+            spent = budget.usage(1.0, spent, 0.1).unwrap().spent;
+        }
+        let err = budget.usage(1.0, spent, 0.1).unwrap_err().to_string();
+        assert!(err.contains("privacy budget of `customers` exhausted"));
+        assert!(PrivacyBudget::new("customers", 0.0).is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Schema-driven conversion of Postgres results into Polars frames.
+//!
+//! Both clients hand over values in Postgres' binary wire format. They are
+//! decoded by the column's type OID, so any query result becomes a typed,
+//! nullable `DataFrame` without per-table code:
+//!
+//! | Postgres                       | Polars                          |
+//! |--------------------------------|---------------------------------|
+//! | `int2` / `int4` / `int8`       | `Int16` / `Int32` / `Int64`     |
+//! | `float4` / `float8`, `numeric` | `Float32` / `Float64`, `Float64`|
+//! | `text`, `varchar`, `bpchar`    | `String`                        |
+//! | `bool`                         | `Boolean`                       |
+//! | `timestamp` / `timestamptz`    | `Datetime(µs)` / `Datetime(µs, UTC)` |
+//! | `date`                         | `Date`                          |
+//! | `uuid`                         | `String` (hyphenated)           |
+//! | `json` / `jsonb`               | `String` (JSON text)            |
+//!
+//! `numeric` is converted to the nearest `f64`. `±infinity` timestamps and
+//! dates become null. Any other type is an error naming the column.
+
+use anyhow::{bail, ensure};
+use polars::prelude::*;
+use std::fmt::Write;
+
+/// Microseconds and days from the Unix epoch to 2000-01-01, Postgres' epoch.
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+const PG_EPOCH_DAYS: i32 = 10_957;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PgType {
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Numeric,
+    Text,
+    Bool,
+    Timestamp,
+    TimestampTz,
+    Date,
+    Uuid,
+    Json,
+    Jsonb,
+}
+
+impl PgType {
+    fn from_oid(oid: u32) -> Option<Self> {
+        Some(match oid {
+            21 => PgType::Int2,
+            23 => PgType::Int4,
+            20 => PgType::Int8,
+            700 => PgType::Float4,
+            701 => PgType::Float8,
+            1700 => PgType::Numeric,
+            19 | 25 | 1042 | 1043 => PgType::Text,
+            16 => PgType::Bool,
+            1114 => PgType::Timestamp,
+            1184 => PgType::TimestampTz,
+            1082 => PgType::Date,
+            2950 => PgType::Uuid,
+            114 => PgType::Json,
+            3802 => PgType::Jsonb,
+            _ => return None,
+        })
+    }
+}
+
+/// Decoded values, by physical type.
+enum Values {
+    I16(Vec<Option<i16>>),
+    I32(Vec<Option<i32>>),
+    I64(Vec<Option<i64>>),
+    F32(Vec<Option<f32>>),
+    F64(Vec<Option<f64>>),
+    Bool(Vec<Option<bool>>),
+    Str(Vec<Option<String>>),
+}
+
+struct ColumnBuilder {
+    name: String,
+    ty: PgType,
+    values: Values,
+}
+
+impl ColumnBuilder {
+    fn push(&mut self, raw: Option<&[u8]>) -> anyhow::Result<()> {
+        let ty = self.ty;
+        match &mut self.values {
+            Values::I16(v) => v.push(raw.map(|b| fixed(b).map(i16::from_be_bytes)).transpose()?),
+            Values::I32(v) => v.push(match raw {
+                Some(b) => date(i32::from_be_bytes(fixed(b)?), ty),
+                None => None,
+            }),
+            Values::I64(v) => v.push(match raw {
+                Some(b) => timestamp(i64::from_be_bytes(fixed(b)?), ty),
+                None => None,
+            }),
+            Values::F32(v) => v.push(raw.map(|b| fixed(b).map(f32::from_be_bytes)).transpose()?),
+            Values::F64(v) => v.push(
+                raw.map(|b| match ty {
+                    PgType::Numeric => numeric(b),
+                    _ => fixed(b).map(f64::from_be_bytes),
+                })
+                .transpose()?,
+            ),
+            Values::Bool(v) => v.push(raw.map(|b| fixed::<1>(b).map(|[v]| v != 0)).transpose()?),
+            Values::Str(v) => v.push(raw.map(|b| text(b, ty)).transpose()?),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Column {
+        let name = PlSmallStr::from(self.name);
+        match self.values {
+            Values::I16(v) => Int16Chunked::from_iter_options(name, v.into_iter()).into_column(),
+            Values::I32(v) if self.ty == PgType::Date => {
+                Int32Chunked::from_iter_options(name, v.into_iter())
+                    .into_date()
+                    .into_column()
+            }
+            Values::I32(v) => Int32Chunked::from_iter_options(name, v.into_iter()).into_column(),
+            Values::I64(v) => {
+                let values = Int64Chunked::from_iter_options(name, v.into_iter());
+                match self.ty {
+                    PgType::Timestamp => values.into_datetime(TimeUnit::Microseconds, None),
+                    PgType::TimestampTz => {
+                        values.into_datetime(TimeUnit::Microseconds, Some("UTC".into()))
+                    }
+                    _ => return values.into_column(),
+                }
+                .into_column()
+            }
+            Values::F32(v) => Float32Chunked::from_iter_options(name, v.into_iter()).into_column(),
+            Values::F64(v) => Float64Chunked::from_iter_options(name, v.into_iter()).into_column(),
+            Values::Bool(v) => BooleanChunked::from_iter_options(name, v.into_iter()).into_column(),
+            Values::Str(v) => StringChunked::from_iter_options(name, v.into_iter()).into_column(),
+        }
+    }
+}
+
+/// Accumulates rows of binary values into a `DataFrame`.
+pub(crate) struct FrameBuilder {
+    columns: Vec<ColumnBuilder>,
+}
+
+impl FrameBuilder {
+    /// `columns` are `(name, type oid, type name)` in result order.
+    pub(crate) fn new<'a>(
+        columns: impl IntoIterator<Item = (&'a str, u32, &'a str)>,
+        capacity: usize,
+    ) -> anyhow::Result<Self> {
+        let columns = columns
+            .into_iter()
+            .map(|(name, oid, type_name)| {
+                let Some(ty) = PgType::from_oid(oid) else {
+                    bail!("column `{name}`: unsupported Postgres type `{type_name}`");
+                };
+                let values = match ty {
+                    PgType::Int2 => Values::I16(Vec::with_capacity(capacity)),
+                    PgType::Int4 | PgType::Date => Values::I32(Vec::with_capacity(capacity)),
+                    PgType::Int8 | PgType::Timestamp | PgType::TimestampTz => {
+                        Values::I64(Vec::with_capacity(capacity))
+                    }
+                    PgType::Float4 => Values::F32(Vec::with_capacity(capacity)),
+                    PgType::Float8 | PgType::Numeric => Values::F64(Vec::with_capacity(capacity)),
+                    PgType::Bool => Values::Bool(Vec::with_capacity(capacity)),
+                    PgType::Text | PgType::Uuid | PgType::Json | PgType::Jsonb => {
+                        Values::Str(Vec::with_capacity(capacity))
+                    }
+                };
+                Ok(ColumnBuilder {
+                    name: name.to_string(),
+                    ty,
+                    values,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(FrameBuilder { columns })
+    }
+
+    /// Append the value of column `index` for the current row.
+    pub(crate) fn push(&mut self, index: usize, raw: Option<&[u8]>) -> anyhow::Result<()> {
+        let column = &mut self.columns[index];
+        column.push(raw).map_err(|e| {
+            e.context(format!(
+                "column `{}`: invalid {:?} value",
+                column.name, column.ty
+            ))
+        })
+    }
+
+    pub(crate) fn finish(self) -> PolarsResult<DataFrame> {
+        DataFrame::new(
+            self.columns
+                .into_iter()
+                .map(ColumnBuilder::finish)
+                .collect(),
+        )
+    }
+}
+
+fn fixed<const N: usize>(raw: &[u8]) -> anyhow::Result<[u8; N]> {
+    raw.try_into()
+        .map_err(|_| anyhow::anyhow!("expected {N} bytes, got {}", raw.len()))
+}
+
+fn timestamp(micros: i64, ty: PgType) -> Option<i64> {
+    match ty {
+        PgType::Timestamp | PgType::TimestampTz if micros == i64::MAX || micros == i64::MIN => None,
+        PgType::Timestamp | PgType::TimestampTz => Some(micros.saturating_add(PG_EPOCH_MICROS)),
+        _ => Some(micros),
+    }
+}
+
+fn date(days: i32, ty: PgType) -> Option<i32> {
+    match ty {
+        PgType::Date if days == i32::MAX || days == i32::MIN => None,
+        PgType::Date => Some(days.saturating_add(PG_EPOCH_DAYS)),
+        _ => Some(days),
+    }
+}
+
+fn text(raw: &[u8], ty: PgType) -> anyhow::Result<String> {
+    match ty {
+        PgType::Uuid => {
+            let hex: String = fixed::<16>(raw)?
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            Ok(format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            ))
+        }
+        PgType::Jsonb => {
+            ensure!(raw.first() == Some(&1), "unknown jsonb version");
+            Ok(std::str::from_utf8(&raw[1..])?.to_string())
+        }
+        _ => Ok(std::str::from_utf8(raw)?.to_string()),
+    }
+}
+
+/// Decode binary `numeric`: a header of `ndigits`, `weight`, `sign` and
+/// `dscale`, then `ndigits` base-10000 digits, the first worth
+/// `10000^weight`.
+fn numeric(raw: &[u8]) -> anyhow::Result<f64> {
+    ensure!(raw.len() >= 8, "numeric header too short");
+    let word = |i: usize| u16::from_be_bytes([raw[2 * i], raw[2 * i + 1]]);
+    let ndigits = word(0) as usize;
+    let weight = word(1) as i16 as i32;
+    let sign = word(2);
+    ensure!(raw.len() == 8 + 2 * ndigits, "numeric digit count mismatch");
+    let digit = |i: i32| match i {
+        i if (0..ndigits as i32).contains(&i) => word(4 + i as usize),
+        _ => 0,
+    };
+
+    let mut s = match sign {
+        0x0000 => String::new(),
+        0x4000 => "-".to_string(),
+        0xC000 => return Ok(f64::NAN),
+        0xD000 => return Ok(f64::INFINITY),
+        0xF000 => return Ok(f64::NEG_INFINITY),
+        _ => bail!("invalid numeric sign {sign:#x}"),
+    };
+    if weight < 0 {
+        s.push('0');
+    } else {
+        write!(s, "{}", digit(0))?;
+        for i in 1..=weight {
+            write!(s, "{:04}", digit(i))?;
+        }
+    }
+    s.push('.');
+    let last = ndigits as i32 - 1;
+    for i in (weight + 1)..=last.max(weight + 1) {
+        write!(s, "{:04}", digit(i))?;
+    }
+    Ok(s.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric_bytes(weight: i16, sign: u16, digits: &[u16]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for word in [digits.len() as u16, weight as u16, sign, 0] {
+            out.extend(word.to_be_bytes());
+        }
+        for d in digits {
+            out.extend(d.to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert_eq!(
+            numeric(&numeric_bytes(0, 0, &[1234, 5000])).unwrap(),
+            1234.5
+        );
+        assert_eq!(numeric(&numeric_bytes(1, 0x4000, &[1])).unwrap(), -10000.0);
+        assert_eq!(numeric(&numeric_bytes(-2, 0, &[25])).unwrap(), 0.000_000_25);
+        assert_eq!(numeric(&numeric_bytes(0, 0, &[])).unwrap(), 0.0);
+        assert!(numeric(&numeric_bytes(0, 0xC000, &[])).unwrap().is_nan());
+        assert!(numeric(&[0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_frame_from_binary_values() {
+        let mut builder = FrameBuilder::new(
+            [
+                ("id", 23, "int4"),
+                ("at", 1114, "timestamp"),
+                ("day", 1082, "date"),
+                ("key", 2950, "uuid"),
+                ("doc", 3802, "jsonb"),
+                ("ok", 16, "bool"),
+            ],
+            2,
+        )
+        .unwrap();
+        let key: Vec<u8> = (0..16).collect();
+        let row: [Option<Vec<u8>>; 6] = [
+            Some(7i32.to_be_bytes().to_vec()),
+            Some(86_400_000_000i64.to_be_bytes().to_vec()),
+            Some(1i32.to_be_bytes().to_vec()),
+            Some(key),
+            Some(b"\x01{\"a\":1}".to_vec()),
+            Some(vec![1]),
+        ];
+        for (i, value) in row.iter().enumerate() {
+            builder.push(i, value.as_deref()).unwrap();
+        }
+        for i in 0..6 {
+            builder.push(i, None).unwrap();
+        }
+        let df = builder.finish().unwrap();
+
+        assert_eq!(df.shape(), (2, 6));
+        assert_eq!(
+            df.dtypes(),
+            [
+                DataType::Int32,
+                DataType::Datetime(TimeUnit::Microseconds, None),
+                DataType::Date,
+                DataType::String,
+                DataType::String,
+                DataType::Boolean,
+            ]
+        );
+        let at = df.column("at").unwrap().datetime().unwrap().get(0);
+        assert_eq!(at, Some(PG_EPOCH_MICROS + 86_400_000_000));
+        assert_eq!(
+            df.column("day").unwrap().date().unwrap().get(0),
+            Some(10_958)
+        );
+        assert_eq!(
+            df.column("key").unwrap().str().unwrap().get(0),
+            Some("00010203-0405-0607-0809-0a0b0c0d0e0f")
+        );
+        assert_eq!(
+            df.column("doc").unwrap().str().unwrap().get(0),
+            Some("{\"a\":1}")
+        );
+        assert_eq!(df.column("ok").unwrap().null_count(), 1);
+    }
+
+    #[test]
+    fn test_unsupported_type_and_bad_value() {
+        let err = FrameBuilder::new([("tags", 1009, "_text")], 0)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("`tags`"));
+
+        let mut builder = FrameBuilder::new([("n", 20, "int8")], 1).unwrap();
+        let err = builder.push(0, Some(&[1, 2])).unwrap_err();
+        assert!(err.to_string().contains("column `n`"));
+    }
+}
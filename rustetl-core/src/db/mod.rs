@@ -0,0 +1,96 @@
+//! Read/write helpers for the `customers` tables, one module per client.
+//!
+//! Frames are written with `COPY ... FROM STDIN` in CSV format, one `COPY`
+//! per batch of rows, so a 100k-row load is a handful of round trips instead
+//! of 100k `INSERT`s. Column names in the frame must match the table.
+//! `write_dataframe` in either client also creates the table from the frame
+//! (see [`table`]).
+
+use crate::privacy::PrivacyCheck;
+use polars::prelude::*;
+
+pub mod budget;
+pub mod config;
+mod decode;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlx")]
+pub mod sqlx;
+pub mod table;
+#[cfg(feature = "postgres")]
+mod tls;
+pub mod watermark;
+
+pub use config::PgConfig;
+
+/// Rows per `COPY` when the caller does not choose.
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// Row ranges `(offset, len)` covering `height` rows in batches.
+fn batches(height: usize, batch_size: usize) -> impl Iterator<Item = (usize, usize)> {
+    let batch_size = batch_size.max(1);
+    (0..height)
+        .step_by(batch_size)
+        .map(move |offset| (offset, batch_size.min(height - offset)))
+}
+
+/// Encode rows as CSV for `COPY ... WITH (FORMAT csv)`: no header, text
+/// quoted so empty strings stay distinct from nulls (unquoted empty fields).
+fn encode_csv(df: &DataFrame) -> PolarsResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    CsvWriter::new(&mut buf)
+        .include_header(false)
+        .with_quote_style(QuoteStyle::NonNumeric)
+        .finish(&mut df.clone())?;
+    Ok(buf)
+}
+
+/// `id`s of the rows [`PrivacyCheck::suppress`] would drop.
+fn violating_ids(check: &PrivacyCheck, df: &DataFrame) -> PolarsResult<Vec<i64>> {
+    let violating = check.violating(df)?;
+    let ids = violating.column("id")?.strict_cast(&DataType::Int64)?;
+    Ok(ids.i64()?.into_no_null_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_cover_all_rows() {
+        assert_eq!(
+            batches(25, 10).collect::<Vec<_>>(),
+            [(0, 10), (10, 10), (20, 5)]
+        );
+        assert_eq!(batches(0, 10).count(), 0);
+        assert_eq!(batches(3, 0).count(), 3);
+    }
+
+    #[test]
+    fn test_csv_keeps_nulls_and_empty_strings_apart() {
+        let df = df! {
+            "id" => &[1, 2],
+            "name" => &[Some(""), None],
+            "note" => &[Some("a,\"b\""), Some("x")],
+        }
+        .unwrap();
+        let csv = String::from_utf8(encode_csv(&df).unwrap()).unwrap();
+        assert_eq!(csv, "1,\"\",\"a,\"\"b\"\"\"\n2,,\"x\"\n");
+    }
+
+    #[test]
+    fn test_violating_ids_keep_64_bit_ids() {
+        let df = df! {
+            "id" => &[1i64, 5_000_000_000, 5_000_000_001],
+            "age" => &["18-29", "30-39", "30-39"],
+        }
+        .unwrap();
+        let check = PrivacyCheck::new(&["age"], 2);
+        assert_eq!(violating_ids(&check, &df).unwrap(), [1]);
+        let check = PrivacyCheck::new(&["age"], 3);
+        assert_eq!(
+            violating_ids(&check, &df).unwrap(),
+            [1, 5_000_000_000, 5_000_000_001]
+        );
+    }
+}
@@ -0,0 +1,470 @@
+//! Helpers for the synchronous `postgres::Client` scripts.
+
+use super::budget::{BudgetUsage, PrivacyBudget};
+use super::config::{PgConfig, SslMode};
+use super::decode::FrameBuilder;
+use super::table::{self, WriteMode, WriteOptions};
+use super::watermark::Watermark;
+use super::{batches, encode_csv, violating_ids};
+use crate::customers::{self, Customer};
+use crate::privacy::{PrivacyCheck, PrivacyReport};
+use crate::schema;
+use crate::shop::{self, Shop, ShopTables};
+use anyhow::Context;
+use polars::prelude::*;
+use postgres::types::{FromSql, ToSql, Type};
+use postgres::{Client, Column, GenericClient, Portal, Row, Transaction};
+use std::error::Error;
+use std::io::Write;
+
+/// Connect over rustls TLS as `config.sslmode` says (see
+/// [`tls`](super::tls) for what each mode verifies).
+pub fn connect(config: &PgConfig) -> anyhow::Result<Client> {
+    let mut pg = postgres::Config::new();
+    pg.host(&config.host)
+        .port(config.port)
+        .user(&config.user)
+        .dbname(&config.dbname)
+        .ssl_mode(match config.sslmode {
+            SslMode::Disable => postgres::config::SslMode::Disable,
+            SslMode::Allow | SslMode::Prefer => postgres::config::SslMode::Prefer,
+            SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull => {
+                postgres::config::SslMode::Require
+            }
+        });
+    if let Some(password) = &config.password {
+        pg.password(password);
+    }
+    pg.connect(super::tls::make_tls(config)?)
+        .with_context(|| format!("connecting to {config}"))
+}
+
+pub fn table_exists(client: &mut Client, table: &str) -> anyhow::Result<bool> {
+    Ok(client.query_one(&schema::table_exists(table), &[])?.get(0))
+}
+
+/// Drop and recreate `table` with the `customers` layout and indexes.
+pub fn recreate_customers(client: &mut Client, table: &str) -> anyhow::Result<()> {
+    client.execute(&schema::drop_table(table), &[])?;
+    client.execute(&schema::create_customers(table), &[])?;
+    for index in schema::customers_indexes(table) {
+        client.execute(&index, &[])?;
+    }
+    Ok(())
+}
+
+/// Add `updated_at` tracking to an existing `customers` table (see
+/// [`schema::track_updates`]), in one transaction. Only the column or
+/// trigger that is missing is created, so tracked tables are not locked.
+pub fn track_updates(client: &mut impl GenericClient, table: &str) -> anyhow::Result<()> {
+    let mut tx = client.transaction()?;
+    let has_column: bool = tx
+        .query_one(&schema::column_exists(table, "updated_at"), &[])?
+        .get(0);
+    let has_trigger: bool = tx
+        .query_one(&schema::trigger_exists(table, schema::TOUCH_TRIGGER), &[])?
+        .get(0);
+    let mut statements = Vec::new();
+    if !has_column {
+        statements.extend(schema::add_updated_at(table));
+    }
+    if !has_trigger {
+        statements.extend(schema::touch_trigger(table));
+    }
+    for statement in statements {
+        tx.batch_execute(&statement)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Copy `customers` into `table`, calling `progress(done, total)` after
+/// each batch (see [`copy_dataframe_with_progress`]).
+pub fn insert_customers(
+    client: &mut Client,
+    table: &str,
+    customers: &[Customer],
+    batch_size: usize,
+    progress: impl FnMut(usize, usize),
+) -> anyhow::Result<usize> {
+    copy_dataframe_with_progress(
+        client,
+        table,
+        &customers::to_dataframe(customers)?,
+        batch_size,
+        progress,
+    )
+}
+
+/// Ids of `table` (a `customers` table), ascending.
+pub fn customer_ids(client: &mut Client, table: &str) -> anyhow::Result<Vec<i32>> {
+    let rows = client.query(&format!("SELECT id FROM {table} ORDER BY id"), &[])?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Drop and recreate the tables of [`schema::recreate_shop`].
+pub fn recreate_shop(client: &mut impl GenericClient, tables: &ShopTables) -> anyhow::Result<()> {
+    for statement in schema::recreate_shop(tables) {
+        client.batch_execute(&statement)?;
+    }
+    Ok(())
+}
+
+/// Load a generated dataset into tables made by [`recreate_shop`], parents
+/// first, in one transaction. Returns the rows written per table.
+pub fn insert_shop(
+    client: &mut impl GenericClient,
+    tables: &ShopTables,
+    data: &Shop,
+    batch_size: usize,
+) -> anyhow::Result<[usize; 3]> {
+    let mut tx = client.transaction()?;
+    let frames = [
+        (&tables.products, shop::products_frame(&data.products)?),
+        (&tables.orders, shop::orders_frame(&data.orders)?),
+        (
+            &tables.order_items,
+            shop::order_items_frame(&data.order_items)?,
+        ),
+    ];
+    let mut written = [0; 3];
+    for (i, (table, df)) in frames.iter().enumerate() {
+        written[i] = copy_dataframe(&mut tx, table, df, batch_size)?;
+        tx.batch_execute(&schema::sync_id_sequence(table))?;
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+/// Bulk-load `df` into the existing `table` with one `COPY ... FROM STDIN`
+/// per `batch_size` rows. Frame columns map to table columns by name.
+///
+/// Each batch commits on its own unless `client` is a transaction.
+pub fn copy_dataframe(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    copy_dataframe_with_progress(client, table, df, batch_size, |_, _| {})
+}
+
+/// [`copy_dataframe`], calling `progress(done, total)` with the rows
+/// copied so far after each batch.
+pub fn copy_dataframe_with_progress(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<usize> {
+    let statement = schema::copy_from_stdin(table, &df.get_column_names_str());
+    let total = df.height();
+    for (offset, len) in batches(total, batch_size) {
+        let csv = encode_csv(&df.slice(offset as i64, len))?;
+        let mut writer = client.copy_in(&statement)?;
+        writer.write_all(&csv)?;
+        writer.finish()?;
+        progress(offset + len, total);
+    }
+    Ok(total)
+}
+
+/// Write `df` to `table`, creating or replacing it from the frame's schema
+/// as `options.mode` says. All statements and batches run in one
+/// transaction (a savepoint if `client` is already a transaction).
+pub fn write_dataframe(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+    options: &WriteOptions,
+) -> anyhow::Result<usize> {
+    let plan = table::plan(table, df, options)?;
+    let mut tx = client.transaction()?;
+    for statement in &plan.before {
+        tx.batch_execute(statement)?;
+    }
+    let written = copy_dataframe(&mut tx, &plan.copy_into, df, options.batch_size)?;
+    for statement in &plan.after {
+        tx.batch_execute(statement)?;
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+/// Columns of `df` the existing `table` cannot take as they are (see
+/// [`table::mismatched_columns`]), so a write can fail before its `COPY`.
+pub fn mismatched_columns(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+) -> anyhow::Result<Vec<String>> {
+    let columns: Vec<(String, String)> = client
+        .query(&schema::column_types(table), &[])?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    Ok(table::mismatched_columns(df, &columns))
+}
+
+/// Replace `table` with `staging` (see [`table::staging_table`]). Run it on
+/// the transaction that filled `staging`; on error, dropping that
+/// transaction rolls back both.
+pub fn swap_table(
+    client: &mut impl GenericClient,
+    staging: &str,
+    table: &str,
+) -> anyhow::Result<()> {
+    for statement in table::swap_tables(staging, table) {
+        client.batch_execute(&statement)?;
+    }
+    Ok(())
+}
+
+/// Read `table` into a frame with the `customers` columns.
+pub fn read_customers(client: &mut Client, table: &str) -> anyhow::Result<DataFrame> {
+    query_frame(client, &schema::select_customers(table), &[])
+}
+
+/// Run `query` and build a frame typed from its result columns (see
+/// [`decode`](super::decode) for the type mapping).
+pub fn query_frame(
+    client: &mut impl GenericClient,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> anyhow::Result<DataFrame> {
+    let statement = client.prepare(query)?;
+    let rows = client.query(&statement, params)?;
+    rows_frame(statement.columns(), &rows)
+}
+
+/// Stream `table` in frames of up to `chunk_size` rows with the `customers`
+/// columns, through a server-side cursor so only one chunk is in memory.
+pub fn read_customers_chunked<'a>(
+    client: &'a mut Client,
+    table: &str,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks<'a>> {
+    query_chunked(client, &schema::select_customers(table), &[], chunk_size)
+}
+
+/// Like [`query_frame`], but fetch the result in frames of up to
+/// `chunk_size` rows.
+pub fn query_chunked<'a>(
+    client: &'a mut Client,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+    chunk_size: usize,
+) -> anyhow::Result<Chunks<'a>> {
+    chunks(client.transaction()?, query, params, chunk_size)
+}
+
+/// Stream the rows of `table` added or updated since `watermark` was last
+/// saved, oldest first, in frames with the `customers` columns. Call
+/// [`save_watermark`] on [`Chunks::transaction`] before committing.
+pub fn read_customers_since<'a>(
+    client: &'a mut Client,
+    table: &str,
+    watermark: &Watermark,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks<'a>> {
+    let mut tx = client.transaction()?;
+    for statement in watermark.claim(table) {
+        tx.batch_execute(&statement)?;
+    }
+    let query = watermark.select_since(table, schema::CUSTOMER_COLUMNS);
+    chunks(tx, &query, &[], chunk_size)
+}
+
+/// Like [`read_customers_chunked`], but first pin `watermark` as
+/// [`read_customers_since`] does, so a full run can save it and the next
+/// incremental run starts after the rows it read.
+pub fn read_customers_marked<'a>(
+    client: &'a mut Client,
+    table: &str,
+    watermark: &Watermark,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks<'a>> {
+    let mut tx = client.transaction()?;
+    for statement in watermark.claim(table) {
+        tx.batch_execute(&statement)?;
+    }
+    chunks(tx, &schema::select_customers(table), &[], chunk_size)
+}
+
+/// Record that the rows read by [`read_customers_since`] or
+/// [`read_customers_marked`] are processed.
+pub fn save_watermark(
+    client: &mut impl GenericClient,
+    watermark: &Watermark,
+) -> anyhow::Result<()> {
+    client.batch_execute(&watermark.save())?;
+    Ok(())
+}
+
+/// Forget `watermark` so the next incremental read returns every row.
+pub fn reset_watermark(
+    client: &mut impl GenericClient,
+    watermark: &Watermark,
+) -> anyhow::Result<()> {
+    for statement in watermark.reset() {
+        client.batch_execute(&statement)?;
+    }
+    Ok(())
+}
+
+/// Charge `epsilon` to `budget`, failing if too little is left. Run it on
+/// the transaction that reads the released data.
+pub fn spend_budget(
+    client: &mut impl GenericClient,
+    budget: &PrivacyBudget,
+    epsilon: f64,
+) -> anyhow::Result<BudgetUsage> {
+    for statement in budget.claim() {
+        client.batch_execute(&statement)?;
+    }
+    let row = client.query_one(&budget.lock(), &[])?;
+    let usage = budget.usage(row.get(0), row.get(1), epsilon)?;
+    client.batch_execute(&budget.spend(epsilon))?;
+    Ok(usage)
+}
+
+fn chunks<'a>(
+    mut tx: Transaction<'a>,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+    chunk_size: usize,
+) -> anyhow::Result<Chunks<'a>> {
+    let portal = tx.bind(query, params)?;
+    Ok(Chunks {
+        tx,
+        portal,
+        chunk_size: chunk_size.clamp(1, i32::MAX as usize),
+        done: false,
+    })
+}
+
+/// Frames of up to `chunk_size` rows fetched from a portal (server-side
+/// cursor) inside a transaction.
+///
+/// Results can be written through [`transaction`](Self::transaction) while
+/// reading; call [`commit`](Self::commit) to keep them. Dropping the chunks
+/// rolls back.
+pub struct Chunks<'a> {
+    tx: Transaction<'a>,
+    portal: Portal,
+    chunk_size: usize,
+    done: bool,
+}
+
+impl<'a> Chunks<'a> {
+    pub fn transaction(&mut self) -> &mut Transaction<'a> {
+        &mut self.tx
+    }
+
+    pub fn commit(self) -> anyhow::Result<()> {
+        Ok(self.tx.commit()?)
+    }
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = anyhow::Result<DataFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let rows = match self.tx.query_portal(&self.portal, self.chunk_size as i32) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+        };
+        self.done = rows.len() < self.chunk_size;
+        let first = rows.first()?;
+        Some(rows_frame(first.columns(), &rows))
+    }
+}
+
+fn rows_frame(columns: &[Column], rows: &[Row]) -> anyhow::Result<DataFrame> {
+    let mut frame = FrameBuilder::new(
+        columns
+            .iter()
+            .map(|c| (c.name(), c.type_().oid(), c.type_().name())),
+        rows.len(),
+    )?;
+    for row in rows {
+        for i in 0..columns.len() {
+            frame.push(i, row.try_get::<_, RawValue>(i)?.0)?;
+        }
+    }
+    Ok(frame.finish()?)
+}
+
+/// A column value in binary wire format, for [`FrameBuilder`].
+struct RawValue<'a>(Option<&'a [u8]>);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(Some(raw)))
+    }
+
+    fn from_sql_null(_: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(None))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+/// Create `table` from the columns of `df`, the first frame produced by
+/// [`anonymize_customers`](crate::anonymize_customers) or a
+/// [`Policy`](crate::Policy), keyed by `id`, and insert it. Append later
+/// frames with [`insert_customers_anonymized`].
+pub fn create_customers_anonymized(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    client.batch_execute(&schema::drop_table(table))?;
+    let options = WriteOptions::new(WriteMode::Create)
+        .with_primary_key(&["id"])
+        .with_batch_size(batch_size);
+    write_dataframe(client, table, df, &options)
+}
+
+/// Insert a frame produced by [`anonymize_customers`](crate::anonymize_customers).
+pub fn insert_customers_anonymized(
+    client: &mut impl GenericClient,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    copy_dataframe(client, table, df, batch_size)
+}
+
+/// Measure `check` on `table`, whose rows are keyed by an integer `id`.
+/// Without `suppress`, fail if any class misses the thresholds; with it,
+/// delete those classes' rows. Returns the report from before suppression
+/// and the number of rows deleted.
+pub fn enforce_privacy(
+    client: &mut impl GenericClient,
+    table: &str,
+    check: &PrivacyCheck,
+    suppress: bool,
+) -> anyhow::Result<(PrivacyReport, u64)> {
+    let df = query_frame(client, &check.select(table, "id"), &[])?;
+    let report = check.measure(&df)?;
+    if report.passed {
+        return Ok((report, 0));
+    }
+    if !suppress {
+        report.ensure()?;
+    }
+    let ids = violating_ids(check, &df)?;
+    let deleted = client.execute(&schema::delete_ids(table), &[&ids])?;
+    Ok((report, deleted))
+}
@@ -0,0 +1,482 @@
+//! Helpers for the async `sqlx::PgPool` scripts.
+
+use super::budget::{BudgetUsage, PrivacyBudget};
+use super::config::{PgConfig, SslMode};
+use super::decode::FrameBuilder;
+use super::table::{self, WriteMode, WriteOptions};
+use super::watermark::Watermark;
+use super::{batches, encode_csv, violating_ids};
+use crate::customers::{self, Customer};
+use crate::privacy::{PrivacyCheck, PrivacyReport};
+use crate::schema;
+use crate::shop::{self, Shop, ShopTables};
+use anyhow::{Context, bail};
+use polars::prelude::*;
+use sqlx::postgres::{PgArguments, PgColumn, PgConnectOptions, PgRow, PgSslMode, PgValueFormat};
+use sqlx::query::Query;
+use sqlx::{
+    Column as _, Connection as _, Execute, Executor, PgConnection, PgPool, Postgres, Row,
+    Transaction, TypeInfo as _, ValueRef as _,
+};
+
+pub async fn connect(config: &PgConfig) -> anyhow::Result<PgPool> {
+    let mut options = PgConnectOptions::new()
+        .host(&config.host)
+        .port(config.port)
+        .username(&config.user)
+        .database(&config.dbname)
+        .ssl_mode(match config.sslmode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        });
+    if let Some(password) = &config.password {
+        options = options.password(password);
+    }
+    if let Some(pem) = &config.root_certificate_pem {
+        options = options.ssl_root_cert_from_pem(pem.as_bytes().to_vec());
+    }
+    PgPool::connect_with(options)
+        .await
+        .with_context(|| format!("connecting to {config}"))
+}
+
+pub async fn table_exists(pool: &PgPool, table: &str) -> anyhow::Result<bool> {
+    let row = sqlx::query(&schema::table_exists(table))
+        .fetch_one(pool)
+        .await?;
+    Ok(row.try_get(0)?)
+}
+
+/// Drop and recreate `table` with the `customers` layout and indexes.
+pub async fn recreate_customers(pool: &PgPool, table: &str) -> anyhow::Result<()> {
+    sqlx::query(&schema::drop_table(table))
+        .execute(pool)
+        .await?;
+    sqlx::query(&schema::create_customers(table))
+        .execute(pool)
+        .await?;
+    for index in schema::customers_indexes(table) {
+        sqlx::query(&index).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// Add `updated_at` tracking to an existing `customers` table (see
+/// [`schema::track_updates`]), in one transaction. Only the column or
+/// trigger that is missing is created, so tracked tables are not locked.
+pub async fn track_updates(pool: &PgPool, table: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let has_column: bool = sqlx::query_scalar(&schema::column_exists(table, "updated_at"))
+        .fetch_one(&mut *tx)
+        .await?;
+    let has_trigger: bool =
+        sqlx::query_scalar(&schema::trigger_exists(table, schema::TOUCH_TRIGGER))
+            .fetch_one(&mut *tx)
+            .await?;
+    let mut statements = Vec::new();
+    if !has_column {
+        statements.extend(schema::add_updated_at(table));
+    }
+    if !has_trigger {
+        statements.extend(schema::touch_trigger(table));
+    }
+    for statement in statements {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Copy `customers` into `table`, calling `progress(done, total)` after
+/// each batch (see [`copy_dataframe_with_progress`]).
+pub async fn insert_customers(
+    pool: &PgPool,
+    table: &str,
+    customers: &[Customer],
+    batch_size: usize,
+    progress: impl FnMut(usize, usize),
+) -> anyhow::Result<usize> {
+    let df = customers::to_dataframe(customers)?;
+    let mut conn = pool.acquire().await?;
+    copy_dataframe_with_progress(&mut conn, table, &df, batch_size, progress).await
+}
+
+/// Ids of `table` (a `customers` table), ascending.
+pub async fn customer_ids(pool: &PgPool, table: &str) -> anyhow::Result<Vec<i32>> {
+    let ids = sqlx::query_scalar(&format!("SELECT id FROM {table} ORDER BY id"))
+        .fetch_all(pool)
+        .await?;
+    Ok(ids)
+}
+
+/// Drop and recreate the tables of [`schema::recreate_shop`].
+pub async fn recreate_shop(conn: &mut PgConnection, tables: &ShopTables) -> anyhow::Result<()> {
+    for statement in schema::recreate_shop(tables) {
+        sqlx::query(&statement).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Load a generated dataset into tables made by [`recreate_shop`], parents
+/// first, in one transaction. Returns the rows written per table.
+pub async fn insert_shop(
+    conn: &mut PgConnection,
+    tables: &ShopTables,
+    data: &Shop,
+    batch_size: usize,
+) -> anyhow::Result<[usize; 3]> {
+    let mut tx = conn.begin().await?;
+    let frames = [
+        (&tables.products, shop::products_frame(&data.products)?),
+        (&tables.orders, shop::orders_frame(&data.orders)?),
+        (
+            &tables.order_items,
+            shop::order_items_frame(&data.order_items)?,
+        ),
+    ];
+    let mut written = [0; 3];
+    for (i, (table, df)) in frames.iter().enumerate() {
+        written[i] = copy_dataframe(&mut tx, table, df, batch_size).await?;
+        sqlx::query(&schema::sync_id_sequence(table))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Bulk-load `df` into the existing `table` with one `COPY ... FROM STDIN`
+/// per `batch_size` rows. Frame columns map to table columns by name.
+///
+/// Takes a connection so it can run inside a transaction (`&mut *tx`);
+/// otherwise each batch commits on its own.
+pub async fn copy_dataframe(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    copy_dataframe_with_progress(conn, table, df, batch_size, |_, _| {}).await
+}
+
+/// [`copy_dataframe`], calling `progress(done, total)` with the rows
+/// copied so far after each batch.
+pub async fn copy_dataframe_with_progress(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> anyhow::Result<usize> {
+    let statement = schema::copy_from_stdin(table, &df.get_column_names_str());
+    let total = df.height();
+    for (offset, len) in batches(total, batch_size) {
+        let csv = encode_csv(&df.slice(offset as i64, len))?;
+        let mut copy = conn.copy_in_raw(&statement).await?;
+        copy.send(csv).await?;
+        copy.finish().await?;
+        progress(offset + len, total);
+    }
+    Ok(total)
+}
+
+/// Write `df` to `table`, creating or replacing it from the frame's schema
+/// as `options.mode` says. All statements and batches run in one
+/// transaction (a savepoint if `conn` is already in one).
+pub async fn write_dataframe(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+    options: &WriteOptions,
+) -> anyhow::Result<usize> {
+    let plan = table::plan(table, df, options)?;
+    let mut tx = conn.begin().await?;
+    for statement in &plan.before {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+    let written = copy_dataframe(&mut tx, &plan.copy_into, df, options.batch_size).await?;
+    for statement in &plan.after {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Columns of `df` the existing `table` cannot take as they are (see
+/// [`table::mismatched_columns`]), so a write can fail before its `COPY`.
+pub async fn mismatched_columns(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+) -> anyhow::Result<Vec<String>> {
+    let columns: Vec<(String, String)> = sqlx::query_as(&schema::column_types(table))
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(table::mismatched_columns(df, &columns))
+}
+
+/// Replace `table` with `staging` (see [`table::staging_table`]). Run it on
+/// the transaction that filled `staging`; on error, dropping that
+/// transaction rolls back both.
+pub async fn swap_table(conn: &mut PgConnection, staging: &str, table: &str) -> anyhow::Result<()> {
+    for statement in table::swap_tables(staging, table) {
+        sqlx::query(&statement).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Read `table` into a frame with the `customers` columns.
+pub async fn read_customers(pool: &PgPool, table: &str) -> anyhow::Result<DataFrame> {
+    query_frame(pool, sqlx::query(&schema::select_customers(table))).await
+}
+
+/// Run `query` and build a frame typed from its result columns (see
+/// [`decode`](super::decode) for the type mapping). Bind parameters on the
+/// query as usual: `query_frame(pool, sqlx::query(sql).bind(x))`.
+pub async fn query_frame<'q>(
+    pool: &PgPool,
+    query: Query<'q, Postgres, PgArguments>,
+) -> anyhow::Result<DataFrame> {
+    let sql = query.sql();
+    let rows = query.fetch_all(pool).await?;
+    if rows.is_empty() {
+        let describe = pool.describe(sql).await?;
+        return Ok(FrameBuilder::new(column_types(describe.columns()), 0)?.finish()?);
+    }
+    rows_frame(&rows)
+}
+
+/// Stream `table` in frames of up to `chunk_size` rows with the `customers`
+/// columns, through a server-side cursor so only one chunk is in memory.
+pub async fn read_customers_chunked(
+    pool: &PgPool,
+    table: &str,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks> {
+    query_chunked(pool, &schema::select_customers(table), chunk_size).await
+}
+
+/// Like [`query_frame`], but fetch the result in frames of up to
+/// `chunk_size` rows.
+pub async fn query_chunked(
+    pool: &PgPool,
+    query: &str,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks> {
+    chunks(pool.begin().await?, query, chunk_size).await
+}
+
+/// Stream the rows of `table` added or updated since `watermark` was last
+/// saved, oldest first, in frames with the `customers` columns. Call
+/// [`save_watermark`] on [`Chunks::transaction`] before committing.
+pub async fn read_customers_since(
+    pool: &PgPool,
+    table: &str,
+    watermark: &Watermark,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks> {
+    let mut tx = pool.begin().await?;
+    for statement in watermark.claim(table) {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    let query = watermark.select_since(table, schema::CUSTOMER_COLUMNS);
+    chunks(tx, &query, chunk_size).await
+}
+
+/// Like [`read_customers_chunked`], but first pin `watermark` as
+/// [`read_customers_since`] does, so a full run can save it and the next
+/// incremental run starts after the rows it read.
+pub async fn read_customers_marked(
+    pool: &PgPool,
+    table: &str,
+    watermark: &Watermark,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks> {
+    let mut tx = pool.begin().await?;
+    for statement in watermark.claim(table) {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    chunks(tx, &schema::select_customers(table), chunk_size).await
+}
+
+/// Record that the rows read by [`read_customers_since`] or
+/// [`read_customers_marked`] are processed.
+pub async fn save_watermark(conn: &mut PgConnection, watermark: &Watermark) -> anyhow::Result<()> {
+    sqlx::query(&watermark.save()).execute(conn).await?;
+    Ok(())
+}
+
+/// Forget `watermark` so the next incremental read returns every row.
+pub async fn reset_watermark(conn: &mut PgConnection, watermark: &Watermark) -> anyhow::Result<()> {
+    for statement in watermark.reset() {
+        sqlx::query(&statement).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Charge `epsilon` to `budget`, failing if too little is left. Run it on
+/// the transaction that reads the released data.
+pub async fn spend_budget(
+    conn: &mut PgConnection,
+    budget: &PrivacyBudget,
+    epsilon: f64,
+) -> anyhow::Result<BudgetUsage> {
+    for statement in budget.claim() {
+        sqlx::query(&statement).execute(&mut *conn).await?;
+    }
+    let (total, spent): (f64, f64) = sqlx::query_as(&budget.lock()).fetch_one(&mut *conn).await?;
+    let usage = budget.usage(total, spent, epsilon)?;
+    sqlx::query(&budget.spend(epsilon))
+        .execute(&mut *conn)
+        .await?;
+    Ok(usage)
+}
+
+async fn chunks(
+    mut tx: Transaction<'static, Postgres>,
+    query: &str,
+    chunk_size: usize,
+) -> anyhow::Result<Chunks> {
+    let chunk_size = chunk_size.max(1);
+    sqlx::query(&schema::declare_cursor(CURSOR, query))
+        .execute(&mut *tx)
+        .await?;
+    Ok(Chunks {
+        tx,
+        fetch: schema::fetch_cursor(CURSOR, chunk_size),
+        chunk_size,
+        done: false,
+    })
+}
+
+/// Frames of up to `chunk_size` rows fetched from a `DECLARE`d cursor in a
+/// transaction that holds one pooled connection until dropped.
+///
+/// Results can be written through [`transaction`](Self::transaction) while
+/// reading; call [`commit`](Self::commit) to keep them. Dropping the chunks
+/// rolls back.
+pub struct Chunks {
+    tx: Transaction<'static, Postgres>,
+    fetch: String,
+    chunk_size: usize,
+    done: bool,
+}
+
+impl Chunks {
+    pub fn transaction(&mut self) -> &mut PgConnection {
+        &mut self.tx
+    }
+
+    pub async fn commit(self) -> anyhow::Result<()> {
+        Ok(self.tx.commit().await?)
+    }
+
+    /// The next frame, or `None` once the cursor is exhausted.
+    pub async fn next_chunk(&mut self) -> anyhow::Result<Option<DataFrame>> {
+        if self.done {
+            return Ok(None);
+        }
+        let rows = sqlx::query(&self.fetch).fetch_all(&mut *self.tx).await?;
+        self.done = rows.len() < self.chunk_size;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        rows_frame(&rows).map(Some)
+    }
+}
+
+const CURSOR: &str = "rustetl_chunks";
+
+fn column_types(columns: &[PgColumn]) -> impl Iterator<Item = (&str, u32, &str)> {
+    columns.iter().map(|c| {
+        let ty = c.type_info();
+        (c.name(), ty.oid().map_or(0, |oid| oid.0), ty.name())
+    })
+}
+
+/// Frame from non-empty `rows`, typed from the first row's columns.
+fn rows_frame(rows: &[PgRow]) -> anyhow::Result<DataFrame> {
+    let columns = rows[0].columns();
+    let mut frame = FrameBuilder::new(column_types(columns), rows.len())?;
+    for row in rows {
+        for (i, column) in columns.iter().enumerate() {
+            let value = row.try_get_raw(i)?;
+            if value.is_null() {
+                frame.push(i, None)?;
+                continue;
+            }
+            if value.format() != PgValueFormat::Binary {
+                bail!("column `{}`: expected a binary result", column.name());
+            }
+            let bytes = value.as_bytes().map_err(|e| anyhow::anyhow!("{e}"))?;
+            frame.push(i, Some(bytes))?;
+        }
+    }
+    Ok(frame.finish()?)
+}
+
+/// Create `table` from the columns of `df`, the first frame produced by
+/// [`anonymize_customers`](crate::anonymize_customers) or a
+/// [`Policy`](crate::Policy), keyed by `id`, and insert it. Append later
+/// frames with [`insert_customers_anonymized`].
+pub async fn create_customers_anonymized(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    sqlx::query(&schema::drop_table(table))
+        .execute(&mut *conn)
+        .await?;
+    let options = WriteOptions::new(WriteMode::Create)
+        .with_primary_key(&["id"])
+        .with_batch_size(batch_size);
+    write_dataframe(conn, table, df, &options).await
+}
+
+/// Insert a frame produced by [`anonymize_customers`](crate::anonymize_customers).
+pub async fn insert_customers_anonymized(
+    conn: &mut PgConnection,
+    table: &str,
+    df: &DataFrame,
+    batch_size: usize,
+) -> anyhow::Result<usize> {
+    copy_dataframe(conn, table, df, batch_size).await
+}
+
+/// Measure `check` on `table`, whose rows are keyed by an integer `id`.
+/// Without `suppress`, fail if any class misses the thresholds; with it,
+/// delete those classes' rows. Returns the report from before suppression
+/// and the number of rows deleted.
+pub async fn enforce_privacy(
+    conn: &mut PgConnection,
+    table: &str,
+    check: &PrivacyCheck,
+    suppress: bool,
+) -> anyhow::Result<(PrivacyReport, u64)> {
+    let sql = check.select(table, "id");
+    let rows = sqlx::query(&sql).fetch_all(&mut *conn).await?;
+    let df = if rows.is_empty() {
+        let describe = (&mut *conn).describe(&sql).await?;
+        FrameBuilder::new(column_types(describe.columns()), 0)?.finish()?
+    } else {
+        rows_frame(&rows)?
+    };
+    let report = check.measure(&df)?;
+    if report.passed {
+        return Ok((report, 0));
+    }
+    if !suppress {
+        report.ensure()?;
+    }
+    let ids = violating_ids(check, &df)?;
+    let deleted = sqlx::query(&schema::delete_ids(table))
+        .bind(ids)
+        .execute(&mut *conn)
+        .await?;
+    Ok((report, deleted.rows_affected()))
+}
@@ -0,0 +1,401 @@
+//! Writing any `DataFrame` to a table whose DDL is derived from the frame.
+//!
+//! Both clients run the same [`WritePlan`] in one transaction: setup
+//! statements, a `COPY` of the frame, then finishing statements.
+//!
+//! | Polars                            | Postgres           |
+//! |-----------------------------------|--------------------|
+//! | `Boolean`                         | `BOOLEAN`          |
+//! | `Int8`, `Int16`, `UInt8`          | `SMALLINT`         |
+//! | `Int32`, `UInt16`                 | `INTEGER`          |
+//! | `Int64`, `UInt32`                 | `BIGINT`           |
+//! | `UInt64`                          | `NUMERIC(20)`      |
+//! | `Float32` / `Float64`             | `REAL` / `DOUBLE PRECISION` |
+//! | `String`                          | `TEXT`             |
+//! | `Date`                            | `DATE`             |
+//! | `Datetime` without / with a zone  | `TIMESTAMP` / `TIMESTAMPTZ` |
+//!
+//! Polars schemas carry no nullability, so only primary key columns are
+//! `NOT NULL`.
+
+use super::DEFAULT_BATCH_SIZE;
+use crate::schema::{drop_table, drop_table_restrict, index_base, quote_ident, quote_literal};
+use anyhow::{bail, ensure};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What to do with the target table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteMode {
+    /// Create the table; fail if it exists.
+    Create,
+    /// Insert into the table, creating it if missing.
+    #[default]
+    Append,
+    /// Build a new table and swap it in for the old one.
+    Replace,
+    /// Insert, updating rows whose primary key already exists. Creates the
+    /// table if missing.
+    Upsert,
+}
+
+impl std::str::FromStr for WriteMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "create" => WriteMode::Create,
+            "append" => WriteMode::Append,
+            "replace" => WriteMode::Replace,
+            "upsert" => WriteMode::Upsert,
+            other => bail!("unknown write mode `{other}` (create, append, replace, upsert)"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub mode: WriteMode,
+    /// Primary key columns for new tables; required for [`WriteMode::Upsert`].
+    pub primary_key: Vec<String>,
+    /// Rows per `COPY`.
+    pub batch_size: usize,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            mode: WriteMode::default(),
+            primary_key: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn new(mode: WriteMode) -> Self {
+        WriteOptions {
+            mode,
+            ..WriteOptions::default()
+        }
+    }
+
+    pub fn with_primary_key(mut self, columns: &[&str]) -> Self {
+        self.primary_key = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+}
+
+/// Postgres column type for a Polars dtype.
+pub fn pg_type(dtype: &DataType) -> anyhow::Result<&'static str> {
+    Ok(match dtype {
+        DataType::Boolean => "BOOLEAN",
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => "SMALLINT",
+        DataType::Int32 | DataType::UInt16 => "INTEGER",
+        DataType::Int64 | DataType::UInt32 => "BIGINT",
+        DataType::UInt64 => "NUMERIC(20)",
+        DataType::Float32 => "REAL",
+        DataType::Float64 => "DOUBLE PRECISION",
+        DataType::String => "TEXT",
+        DataType::Date => "DATE",
+        DataType::Datetime(_, None) => "TIMESTAMP",
+        DataType::Datetime(_, Some(_)) => "TIMESTAMPTZ",
+        other => bail!("no Postgres type for Polars dtype {other}"),
+    })
+}
+
+/// Frame columns an existing table cannot take: missing from `columns`
+/// (`column_name, data_type` pairs from
+/// [`schema::column_types`](crate::schema::column_types)) or with values
+/// that would not load into the column's type, such as text into `integer`.
+pub fn mismatched_columns(df: &DataFrame, columns: &[(String, String)]) -> Vec<String> {
+    df.get_columns()
+        .iter()
+        .filter(|column| {
+            let name = column.name().as_str();
+            !columns.iter().any(|(table_column, data_type)| {
+                table_column == name && loads_into(column.dtype(), data_type)
+            })
+        })
+        .map(|column| column.name().to_string())
+        .collect()
+}
+
+/// Whether `COPY` can load values of `dtype` into a column of `data_type`
+/// (as `information_schema` names it). Types without a Polars counterpart
+/// are left for Postgres to check.
+fn loads_into(dtype: &DataType, data_type: &str) -> bool {
+    match data_type {
+        _ if dtype == &DataType::Null => true,
+        "text" | "character varying" | "character" => true,
+        "smallint" | "integer" | "bigint" => dtype.is_integer(),
+        "real" | "double precision" | "numeric" => dtype.is_numeric(),
+        "boolean" => dtype == &DataType::Boolean,
+        "date" => dtype == &DataType::Date,
+        "timestamp without time zone" | "timestamp with time zone" => {
+            matches!(dtype, DataType::Datetime(..))
+        }
+        _ => true,
+    }
+}
+
+/// `CREATE TABLE` for the frame's schema.
+pub fn create_table(
+    table: &str,
+    df: &DataFrame,
+    primary_key: &[String],
+    if_not_exists: bool,
+) -> anyhow::Result<String> {
+    for key in primary_key {
+        ensure!(
+            df.get_column_index(key).is_some(),
+            "primary key column `{key}` is not in the frame"
+        );
+    }
+    let mut columns = Vec::with_capacity(df.width() + 1);
+    for column in df.get_columns() {
+        let name = column.name().as_str();
+        let not_null = match primary_key.iter().any(|k| k == name) {
+            true => " NOT NULL",
+            false => "",
+        };
+        columns.push(format!(
+            "{} {}{not_null}",
+            quote_ident(name),
+            pg_type(column.dtype())?
+        ));
+    }
+    if !primary_key.is_empty() {
+        columns.push(format!("PRIMARY KEY ({})", quoted_list(primary_key)));
+    }
+    let if_not_exists = if if_not_exists { "IF NOT EXISTS " } else { "" };
+    Ok(format!(
+        "CREATE TABLE {if_not_exists}{table} (\n    {}\n)",
+        columns.join(",\n    ")
+    ))
+}
+
+/// Statements around the `COPY` for one write. Upserts copy into a temp
+/// staging table, merge it with `INSERT ... ON CONFLICT` and drop it, so a
+/// key repeated within the frame is an error.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WritePlan {
+    pub before: Vec<String>,
+    /// Table the frame is copied into.
+    pub copy_into: String,
+    pub after: Vec<String>,
+}
+
+const UPSERT_STAGING: &str = "rustetl_upsert_staging";
+
+pub(crate) fn plan(
+    table: &str,
+    df: &DataFrame,
+    options: &WriteOptions,
+) -> anyhow::Result<WritePlan> {
+    ensure!(
+        df.width() > 0,
+        "cannot write a frame without columns to {table}"
+    );
+    let key = &options.primary_key;
+    let create = |if_not_exists| create_table(table, df, key, if_not_exists);
+    let direct = |before| WritePlan {
+        before,
+        copy_into: table.to_string(),
+        after: Vec::new(),
+    };
+    Ok(match options.mode {
+        WriteMode::Create => direct(vec![create(false)?]),
+        WriteMode::Append => direct(vec![create(true)?]),
+        WriteMode::Replace => {
+            let staging = staging_table(table);
+            WritePlan {
+                before: vec![
+                    drop_table(&staging),
+                    create_table(&staging, df, key, false)?,
+                ],
+                after: swap_tables(&staging, table),
+                copy_into: staging,
+            }
+        }
+        WriteMode::Upsert => {
+            ensure!(!key.is_empty(), "upsert needs a primary key");
+            let names: Vec<String> = df
+                .get_column_names_str()
+                .into_iter()
+                .map(String::from)
+                .collect();
+            let updates: Vec<String> = names
+                .iter()
+                .filter(|c| !key.contains(c))
+                .map(|c| format!("{0} = EXCLUDED.{0}", quote_ident(c)))
+                .collect();
+            let on_conflict = match updates.is_empty() {
+                true => "DO NOTHING".to_string(),
+                false => format!("DO UPDATE SET {}", updates.join(", ")),
+            };
+            let columns = quoted_list(&names);
+            WritePlan {
+                before: vec![
+                    create(true)?,
+                    format!("CREATE TEMP TABLE {UPSERT_STAGING} (LIKE {table} INCLUDING DEFAULTS)"),
+                ],
+                copy_into: UPSERT_STAGING.to_string(),
+                after: vec![
+                    format!(
+                        "INSERT INTO {table} ({columns}) SELECT {columns} FROM {UPSERT_STAGING} \
+                         ON CONFLICT ({}) {on_conflict}",
+                        quoted_list(key)
+                    ),
+                    drop_table(UPSERT_STAGING),
+                ],
+            }
+        }
+    })
+}
+
+/// Name of the table a replacement of `table` is built in.
+pub fn staging_table(table: &str) -> String {
+    format!("{table}_staging")
+}
+
+/// Replace `table` with `staging`: drop the old table, rename `staging`,
+/// and rename its indexes (and so its constraints) to match. Run it in the
+/// transaction that filled `staging` so readers see the old table or the
+/// complete new one, never a missing or half-written one.
+///
+/// The old table is dropped without `CASCADE`, so the swap fails while
+/// views or foreign keys depend on it rather than dropping them.
+pub(crate) fn swap_tables(staging: &str, table: &str) -> Vec<String> {
+    let (from, to) = (index_base(staging), index_base(table));
+    vec![
+        drop_table_restrict(table),
+        format!("ALTER TABLE {staging} RENAME TO {to}"),
+        format!(
+            "DO $$ DECLARE i record; BEGIN
+                FOR i IN SELECT n.nspname, c.relname FROM pg_index x
+                    JOIN pg_class c ON c.oid = x.indexrelid
+                    JOIN pg_namespace n ON n.oid = c.relnamespace
+                    WHERE x.indrelid = {}::regclass AND strpos(c.relname, {from_lit}) > 0
+                LOOP
+                    EXECUTE format('ALTER INDEX %I.%I RENAME TO %I', i.nspname, i.relname,
+                        replace(i.relname, {from_lit}, {to_lit}));
+                END LOOP;
+            END $$",
+            quote_literal(table),
+            from_lit = quote_literal(from),
+            to_lit = quote_literal(to),
+        ),
+    ]
+}
+
+fn quoted_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|n| quote_ident(n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DataFrame {
+        df! {
+            "id" => &[1i64, 2],
+            "name" => &[Some("a"), None],
+            "score" => &[1.5f64, 2.0],
+            "ok" => &[true, false],
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn test_create_table_ddl() {
+        let ddl = create_table("shopping.t", &sample(), &["id".to_string()], false).unwrap();
+        assert_eq!(
+            ddl,
+            "CREATE TABLE shopping.t (\n    \"id\" BIGINT NOT NULL,\n    \"name\" TEXT,\n    \
+             \"score\" DOUBLE PRECISION,\n    \"ok\" BOOLEAN,\n    PRIMARY KEY (\"id\")\n)"
+        );
+        assert!(create_table("t", &sample(), &["nope".to_string()], true).is_err());
+    }
+
+    #[test]
+    fn test_pg_types() {
+        assert_eq!(pg_type(&DataType::Int32).unwrap(), "INTEGER");
+        assert_eq!(pg_type(&DataType::Date).unwrap(), "DATE");
+        assert_eq!(
+            pg_type(&DataType::Datetime(
+                TimeUnit::Microseconds,
+                Some("UTC".into())
+            ))
+            .unwrap(),
+            "TIMESTAMPTZ"
+        );
+        assert!(pg_type(&DataType::List(Box::new(DataType::Int32))).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_columns() {
+        let s = |v: &str| v.to_string();
+        let columns = [
+            (s("id"), s("bigint")),
+            (s("name"), s("character varying")),
+            (s("score"), s("integer")),
+            (s("extra"), s("boolean")),
+        ];
+        // `score` holds floats and `ok` is not in the table
+        assert_eq!(mismatched_columns(&sample(), &columns), ["score", "ok"]);
+        let labels = df!("id" => &[1i64], "score" => &["25-34"]).unwrap();
+        assert_eq!(mismatched_columns(&labels, &columns), ["score"]);
+        let numbers = df!("id" => &[1i32], "name" => &[7i64]).unwrap();
+        assert!(mismatched_columns(&numbers, &columns).is_empty());
+    }
+
+    #[test]
+    fn test_plans() {
+        let df = sample();
+        let replace = plan("t", &df, &WriteOptions::new(WriteMode::Replace)).unwrap();
+        assert_eq!(replace.before[0], "DROP TABLE IF EXISTS t_staging CASCADE");
+        assert_eq!(replace.copy_into, "t_staging");
+        assert_eq!(replace.after[0], "DROP TABLE IF EXISTS t RESTRICT");
+
+        let append = plan("t", &df, &WriteOptions::default()).unwrap();
+        assert!(append.before[0].starts_with("CREATE TABLE IF NOT EXISTS t"));
+
+        assert!(plan("t", &df, &WriteOptions::new(WriteMode::Upsert)).is_err());
+        let upsert = plan(
+            "t",
+            &df,
+            &WriteOptions::new(WriteMode::Upsert).with_primary_key(&["id"]),
+        )
+        .unwrap();
+        assert_eq!(upsert.copy_into, UPSERT_STAGING);
+        assert!(upsert.after[0].ends_with(
+            "ON CONFLICT (\"id\") DO UPDATE SET \"name\" = EXCLUDED.\"name\", \
+             \"score\" = EXCLUDED.\"score\", \"ok\" = EXCLUDED.\"ok\""
+        ));
+    }
+
+    #[test]
+    fn test_swap_renames_staging_and_its_indexes() {
+        let swap = swap_tables("shopping.t_staging", "shopping.t");
+        assert_eq!(swap[1], "ALTER TABLE shopping.t_staging RENAME TO t");
+        assert!(swap[2].contains("x.indrelid = 'shopping.t'::regclass"));
+        assert!(swap[2].contains("replace(i.relname, 't_staging', 't')"));
+    }
+
+    #[test]
+    fn test_write_mode_from_str() {
+        assert_eq!("Upsert".parse::<WriteMode>().unwrap(), WriteMode::Upsert);
+        assert!("merge".parse::<WriteMode>().is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! rustls connector for the synchronous `postgres` client, following libpq's
+//! `sslmode` rules:
+//!
+//! | sslmode                   | Encrypted         | Certificate checked                   |
+//! |---------------------------|-------------------|---------------------------------------|
+//! | `disable`                 | no                | no                                    |
+//! | `allow`, `prefer`         | if the server can | only if a CA is given (as `verify-ca`) |
+//! | `require`                 | yes               | only if a CA is given (as `verify-ca`) |
+//! | `verify-ca`               | yes               | chain, not the host name              |
+//! | `verify-full`             | yes               | chain and host name                   |
+//!
+//! The CA is `root_certificate_pem` when set, else the Mozilla roots from
+//! `webpki-roots`.
+
+use super::config::{PgConfig, SslMode};
+use anyhow::{Context, ensure};
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::sync::Arc;
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+/// How the server certificate is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verification {
+    None,
+    Chain,
+    Full,
+}
+
+fn verification(config: &PgConfig) -> Verification {
+    match config.sslmode {
+        SslMode::VerifyFull => Verification::Full,
+        SslMode::VerifyCa => Verification::Chain,
+        _ if config.root_certificate_pem.is_some() => Verification::Chain,
+        _ => Verification::None,
+    }
+}
+
+pub(crate) fn make_tls(config: &PgConfig) -> anyhow::Result<MakeRustlsConnect> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("TLS protocol versions")?;
+    let verifier: Arc<dyn ServerCertVerifier> = match verification(config) {
+        Verification::None => Arc::new(NoVerification(provider)),
+        checked => {
+            let webpki = WebPkiServerVerifier::builder_with_provider(
+                Arc::new(root_store(config)?),
+                provider,
+            )
+            .build()?;
+            match checked {
+                Verification::Full => webpki,
+                _ => Arc::new(SkipHostname(webpki)),
+            }
+        }
+    };
+    let tls = builder
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    Ok(MakeRustlsConnect::new(tls))
+}
+
+fn root_store(config: &PgConfig) -> anyhow::Result<RootCertStore> {
+    let Some(pem) = &config.root_certificate_pem else {
+        return Ok(RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        });
+    };
+    let mut store = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+        store
+            .add(cert.context("invalid root certificate PEM")?)
+            .context("invalid root certificate")?;
+    }
+    ensure!(
+        !store.is_empty(),
+        "root certificate PEM has no certificates"
+    );
+    Ok(store)
+}
+
+/// `require` / `prefer` without a CA: encrypt, but accept any certificate.
+/// Handshake signatures are still checked.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// `verify-ca`: the chain must lead to a trusted root, whatever host name
+/// the certificate is for.
+#[derive(Debug)]
+struct SkipHostname(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for SkipHostname {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(sslmode: &str, pem: Option<&str>) -> PgConfig {
+        let mut config =
+            PgConfig::from_url(&format!("postgres://u@db/d?sslmode={sslmode}")).unwrap();
+        config.root_certificate_pem = pem.map(String::from);
+        config
+    }
+
+    #[test]
+    fn test_sslmode_verification() {
+        assert_eq!(verification(&config("prefer", None)), Verification::None);
+        assert_eq!(verification(&config("require", None)), Verification::None);
+        assert_eq!(
+            verification(&config("require", Some("pem"))),
+            Verification::Chain
+        );
+        assert_eq!(
+            verification(&config("verify-ca", None)),
+            Verification::Chain
+        );
+        assert_eq!(
+            verification(&config("verify-full", None)),
+            Verification::Full
+        );
+        for mode in ["disable", "prefer", "require", "verify-ca", "verify-full"] {
+            assert!(make_tls(&config(mode, None)).is_ok(), "{mode}");
+        }
+    }
+
+    #[test]
+    fn test_bad_root_certificate() {
+        assert!(make_tls(&config("verify-full", Some("not a pem"))).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+//! High-water marks for incremental runs, kept in [`WATERMARKS_TABLE`].
+//!
+//! A mark is the last `(column, id)` a pipeline has processed, where
+//! `column` is a non-null `TIMESTAMPTZ` stamped on insert and update (the
+//! `customers` table has `updated_at` for this, see
+//! [`schema::track_updates`](crate::schema::track_updates)). A run first
+//! pins the newest row older than the [lag](Watermark::with_lag) as
+//! `pending`, reads rows between the saved mark and the pending one, and
+//! promotes `pending` on save. All of it happens in the run's transaction,
+//! so a failed run leaves the mark untouched and concurrent runs of one
+//! pipeline queue behind each other.
+//!
+//! Timestamps are taken when a row is written but only become visible when
+//! its transaction commits. A write that is still uncommitted while a run
+//! pins a mark past it is skipped for good, so a writer must commit within
+//! the lag of writing a row. Rows newer than the lag wait for the next run.
+//! Deleted source rows are not tracked.
+
+use crate::schema::{quote_ident, quote_literal};
+use std::time::Duration;
+
+pub const WATERMARKS_TABLE: &str = "rustetl_watermarks";
+
+/// How far behind `now()` a mark is pinned unless the caller chooses.
+pub const DEFAULT_LAG: Duration = Duration::from_secs(60);
+
+/// The mark of one pipeline over one timestamp column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watermark {
+    pipeline: String,
+    column: String,
+    lag: Duration,
+}
+
+impl Watermark {
+    /// `pipeline` names the state row, usually the target table.
+    pub fn new(pipeline: &str, column: &str) -> Self {
+        Watermark {
+            pipeline: pipeline.to_string(),
+            column: column.to_string(),
+            lag: DEFAULT_LAG,
+        }
+    }
+
+    /// Only pin rows written at least `lag` before the run started; longer
+    /// than any source transaction stays open after writing.
+    pub fn with_lag(mut self, lag: Duration) -> Self {
+        self.lag = lag;
+        self
+    }
+
+    pub fn pipeline(&self) -> &str {
+        &self.pipeline
+    }
+
+    fn key(&self) -> String {
+        quote_literal(&self.pipeline)
+    }
+
+    /// Create the state row if needed, lock it and pin the newest
+    /// `(column, id)` of `source` older than the lag as pending.
+    pub(crate) fn claim(&self, source: &str) -> Vec<String> {
+        let key = self.key();
+        let column = quote_ident(&self.column);
+        let lag = self.lag.as_millis();
+        vec![
+            create_watermarks(),
+            format!(
+                "INSERT INTO {WATERMARKS_TABLE} (pipeline) VALUES ({key}) \
+                 ON CONFLICT (pipeline) DO NOTHING"
+            ),
+            format!("SELECT 1 FROM {WATERMARKS_TABLE} WHERE pipeline = {key} FOR UPDATE"),
+            format!(
+                "UPDATE {WATERMARKS_TABLE} SET (pending_watermark, pending_id) = \
+                 (SELECT {column}, id FROM {source} \
+                 WHERE {column} <= now() - interval '{lag} milliseconds' \
+                 ORDER BY {column} DESC, id DESC LIMIT 1) \
+                 WHERE pipeline = {key}"
+            ),
+        ]
+    }
+
+    /// `columns` of `source` rows after the saved mark, up to the pending
+    /// one, oldest first.
+    pub(crate) fn select_since(&self, source: &str, columns: &str) -> String {
+        let key = self.key();
+        let column = quote_ident(&self.column);
+        format!(
+            "SELECT {columns} FROM {source} \
+             WHERE ({column}, id) > (SELECT watermark, last_id FROM {WATERMARKS_TABLE} WHERE pipeline = {key}) \
+             AND ({column}, id) <= (SELECT pending_watermark, pending_id FROM {WATERMARKS_TABLE} WHERE pipeline = {key}) \
+             ORDER BY {column}, id"
+        )
+    }
+
+    /// Promote the pending mark if it is ahead of the saved one. It can be
+    /// missing (nothing older than the lag) or behind (the marked row was
+    /// updated since), and then the old mark stays.
+    pub(crate) fn save(&self) -> String {
+        let ahead = "(pending_watermark, pending_id) > (watermark, last_id)";
+        format!(
+            "UPDATE {WATERMARKS_TABLE} SET \
+             watermark = CASE WHEN {ahead} THEN pending_watermark ELSE watermark END, \
+             last_id = CASE WHEN {ahead} THEN pending_id ELSE last_id END, \
+             pending_watermark = NULL, pending_id = NULL, \
+             updated_at = CURRENT_TIMESTAMP \
+             WHERE pipeline = {}",
+            self.key()
+        )
+    }
+
+    /// Forget the mark so the next run reads every row.
+    pub(crate) fn reset(&self) -> Vec<String> {
+        vec![
+            create_watermarks(),
+            format!(
+                "DELETE FROM {WATERMARKS_TABLE} WHERE pipeline = {}",
+                self.key()
+            ),
+        ]
+    }
+}
+
+fn create_watermarks() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {WATERMARKS_TABLE} (
+            pipeline TEXT PRIMARY KEY,
+            watermark TIMESTAMPTZ NOT NULL DEFAULT '-infinity',
+            last_id BIGINT NOT NULL DEFAULT 0,
+            pending_watermark TIMESTAMPTZ,
+            pending_id BIGINT,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark_statements() {
+        let mark = Watermark::new("it's", "updated_at").with_lag(Duration::from_secs(5));
+        let claim = mark.claim("customers");
+        assert!(claim[1].contains("VALUES ('it''s')"));
+        assert!(claim[3].contains(
+            "(SELECT \"updated_at\", id FROM customers \
+             WHERE \"updated_at\" <= now() - interval '5000 milliseconds' \
+             ORDER BY \"updated_at\" DESC, id DESC LIMIT 1)"
+        ));
+        let select = mark.select_since("customers", "id, name");
+        assert!(select.starts_with("SELECT id, name FROM customers WHERE (\"updated_at\", id) > "));
+        assert!(select.ends_with("ORDER BY \"updated_at\", id"));
+        assert!(mark.save().contains(
+            "watermark = CASE WHEN (pending_watermark, pending_id) > (watermark, last_id) \
+             THEN pending_watermark ELSE watermark END"
+        ));
+        assert_eq!(
+            mark.reset()[1],
+            "DELETE FROM rustetl_watermarks WHERE pipeline = 'it''s'"
+        );
+    }
+}
@@ -0,0 +1,564 @@
+//! Differentially private group-by aggregates.
+//!
+//! A [`DpQuery`] computes counts, sums and means per group and adds noise
+//! calibrated to how much one row can move each statistic, so the result
+//! can be published without releasing row-level data:
+//!
+//! ```toml
+//! epsilon = 1.0
+//! group_by = ["department"]
+//!
+//! [[aggregates]]
+//! statistic = "count"
+//! alias = "employee_count"
+//!
+//! [[aggregates]]
+//! statistic = "mean"
+//! column = "salary"
+//! lower = 30000
+//! upper = 200000
+//! ```
+//!
+//! Values are clamped to `[lower, upper]`, which bounds one row's effect on
+//! a sum (and so the noise needed). Every row lands in exactly one group, so
+//! all groups share the whole `epsilon`; the aggregates of a group split it
+//! evenly, and a mean spends its share half on a noisy sum and half on a
+//! noisy count. This assumes one row per person: group by a table with
+//! several rows per person (orders per customer) and the guarantee weakens
+//! by that factor.
+//!
+//! Group keys come from the data, so a group of one would publish that
+//! person's key. Groups whose noisy row count is below `threshold` (default
+//! 10) are dropped. The count is the query's `count` aggregate without a
+//! column if it has one; otherwise an extra noisy count takes an equal share
+//! of `epsilon`. Set `threshold = 0` only when every key is public anyway.
+
+use anyhow::{Context, bail};
+use polars::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Noise distribution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mechanism {
+    /// Pure epsilon-DP.
+    #[default]
+    Laplace,
+    /// (epsilon, delta)-DP; lighter tails, needs `epsilon <= 1`.
+    Gaussian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Statistic {
+    /// Rows per group, or non-null values of `column` if given.
+    Count,
+    Sum,
+    Mean,
+}
+
+/// One released statistic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DpAggregate {
+    pub statistic: Statistic,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lower: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upper: Option<f64>,
+    /// Output column name (default `count`, `sum_<column>`, `mean_<column>`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+impl DpAggregate {
+    pub fn name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        match (self.statistic, &self.column) {
+            (Statistic::Count, None) => "count".to_string(),
+            (Statistic::Count, Some(column)) => format!("count_{column}"),
+            (Statistic::Sum, Some(column)) => format!("sum_{column}"),
+            (Statistic::Mean, Some(column)) => format!("mean_{column}"),
+            (_, None) => String::new(),
+        }
+    }
+
+    /// Largest change one row can make to the noisy sum.
+    fn sum_sensitivity(&self) -> f64 {
+        let (lower, upper) = self.bounds();
+        lower.abs().max(upper.abs())
+    }
+
+    fn bounds(&self) -> (f64, f64) {
+        (self.lower.unwrap_or(0.0), self.upper.unwrap_or(0.0))
+    }
+
+    fn clamped(&self, column: &str) -> Expr {
+        let (lower, upper) = self.bounds();
+        let value = col(column).cast(DataType::Float64);
+        when(value.clone().lt(lit(lower)))
+            .then(lit(lower))
+            .when(value.clone().gt(lit(upper)))
+            .then(lit(upper))
+            .otherwise(value)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let name = self.name();
+        match (self.statistic, &self.column) {
+            (Statistic::Count, _) => return Ok(()),
+            (_, None) => bail!("{:?} needs a `column`", self.statistic),
+            _ => {}
+        }
+        let (Some(lower), Some(upper)) = (self.lower, self.upper) else {
+            bail!("`{name}` needs `lower` and `upper` bounds");
+        };
+        if !(lower.is_finite() && upper.is_finite() && lower < upper) {
+            bail!("`{name}`: bounds must be finite with lower < upper");
+        }
+        Ok(())
+    }
+}
+
+fn default_delta() -> f64 {
+    1e-6
+}
+
+fn default_threshold() -> f64 {
+    10.0
+}
+
+/// A group-by released under an epsilon budget.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DpQuery {
+    pub epsilon: f64,
+    #[serde(default)]
+    pub mechanism: Mechanism,
+    /// Only used by [`Mechanism::Gaussian`].
+    #[serde(default = "default_delta")]
+    pub delta: f64,
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// Smallest noisy row count a group needs to be released.
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    pub aggregates: Vec<DpAggregate>,
+}
+
+impl DpQuery {
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let query: DpQuery = toml::from_str(text).context("invalid TOML query")?;
+        query.validate()?;
+        Ok(query)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let query: DpQuery = serde_json::from_str(text).context("invalid JSON query")?;
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// JSON if `text` starts with `{`, TOML otherwise.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.epsilon.is_finite() && self.epsilon > 0.0) {
+            bail!("epsilon must be positive");
+        }
+        if self.mechanism == Mechanism::Gaussian {
+            if self.epsilon > 1.0 {
+                bail!("the Gaussian mechanism needs epsilon <= 1");
+            }
+            if !(self.delta > 0.0 && self.delta < 1.0) {
+                bail!("delta must be between 0 and 1");
+            }
+        }
+        if !(self.threshold.is_finite() && self.threshold >= 0.0) {
+            bail!("threshold must be zero or positive");
+        }
+        if self.aggregates.is_empty() {
+            bail!("at least one aggregate is required");
+        }
+        let mut names = Vec::new();
+        for aggregate in &self.aggregates {
+            aggregate.validate()?;
+            let name = aggregate.name();
+            if names.contains(&name) || self.group_by.contains(&name) {
+                bail!("duplicate output column `{name}`");
+            }
+            names.push(name);
+        }
+        Ok(())
+    }
+
+    /// Columns the query reads from `table`, an unqualified or
+    /// `schema.table` name that is quoted here.
+    pub fn select(&self, table: &str) -> String {
+        let table = crate::schema::quote_table(table);
+        let mut columns: Vec<&str> = self.group_by.iter().map(String::as_str).collect();
+        for aggregate in &self.aggregates {
+            if let Some(column) = aggregate.column.as_deref()
+                && !columns.contains(&column)
+            {
+                columns.push(column);
+            }
+        }
+        if columns.is_empty() {
+            return format!("SELECT 1 AS one FROM {table}");
+        }
+        let columns: Vec<String> = columns
+            .into_iter()
+            .map(crate::schema::quote_ident)
+            .collect();
+        format!("SELECT {} FROM {table}", columns.join(", "))
+    }
+
+    /// Noise for a statistic with `sensitivity`, spending `epsilon` of the
+    /// budget.
+    fn noise(&self, sensitivity: f64, epsilon: f64) -> Noise {
+        match self.mechanism {
+            Mechanism::Laplace => Noise::Laplace(sensitivity / epsilon),
+            Mechanism::Gaussian => {
+                Noise::Gaussian(sensitivity * (2.0 * (1.25 / self.delta).ln()).sqrt() / epsilon)
+            }
+        }
+    }
+
+    /// The aggregate whose noisy value is the group's row count, if any.
+    fn row_count(&self) -> Option<usize> {
+        self.aggregates
+            .iter()
+            .position(|a| a.statistic == Statistic::Count && a.column.is_none())
+    }
+
+    /// Compute the aggregates of `df`, add noise and drop groups below the
+    /// threshold. Without a `seed` the noise is fresh on every call; only
+    /// set it in tests, since anyone who knows the seed can subtract the
+    /// noise.
+    pub fn release(&self, df: &DataFrame, seed: Option<u64>) -> anyhow::Result<DataFrame> {
+        self.validate()?;
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        // Exact per-group statistics; means need a sum and a count
+        let mut exprs = Vec::new();
+        for (i, aggregate) in self.aggregates.iter().enumerate() {
+            let column = aggregate.column.as_deref();
+            match (aggregate.statistic, column) {
+                (Statistic::Count, None) => exprs.push(len().alias(format!("__count_{i}"))),
+                (Statistic::Count, Some(column)) => {
+                    exprs.push(col(column).count().alias(format!("__count_{i}")))
+                }
+                (Statistic::Sum, Some(column)) => {
+                    exprs.push(aggregate.clamped(column).sum().alias(format!("__sum_{i}")))
+                }
+                (Statistic::Mean, Some(column)) => {
+                    exprs.push(aggregate.clamped(column).sum().alias(format!("__sum_{i}")));
+                    exprs.push(col(column).count().alias(format!("__count_{i}")));
+                }
+                _ => unreachable!("validated"),
+            }
+        }
+        let thresholded = !self.group_by.is_empty() && self.threshold > 0.0;
+        let extra_count = thresholded && self.row_count().is_none();
+        if extra_count {
+            exprs.push(len().alias("__rows"));
+        }
+        let keys: Vec<Expr> = self.group_by.iter().map(col).collect();
+        let exact = if keys.is_empty() {
+            df.clone().lazy().select(exprs).collect()?
+        } else {
+            let by: Vec<&str> = self.group_by.iter().map(String::as_str).collect();
+            df.clone()
+                .lazy()
+                .group_by(keys)
+                .agg(exprs)
+                .sort(by, SortMultipleOptions::default().with_nulls_last(true))
+                .collect()?
+        };
+
+        let shares = self.aggregates.len() + usize::from(extra_count);
+        let share = self.epsilon / shares as f64;
+        let mut columns: Vec<Column> = self
+            .group_by
+            .iter()
+            .map(|name| exact.column(name).cloned())
+            .collect::<PolarsResult<_>>()?;
+        for (i, aggregate) in self.aggregates.iter().enumerate() {
+            let stat = |prefix: &str| -> PolarsResult<Vec<f64>> {
+                let values = exact
+                    .column(&format!("__{prefix}_{i}"))?
+                    .cast(&DataType::Float64)?;
+                Ok(values
+                    .f64()?
+                    .into_iter()
+                    .map(|v| v.unwrap_or(0.0))
+                    .collect())
+            };
+            let name = aggregate.name();
+            let column = match aggregate.statistic {
+                Statistic::Count => {
+                    let noise = self.noise(1.0, share);
+                    let counts: Vec<i64> = stat("count")?
+                        .into_iter()
+                        .map(|n| noise.count(n, &mut rng))
+                        .collect();
+                    Column::new(name.into(), counts)
+                }
+                Statistic::Sum => {
+                    let noise = self.noise(aggregate.sum_sensitivity(), share);
+                    let sums: Vec<f64> = stat("sum")?
+                        .into_iter()
+                        .map(|s| s + noise.sample(&mut rng))
+                        .collect();
+                    Column::new(name.into(), sums)
+                }
+                Statistic::Mean => {
+                    let sum_noise = self.noise(aggregate.sum_sensitivity(), share / 2.0);
+                    let count_noise = self.noise(1.0, share / 2.0);
+                    let (lower, upper) = aggregate.bounds();
+                    let means: Vec<f64> = stat("sum")?
+                        .into_iter()
+                        .zip(stat("count")?)
+                        .map(|(s, n)| {
+                            let s = s + sum_noise.sample(&mut rng);
+                            let n = (n + count_noise.sample(&mut rng)).max(1.0);
+                            (s / n).clamp(lower, upper)
+                        })
+                        .collect();
+                    Column::new(name.into(), means)
+                }
+            };
+            columns.push(column);
+        }
+        let released = DataFrame::new(columns)?;
+        if !thresholded {
+            return Ok(released);
+        }
+
+        let sizes: Vec<i64> = match self.row_count() {
+            Some(i) => released
+                .column(&self.aggregates[i].name())?
+                .i64()?
+                .into_no_null_iter()
+                .collect(),
+            None => {
+                let noise = self.noise(1.0, share);
+                exact
+                    .column("__rows")?
+                    .cast(&DataType::Float64)?
+                    .f64()?
+                    .into_no_null_iter()
+                    .map(|n| noise.count(n, &mut rng))
+                    .collect()
+            }
+        };
+        let keep: BooleanChunked = sizes
+            .into_iter()
+            .map(|n| n as f64 >= self.threshold)
+            .collect();
+        Ok(released.filter(&keep)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Noise {
+    /// Scale `b`: standard deviation `b * sqrt(2)`.
+    Laplace(f64),
+    /// Standard deviation.
+    Gaussian(f64),
+}
+
+impl Noise {
+    /// A noisy count, rounded and never negative.
+    fn count(self, n: f64, rng: &mut StdRng) -> i64 {
+        (n + self.sample(rng)).round().max(0.0) as i64
+    }
+
+    fn sample(self, rng: &mut StdRng) -> f64 {
+        match self {
+            Noise::Laplace(scale) => {
+                // Inverse CDF of a uniform draw from (-0.5, 0.5)
+                let u: f64 = rng.r#gen::<f64>() - 0.5;
+                -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+            }
+            Noise::Gaussian(std_dev) => {
+                // Box-Muller
+                let u: f64 = 1.0 - rng.r#gen::<f64>();
+                let v: f64 = rng.r#gen();
+                std_dev * (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn employees() -> DataFrame {
+        let departments: Vec<&str> = (0..30_000)
+            .map(|i| ["Engineering", "Sales", "HR"][i % 3])
+            .collect();
+        let salaries: Vec<i32> = (0..30_000)
+            .map(|i| [80_000, 60_000, 50_000][i % 3] + (i as i32 % 7) * 1000)
+            .collect();
+        df!("department" => departments, "salary" => salaries).unwrap()
+    }
+
+    fn query(mechanism: &str) -> DpQuery {
+        DpQuery::parse(&format!(
+            r#"
+            epsilon = 1.0
+            mechanism = "{mechanism}"
+            group_by = ["department"]
+
+            [[aggregates]]
+            statistic = "count"
+
+            [[aggregates]]
+            statistic = "mean"
+            column = "salary"
+            lower = 0
+            upper = 200000
+            alias = "avg_salary"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_release_is_close_to_exact() {
+        for mechanism in ["laplace", "gaussian"] {
+            let out = query(mechanism).release(&employees(), Some(7)).unwrap();
+            assert_eq!(
+                out.get_column_names_str(),
+                ["department", "count", "avg_salary"]
+            );
+            let departments: Vec<_> = out
+                .column("department")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .collect();
+            assert_eq!(departments, ["Engineering", "HR", "Sales"]);
+            let counts = out.column("count").unwrap().i64().unwrap();
+            let means = out.column("avg_salary").unwrap().f64().unwrap();
+            for (row, exact_mean) in [(0, 83_000.0), (1, 53_000.0), (2, 63_000.0)] {
+                assert!(
+                    (counts.get(row).unwrap() - 10_000).abs() < 100,
+                    "{mechanism}"
+                );
+                assert!(
+                    (means.get(row).unwrap() - exact_mean).abs() < 2_000.0,
+                    "{mechanism}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_release_adds_noise() {
+        let query = query("laplace");
+        let a = query.release(&employees(), Some(1)).unwrap();
+        let b = query.release(&employees(), Some(2)).unwrap();
+        assert!(!a.equals(&b));
+        assert!(a.equals(&query.release(&employees(), Some(1)).unwrap()));
+
+        let total = DpQuery::parse(
+            r#"{"epsilon": 0.5, "aggregates": [{"statistic": "sum", "column": "salary", "lower": 0, "upper": 100000}]}"#,
+        )
+        .unwrap()
+        .release(&employees(), None)
+        .unwrap();
+        assert_eq!(total.shape(), (1, 1));
+        assert_eq!(total.get_column_names_str(), ["sum_salary"]);
+    }
+
+    #[test]
+    fn test_rare_groups_are_suppressed() {
+        let mut departments = vec!["Engineering"; 1000];
+        departments.push("CEO");
+        let salaries = vec![100_000; departments.len()];
+        let df = df!("department" => departments, "salary" => salaries).unwrap();
+        let released = |query: &DpQuery| -> Vec<String> {
+            let out = query.release(&df, Some(5)).unwrap();
+            out.column("department")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .map(String::from)
+                .collect()
+        };
+
+        // Thresholded on the released count
+        let mut counted = query("laplace");
+        assert_eq!(released(&counted), ["Engineering"]);
+        counted.threshold = 0.0;
+        assert_eq!(released(&counted), ["CEO", "Engineering"]);
+
+        // Thresholded on an extra noisy count
+        let mean_only = DpQuery::parse(
+            r#"
+            epsilon = 1.0
+            group_by = ["department"]
+
+            [[aggregates]]
+            statistic = "mean"
+            column = "salary"
+            lower = 0
+            upper = 200000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(released(&mean_only), ["Engineering"]);
+    }
+
+    #[test]
+    fn test_select_quotes_names() {
+        assert_eq!(
+            query("laplace").select("hr.employees"),
+            r#"SELECT "department", "salary" FROM "hr"."employees""#
+        );
+        assert_eq!(
+            query("laplace").select("t; DROP TABLE t"),
+            r#"SELECT "department", "salary" FROM "t; DROP TABLE t""#
+        );
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        let aggregates = r#""aggregates": [{"statistic": "count"}]"#;
+        assert!(DpQuery::parse(&format!(r#"{{"epsilon": 0, {aggregates}}}"#)).is_err());
+        let gaussian = format!(r#"{{"epsilon": 2, "mechanism": "gaussian", {aggregates}}}"#);
+        assert!(DpQuery::parse(&gaussian).is_err());
+        let unbounded =
+            r#"{"epsilon": 1, "aggregates": [{"statistic": "mean", "column": "salary"}]}"#;
+        assert!(
+            DpQuery::parse(unbounded)
+                .unwrap_err()
+                .to_string()
+                .contains("`mean_salary` needs `lower` and `upper`")
+        );
+        let twice =
+            r#"{"epsilon": 1, "aggregates": [{"statistic": "count"}, {"statistic": "count"}]}"#;
+        assert!(DpQuery::parse(twice).is_err());
+        let threshold = format!(r#"{{"epsilon": 1, "threshold": -1, {aggregates}}}"#);
+        assert!(DpQuery::parse(&threshold).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Fake value generators shared by the data generators and the `fake`
+//! anonymization strategy.
+
+use fake::Fake;
+use fake::faker::address::en::{BuildingNumber, CityName, StreetName};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::faker::phone_number::en::PhoneNumber;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Kind of fake value to generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FakeKind {
+    Name,
+    Email,
+    Phone,
+    Address,
+    City,
+    Ssn,
+}
+
+impl FakeKind {
+    pub fn generate(self) -> String {
+        self.generate_with(&mut rand::thread_rng())
+    }
+
+    /// Generate from `rng`, so a seeded RNG gives the same values every run.
+    pub fn generate_with<R: Rng + ?Sized>(self, rng: &mut R) -> String {
+        match self {
+            FakeKind::Name => Name().fake_with_rng(rng),
+            FakeKind::Email => SafeEmail().fake_with_rng(rng),
+            FakeKind::Phone => PhoneNumber().fake_with_rng(rng),
+            FakeKind::Address => format!(
+                "{} {}, {}",
+                BuildingNumber().fake_with_rng::<String, _>(rng),
+                StreetName().fake_with_rng::<String, _>(rng),
+                CityName().fake_with_rng::<String, _>(rng)
+            ),
+            FakeKind::City => CityName().fake_with_rng(rng),
+            FakeKind::Ssn => format!(
+                "{:03}-{:02}-{:04}",
+                rng.gen_range(100..999),
+                rng.gen_range(10..99),
+                rng.gen_range(1000..9999)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_seeded_generation_repeats() {
+        let run = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            [FakeKind::Name, FakeKind::Address, FakeKind::Ssn].map(|k| k.generate_with(&mut rng))
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+}
@@ -0,0 +1,437 @@
+//! Generalization of quasi-identifiers: numeric values to ranges and dates
+//! to months or years, as Polars expressions.
+//!
+//! [`Buckets`] turns numbers into range labels with `cut`. The ranges come
+//! from one of:
+//!
+//! - `breaks`: explicit boundaries; the first and last ranges are open,
+//! - `width`: ranges of equal width aligned to multiples of it (`20-30`,
+//!   `30-40`, ...),
+//! - `bins`: that many equal-width ranges between the column's min and max,
+//! - `quantiles`: that many ranges holding about the same number of rows.
+//!
+//! Ranges include their lower bound and exclude the upper one. `bins` and
+//! `quantiles` depend on the data, so chunked pipelines get different ranges
+//! per chunk; use `breaks` or `width` there.
+//!
+//! Labels are either listed or rendered from templates with `{lower}` and
+//! `{upper}`, after dividing the bounds by `scale`:
+//!
+//! ```toml
+//! [columns.salary]
+//! strategy = "bucket"
+//! breaks = [50000, 75000, 100000, 125000]
+//! scale = 1000
+//! label = "${lower}k-${upper}k"       # $50k-$75k
+//! first_label = "< ${upper}k"         # < $50k
+//! last_label = "> ${lower}k"          # > $125k
+//! ```
+
+use anyhow::{bail, ensure};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Most ranges one bucket column may have; more would build a label per
+/// range for every chunk.
+pub const MAX_RANGES: usize = 10_000;
+
+/// How numbers are grouped into ranges and how the ranges are labelled.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Buckets {
+    /// Explicit boundaries, strictly increasing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breaks: Vec<f64>,
+    /// Width of equal ranges aligned to its multiples.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    /// Number of equal-width ranges between the data's min and max.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bins: Option<usize>,
+    /// Number of equal-frequency ranges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantiles: Option<usize>,
+    /// One label per range. Only with `breaks`; overrides the templates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Template for ranges with both bounds (default `{lower}-{upper}`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Template for the open range below the first break (default `< {upper}`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_label: Option<String>,
+    /// Template for the open range from the last break (default `>= {lower}`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_label: Option<String>,
+    /// Bounds are divided by this before rendering.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+}
+
+/// Ranges with their `(lower, upper)` bounds, infinite where open.
+struct Ranges {
+    breaks: Vec<f64>,
+    bounds: Vec<(f64, f64)>,
+}
+
+impl Buckets {
+    /// Ranges between explicit `breaks`, labelled by `labels`.
+    pub fn with_labels(breaks: &[f64], labels: &[&str]) -> Self {
+        Buckets {
+            breaks: breaks.to_vec(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            ..Buckets::default()
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let methods = [
+            !self.breaks.is_empty(),
+            self.width.is_some(),
+            self.bins.is_some(),
+            self.quantiles.is_some(),
+        ];
+        ensure!(
+            methods.iter().filter(|m| **m).count() == 1,
+            "bucket needs exactly one of breaks, width, bins or quantiles"
+        );
+        if self.breaks.windows(2).any(|w| w[0] >= w[1])
+            || self.breaks.iter().any(|b| !b.is_finite())
+        {
+            bail!("bucket breaks must be finite and strictly increasing");
+        }
+        if !self.labels.is_empty() {
+            ensure!(
+                !self.breaks.is_empty(),
+                "bucket labels need explicit breaks; use label templates otherwise"
+            );
+            ensure!(
+                self.labels.len() == self.breaks.len() + 1,
+                "bucket needs {} labels for {} breaks, got {}",
+                self.breaks.len() + 1,
+                self.breaks.len(),
+                self.labels.len()
+            );
+        }
+        if let Some(width) = self.width {
+            ensure!(
+                width.is_finite() && width > 0.0,
+                "bucket width must be positive"
+            );
+        }
+        ensure!(
+            self.breaks.len() < MAX_RANGES,
+            "bucket has {} breaks, at most {} allowed",
+            self.breaks.len(),
+            MAX_RANGES - 1
+        );
+        for count in [self.bins, self.quantiles].into_iter().flatten() {
+            ensure!(count >= 1, "bucket count must be at least 1");
+            ensure!(
+                count <= MAX_RANGES,
+                "bucket count {count} is above the maximum of {MAX_RANGES}"
+            );
+        }
+        if let Some(scale) = self.scale {
+            ensure!(
+                scale.is_finite() && scale != 0.0,
+                "bucket scale must be non-zero"
+            );
+        }
+        Ok(())
+    }
+
+    /// `col(name)` as range labels (`String`); nulls stay null. `values`
+    /// is the column, needed for data-dependent ranges.
+    pub fn expr(&self, name: &str, values: &Column) -> PolarsResult<Expr> {
+        let ranges = self.ranges(values)?;
+        let labels: Vec<String> = if self.labels.is_empty() {
+            ranges
+                .bounds
+                .iter()
+                .map(|&(lower, upper)| self.render(lower, upper))
+                .collect()
+        } else {
+            self.labels.clone()
+        };
+        Ok(col(name)
+            .cast(DataType::Float64)
+            .cut(ranges.breaks, Some(labels), true, false)
+            .cast(DataType::String))
+    }
+
+    fn ranges(&self, values: &Column) -> PolarsResult<Ranges> {
+        if !self.breaks.is_empty() {
+            return Ok(Ranges::between(
+                f64::NEG_INFINITY,
+                self.breaks.clone(),
+                f64::INFINITY,
+            ));
+        }
+        let values = values.as_materialized_series().cast(&DataType::Float64)?;
+        let values = values.f64()?;
+        let (Some(min), Some(max)) = (values.min(), values.max()) else {
+            // No values: one range, never used.
+            return Ok(Ranges::between(f64::NEG_INFINITY, vec![], f64::INFINITY));
+        };
+        if let Some(width) = self.width {
+            let first = (min / width).floor();
+            let last = (max / width).floor();
+            let count = last - first + 1.0;
+            if count > MAX_RANGES as f64 {
+                polars_bail!(
+                    InvalidOperation: "bucket width {width} makes {count} ranges between {min} and {max}, \
+                    at most {MAX_RANGES} allowed; use a larger width"
+                );
+            }
+            let (first, last) = (first as i64, last as i64);
+            let breaks = (first + 1..=last).map(|k| k as f64 * width).collect();
+            return Ok(Ranges::between(
+                first as f64 * width,
+                breaks,
+                (last + 1) as f64 * width,
+            ));
+        }
+        let mut breaks: Vec<f64> = match (self.bins, self.quantiles) {
+            (Some(bins), _) => (1..bins)
+                .map(|i| min + (max - min) * i as f64 / bins as f64)
+                .collect(),
+            (_, Some(quantiles)) => (1..quantiles)
+                .map(|i| values.quantile(i as f64 / quantiles as f64, QuantileMethod::Linear))
+                .collect::<PolarsResult<Option<Vec<f64>>>>()?
+                .unwrap_or_default(),
+            _ => vec![],
+        };
+        breaks.dedup();
+        breaks.retain(|b| *b > min && *b < max);
+        Ok(Ranges::between(min, breaks, max))
+    }
+
+    fn render(&self, lower: f64, upper: f64) -> String {
+        let template = match (lower.is_finite(), upper.is_finite()) {
+            (false, true) => self.first_label.as_deref().unwrap_or("< {upper}"),
+            (true, false) => self.last_label.as_deref().unwrap_or(">= {lower}"),
+            (false, false) => "all",
+            (true, true) => self.label.as_deref().unwrap_or("{lower}-{upper}"),
+        };
+        let scale = self.scale.unwrap_or(1.0);
+        template
+            .replace("{lower}", &format_bound(lower / scale))
+            .replace("{upper}", &format_bound(upper / scale))
+    }
+}
+
+impl Ranges {
+    fn between(lower: f64, breaks: Vec<f64>, upper: f64) -> Self {
+        let mut edges = vec![lower];
+        edges.extend(&breaks);
+        edges.push(upper);
+        let bounds = edges.windows(2).map(|w| (w[0], w[1])).collect();
+        Ranges { breaks, bounds }
+    }
+}
+
+/// Whole numbers without decimals, others to at most two.
+fn format_bound(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value:.0}")
+    } else {
+        let text = format!("{value:.2}");
+        match text.trim_end_matches('0').trim_end_matches('.') {
+            "-0" => "0".to_string(),
+            trimmed => trimmed.to_string(),
+        }
+    }
+}
+
+/// Calendar unit a date is truncated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateUnit {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl DateUnit {
+    fn every(self) -> &'static str {
+        match self {
+            DateUnit::Day => "1d",
+            DateUnit::Week => "1w",
+            DateUnit::Month => "1mo",
+            DateUnit::Quarter => "1q",
+            DateUnit::Year => "1y",
+        }
+    }
+
+    /// `col(name)` truncated to the start of its unit, keeping the type.
+    /// The column must be a `Date` or `Datetime`.
+    pub fn expr(self, name: &str, dtype: &DataType) -> PolarsResult<Expr> {
+        polars_ensure!(
+            dtype.is_temporal() && !matches!(dtype, DataType::Duration(_) | DataType::Time),
+            InvalidOperation: "truncate needs a date or timestamp column, `{}` is {}", name, dtype
+        );
+        Ok(col(name).dt().truncate(lit(self.every())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cut(buckets: &Buckets, values: &[Option<f64>]) -> Vec<Option<String>> {
+        let df = df!("v" => values).unwrap();
+        let expr = buckets.expr("v", df.column("v").unwrap()).unwrap();
+        let out = df.lazy().select([expr]).collect().unwrap();
+        out.column("v")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.map(String::from))
+            .collect()
+    }
+
+    fn labels(values: &[&str]) -> Vec<Option<String>> {
+        values.iter().map(|v| Some(v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_breaks_with_templates() {
+        let buckets = Buckets {
+            breaks: vec![50000.0, 75000.0],
+            scale: Some(1000.0),
+            label: Some("${lower}k-${upper}k".into()),
+            first_label: Some("< ${upper}k".into()),
+            last_label: Some("> ${lower}k".into()),
+            ..Buckets::default()
+        };
+        assert_eq!(
+            cut(
+                &buckets,
+                &[Some(49999.0), Some(50000.0), Some(80000.0), None]
+            ),
+            [
+                Some("< $50k".into()),
+                Some("$50k-$75k".into()),
+                Some("> $75k".into()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bounds_round_without_trailing_dot() {
+        assert_eq!(format_bound(2.999), "3");
+        assert_eq!(format_bound(1.001), "1");
+        assert_eq!(format_bound(-0.001), "0");
+        assert_eq!(format_bound(2.5), "2.5");
+        assert_eq!(format_bound(0.125), "0.12");
+        assert_eq!(format_bound(40.0), "40");
+    }
+
+    #[test]
+    fn test_width_bins_and_quantiles() {
+        let ages: Vec<Option<f64>> = [18.0, 29.0, 30.0, 64.5].map(Some).to_vec();
+        let width = Buckets {
+            width: Some(10.0),
+            ..Buckets::default()
+        };
+        assert_eq!(
+            cut(&width, &ages),
+            labels(&["10-20", "20-30", "30-40", "60-70"])
+        );
+
+        let bins = Buckets {
+            bins: Some(2),
+            ..Buckets::default()
+        };
+        assert_eq!(
+            cut(&bins, &[Some(0.0), Some(4.9), Some(5.0), Some(10.0)]),
+            { labels(&["0-5", "0-5", "5-10", "5-10"]) }
+        );
+
+        let quantiles = Buckets {
+            quantiles: Some(4),
+            label: Some("[{lower}, {upper})".into()),
+            ..Buckets::default()
+        };
+        let values: Vec<Option<f64>> = (1..=8).map(|v| Some(v as f64)).collect();
+        let out = cut(&quantiles, &values);
+        assert_eq!(out[0].as_deref(), Some("[1, 2.75)"));
+        assert_eq!(out[7].as_deref(), Some("[6.25, 8)"));
+        let distinct: std::collections::HashSet<_> = out.iter().collect();
+        assert_eq!(distinct.len(), 4);
+    }
+
+    #[test]
+    fn test_invalid_buckets() {
+        let both = Buckets {
+            breaks: vec![1.0],
+            width: Some(5.0),
+            ..Buckets::default()
+        };
+        assert!(both.validate().is_err());
+        assert!(Buckets::default().validate().is_err());
+        assert!(
+            Buckets::with_labels(&[2.0, 1.0], &["a", "b", "c"])
+                .validate()
+                .is_err()
+        );
+        let labels_without_breaks = Buckets {
+            width: Some(5.0),
+            labels: vec!["a".into()],
+            ..Buckets::default()
+        };
+        assert!(labels_without_breaks.validate().is_err());
+        let too_many_bins = Buckets {
+            bins: Some(MAX_RANGES + 1),
+            ..Buckets::default()
+        };
+        assert!(too_many_bins.validate().is_err());
+
+        // Checked against the data, since the range count depends on it
+        let narrow = Buckets {
+            width: Some(0.01),
+            ..Buckets::default()
+        };
+        assert!(narrow.validate().is_ok());
+        let salaries = Column::new("salary".into(), &[30000.0, 180000.0]);
+        let err = narrow.expr("salary", &salaries).unwrap_err().to_string();
+        assert!(err.contains("at most 10000 allowed"), "{err}");
+    }
+
+    #[test]
+    fn test_truncate_dates() {
+        let df = df!("d" => ["2024-02-29", "2023-11-05"]).unwrap();
+        let df = df
+            .lazy()
+            .select([col("d").cast(DataType::Date)])
+            .collect()
+            .unwrap();
+        let dtype = df.column("d").unwrap().dtype().clone();
+        let out = df
+            .lazy()
+            .select([
+                DateUnit::Month.expr("d", &dtype).unwrap().alias("month"),
+                DateUnit::Year.expr("d", &dtype).unwrap().alias("year"),
+            ])
+            .collect()
+            .unwrap();
+        let text = |name: &str| {
+            out.column(name)
+                .unwrap()
+                .cast(&DataType::String)
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(text("month"), ["2024-02-01", "2023-11-01"]);
+        assert_eq!(text("year"), ["2024-01-01", "2023-01-01"]);
+        assert!(DateUnit::Month.expr("s", &DataType::String).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! Tolerant CSV ingest: load the rows that parse, quarantine the rest.
+//!
+//! The strict reader ([`crate::io::read_input`]) fails the whole load on one
+//! malformed line. With [`BadRows::Quarantine`], [`read_csv`] splits the
+//! text into records itself and sets rows aside, with their line number and
+//! the reason, when they have:
+//!
+//! - more or fewer fields than the header (`field_count`),
+//! - a quoted field that never closes (`malformed`),
+//! - a value that does not parse as its column's type (`unparsable`),
+//! - a value that breaks a [`Contract`] rule (`contract`).
+//!
+//! Column types come from the contract where it declares one. Otherwise a
+//! column is numeric when at least 90% of its values are numbers, so a stray
+//! `n/a` is quarantined instead of turning the whole column into text;
+//! declare `type = "string"` to keep such a column as text.
+
+use crate::contract::{Contract, ValueType, article};
+use crate::io::{self, FileFormat};
+use anyhow::{Context, bail, ensure};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Share of numeric values from which a column is read as numbers.
+const NUMERIC_SHARE: f64 = 0.9;
+
+/// What to do with rows that do not fit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BadRows {
+    /// Fail the load, like the strict reader.
+    #[default]
+    Fail,
+    /// Load the rest and return bad rows in the quarantine frame.
+    Quarantine,
+}
+
+impl FromStr for BadRows {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "fail" => Ok(BadRows::Fail),
+            "quarantine" => Ok(BadRows::Quarantine),
+            _ => bail!("unknown bad_rows mode `{name}` (expected fail or quarantine)"),
+        }
+    }
+}
+
+/// Why a row was quarantined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    FieldCount,
+    Malformed,
+    Unparsable,
+    Contract,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::FieldCount => "field_count",
+            Reason::Malformed => "malformed",
+            Reason::Unparsable => "unparsable",
+            Reason::Contract => "contract",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct IngestReport {
+    /// Data rows in the input, not counting the header.
+    pub rows_read: usize,
+    pub rows_loaded: usize,
+    pub rows_quarantined: usize,
+    /// Quarantined rows per [`Reason`].
+    pub reasons: BTreeMap<Reason, usize>,
+}
+
+/// The loaded rows and the quarantined ones. `quarantine` has the columns
+/// `line` (1-based, the header is line 1), `reason`, `message` and `raw`,
+/// the record as it appeared in the input.
+#[derive(Debug, Clone)]
+pub struct Ingested {
+    pub data: DataFrame,
+    pub quarantine: DataFrame,
+    pub report: IngestReport,
+}
+
+/// One CSV record and where it came from.
+struct Record<'a> {
+    line: usize,
+    raw: &'a str,
+    fields: Vec<String>,
+    /// A quoted field runs to the end of the input.
+    unterminated: bool,
+}
+
+struct Quarantined {
+    line: usize,
+    reason: Reason,
+    message: String,
+    raw: String,
+}
+
+/// Split CSV text into records, keeping quoted commas, `""` escapes and
+/// quoted line breaks. Blank lines are skipped.
+fn split_records(text: &str) -> Vec<Record<'_>> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let (mut start, mut start_line, mut line) = (0, 1, 1);
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        match c {
+            '"' if quoted && next == Some('"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            _ if quoted => field.push(c),
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if next == Some('\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                records.push(Record {
+                    line: start_line,
+                    raw: text[start..i].trim_end_matches('\r'),
+                    fields: std::mem::take(&mut fields),
+                    unterminated: false,
+                });
+                line += 1;
+                start = i + 1;
+                start_line = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if start < text.len() {
+        fields.push(field);
+        records.push(Record {
+            line: start_line,
+            raw: &text[start..],
+            fields,
+            unterminated: quoted,
+        });
+    }
+    records.retain(|r| !r.raw.trim().is_empty());
+    records
+}
+
+/// Integer or float if enough values are numbers, boolean if all are
+/// `true`/`false`, text (`None`) otherwise.
+fn infer(text: &StringChunked) -> Option<ValueType> {
+    let values: Vec<&str> = text.into_iter().flatten().collect();
+    if values.is_empty() {
+        return None;
+    }
+    if values
+        .iter()
+        .all(|v| v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false"))
+    {
+        return Some(ValueType::Boolean);
+    }
+    let integers = values.iter().filter(|v| v.parse::<i64>().is_ok()).count();
+    let numbers = values.iter().filter(|v| v.parse::<f64>().is_ok()).count();
+    if (numbers as f64) < NUMERIC_SHARE * values.len() as f64 {
+        None
+    } else if integers == numbers {
+        Some(ValueType::Integer)
+    } else {
+        Some(ValueType::Float)
+    }
+}
+
+/// Move the rows in `problems` (by index into `rows`) to `quarantine`.
+fn set_aside<'a>(
+    df: DataFrame,
+    rows: Vec<Record<'a>>,
+    problems: BTreeMap<usize, Vec<String>>,
+    reason: Reason,
+    quarantine: &mut Vec<Quarantined>,
+) -> PolarsResult<(DataFrame, Vec<Record<'a>>)> {
+    if problems.is_empty() {
+        return Ok((df, rows));
+    }
+    let keep: BooleanChunked = (0..rows.len())
+        .map(|row| !problems.contains_key(&row))
+        .collect();
+    let mut kept = Vec::with_capacity(rows.len() - problems.len());
+    for (row, record) in rows.into_iter().enumerate() {
+        match problems.get(&row) {
+            Some(messages) => quarantine.push(Quarantined {
+                line: record.line,
+                reason,
+                message: messages.join("; "),
+                raw: record.raw.to_string(),
+            }),
+            None => kept.push(record),
+        }
+    }
+    Ok((df.filter(&keep)?, kept))
+}
+
+fn quarantine_frame(quarantine: &[Quarantined]) -> PolarsResult<DataFrame> {
+    df! {
+        "line" => quarantine.iter().map(|q| q.line as u64).collect::<Vec<_>>(),
+        "reason" => quarantine.iter().map(|q| q.reason.as_str()).collect::<Vec<_>>(),
+        "message" => quarantine.iter().map(|q| q.message.as_str()).collect::<Vec<_>>(),
+        "raw" => quarantine.iter().map(|q| q.raw.as_str()).collect::<Vec<_>>(),
+    }
+}
+
+/// Parse CSV text with a header row, quarantining rows that do not fit
+/// instead of failing. Only a broken header fails the load.
+pub fn read_csv(text: &str, contract: Option<&Contract>) -> anyhow::Result<Ingested> {
+    let mut records = split_records(text).into_iter();
+    let Some(header) = records.next() else {
+        bail!("no input: the CSV has no header row");
+    };
+    ensure!(
+        !header.unterminated,
+        "the CSV header has an unterminated quote"
+    );
+    let names = header.fields;
+    let mut seen = HashSet::new();
+    for name in &names {
+        ensure!(
+            seen.insert(name.as_str()),
+            "column `{name}` appears twice in the CSV header"
+        );
+    }
+
+    let mut quarantine = Vec::new();
+    let mut rows = Vec::new();
+    let mut rows_read = 0;
+    for record in records {
+        rows_read += 1;
+        if record.unterminated {
+            quarantine.push(Quarantined {
+                line: record.line,
+                reason: Reason::Malformed,
+                message: "quoted field is never closed".to_string(),
+                raw: record.raw.to_string(),
+            });
+        } else if record.fields.len() != names.len() {
+            quarantine.push(Quarantined {
+                line: record.line,
+                reason: Reason::FieldCount,
+                message: format!(
+                    "expected {} fields, found {}",
+                    names.len(),
+                    record.fields.len()
+                ),
+                raw: record.raw.to_string(),
+            });
+        } else {
+            rows.push(record);
+        }
+    }
+
+    // Parse each column; rows with a value that does not parse go aside
+    let mut problems: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut columns = Vec::with_capacity(names.len());
+    for (index, name) in names.iter().enumerate() {
+        let text: StringChunked = rows
+            .iter()
+            .map(|r| Some(r.fields[index].as_str()).filter(|v| !v.is_empty()))
+            .collect::<StringChunked>()
+            .with_name(name.as_str().into());
+        let declared = contract
+            .and_then(|c| c.columns.iter().find(|c| &c.name == name))
+            .and_then(|c| c.value_type);
+        let value_type = match (declared, infer(&text)) {
+            (Some(ValueType::String), _) => None,
+            // Integers satisfy a float column; keep them as read
+            (Some(ValueType::Float), Some(ValueType::Integer)) => Some(ValueType::Integer),
+            (Some(declared), _) => Some(declared),
+            (None, inferred) => inferred,
+        };
+        let Some(value_type) = value_type else {
+            columns.push(text.into_series().into());
+            continue;
+        };
+        let parsed = value_type.parse(&text)?;
+        let unparsed = text.is_not_null() & parsed.is_null();
+        for (row, bad) in unparsed.into_iter().enumerate() {
+            if bad == Some(true) {
+                problems.entry(row).or_default().push(format!(
+                    "{name}: `{}` is not {}",
+                    text.get(row).unwrap_or_default(),
+                    article(value_type)
+                ));
+            }
+        }
+        columns.push(parsed.into());
+    }
+    let df = DataFrame::new(columns)?;
+    let (df, rows) = set_aside(df, rows, problems, Reason::Unparsable, &mut quarantine)?;
+
+    // Row-level contract violations; column-level ones (a missing column)
+    // are left for the caller's own check.
+    let (df, _) = match contract {
+        Some(contract) => {
+            let mut problems: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+            for violation in contract.violations(&df)? {
+                if let Some(row) = violation.row {
+                    problems
+                        .entry(row)
+                        .or_default()
+                        .push(format!("{}: {}", violation.column, violation.message));
+                }
+            }
+            set_aside(df, rows, problems, Reason::Contract, &mut quarantine)?
+        }
+        None => (df, rows),
+    };
+
+    quarantine.sort_by_key(|q| q.line);
+    let mut reasons = BTreeMap::new();
+    for q in &quarantine {
+        *reasons.entry(q.reason).or_insert(0) += 1;
+    }
+    Ok(Ingested {
+        report: IngestReport {
+            rows_read,
+            rows_loaded: df.height(),
+            rows_quarantined: quarantine.len(),
+            reasons,
+        },
+        quarantine: quarantine_frame(&quarantine)?,
+        data: df,
+    })
+}
+
+/// Load a script's input from pasted CSV text or a file path, like
+/// [`io::read_input`]. With [`BadRows::Quarantine`] CSV input goes through
+/// [`read_csv`]; with [`BadRows::Fail`] the strict reader is used and
+/// `contract` is left to the caller's own [`Contract::check`].
+pub fn read_input(
+    csv_data: Option<&str>,
+    input_path: Option<&str>,
+    contract: Option<&Contract>,
+    bad_rows: BadRows,
+) -> anyhow::Result<Ingested> {
+    if bad_rows == BadRows::Fail {
+        let data = io::read_input(csv_data, input_path)?;
+        return Ok(Ingested {
+            report: IngestReport {
+                rows_read: data.height(),
+                rows_loaded: data.height(),
+                ..IngestReport::default()
+            },
+            quarantine: quarantine_frame(&[])?,
+            data,
+        });
+    }
+    match (csv_data, input_path) {
+        (Some(csv_data), None) => read_csv(csv_data, contract),
+        (None, Some(path)) => {
+            ensure!(
+                FileFormat::from_path(Path::new(path))? == FileFormat::Csv,
+                "bad-row quarantine reads CSV line by line; Parquet and IPC input is \
+                 already typed, check it with a contract instead"
+            );
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read `{path}`"))?;
+            read_csv(&text, contract)
+        }
+        (Some(_), Some(_)) => bail!("pass either CSV data or an input path, not both"),
+        (None, None) => bail!("no input: pass CSV data or an input path"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column<'a>(df: &'a DataFrame, name: &str) -> &'a Series {
+        df.column(name).unwrap().as_materialized_series()
+    }
+
+    #[test]
+    fn test_quarantine_bad_rows() {
+        let mut csv = String::from("id,name,score\r\n");
+        for id in 1..=9 {
+            csv.push_str(&format!("{id},\"Smith, J{id}\",{id}.5\r\n"));
+        }
+        csv.push_str("10,Ann,n/a\n11,Bob\n\n12,\"Multi\nline\",3\n13,Eve,1,extra\n14,\"Open,2");
+
+        let ingested = read_input(Some(&csv), None, None, BadRows::Quarantine).unwrap();
+        let data = &ingested.data;
+        assert_eq!(data.height(), 10);
+        assert_eq!(column(data, "id").dtype(), &DataType::Int64);
+        assert_eq!(column(data, "score").dtype(), &DataType::Float64);
+        assert_eq!(
+            column(data, "name").str().unwrap().get(0),
+            Some("Smith, J1")
+        );
+        assert_eq!(
+            column(data, "name").str().unwrap().get(9),
+            Some("Multi\nline")
+        );
+
+        let quarantine = &ingested.quarantine;
+        let lines: Vec<_> = column(quarantine, "line")
+            .u64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(lines, [11, 12, 16, 17]);
+        let reasons: Vec<_> = column(quarantine, "reason")
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(
+            reasons,
+            ["unparsable", "field_count", "field_count", "malformed"]
+        );
+        let messages = column(quarantine, "message").str().unwrap();
+        assert_eq!(messages.get(0), Some("score: `n/a` is not a number"));
+        assert_eq!(messages.get(2), Some("expected 3 fields, found 4"));
+        assert_eq!(
+            column(quarantine, "raw").str().unwrap().get(1),
+            Some("11,Bob")
+        );
+
+        let report = &ingested.report;
+        assert_eq!(
+            (
+                report.rows_read,
+                report.rows_loaded,
+                report.rows_quarantined
+            ),
+            (14, 10, 4)
+        );
+        assert_eq!(report.reasons[&Reason::FieldCount], 2);
+
+        // The strict reader gives up on the whole input
+        assert!(read_input(Some(&csv), None, None, BadRows::Fail).is_err());
+    }
+
+    #[test]
+    fn test_quarantine_contract_violations() {
+        let csv = "name,age,department,salary
+Alice,28,Engineering,75000
+Bob,thirty,Sales,65000
+,29,Marketing,-5
+Dan,41,Sales,82000";
+        let contract = Contract::employees();
+        let ingested = read_csv(csv, Some(&contract)).unwrap();
+        assert_eq!(ingested.data.height(), 2);
+        assert_eq!(column(&ingested.data, "salary").dtype(), &DataType::Int64);
+        assert!(contract.check(&ingested.data).unwrap().passed);
+
+        let quarantine = &ingested.quarantine;
+        let reasons: Vec<_> = column(quarantine, "reason")
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(reasons, ["unparsable", "contract"]);
+        assert_eq!(
+            column(quarantine, "message").str().unwrap().get(1),
+            Some("name: value is missing; salary: -5 is below the minimum 0")
+        );
+        assert_eq!(
+            serde_json::to_value(&ingested.report).unwrap()["reasons"],
+            serde_json::json!({"unparsable": 1, "contract": 1})
+        );
+
+        let strict = read_input(Some(csv), None, Some(&contract), BadRows::Fail).unwrap();
+        assert_eq!(strict.data.height(), 4);
+        assert_eq!(strict.quarantine.height(), 0);
+        assert!(read_input(None, Some("data.parquet"), None, BadRows::Quarantine).is_err());
+        assert!(read_csv("a,a\n1,2", None).is_err());
+        assert_eq!(
+            "Quarantine".parse::<BadRows>().unwrap(),
+            BadRows::Quarantine
+        );
+        assert!("skip".parse::<BadRows>().is_err());
+    }
+}
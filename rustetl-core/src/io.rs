@@ -0,0 +1,332 @@
+//! Reading and writing frames as CSV, Parquet and Arrow IPC files.
+//!
+//! Parquet and IPC (also known as Feather v2) keep column types, nulls and
+//! timestamps exactly, so other tools (DuckDB, pandas, Spark) can pick up a
+//! pipeline's output without a lossy text round trip. The format follows
+//! the file extension unless [`FileOptions::with_format`] says otherwise:
+//!
+//! | Format | Extensions |
+//! |--------|------------|
+//! | CSV | `.csv` |
+//! | Parquet | `.parquet`, `.pq` |
+//! | Arrow IPC | `.arrow`, `.ipc`, `.feather` |
+
+use anyhow::{Context, bail};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Csv,
+    Parquet,
+    Ipc,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("csv") => Ok(FileFormat::Csv),
+            Some("parquet" | "pq") => Ok(FileFormat::Parquet),
+            Some("arrow" | "ipc" | "feather") => Ok(FileFormat::Ipc),
+            _ => bail!(
+                "cannot tell the format of `{}` from its extension; pass one of csv, parquet, ipc",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Ok(FileFormat::Csv),
+            "parquet" => Ok(FileFormat::Parquet),
+            "ipc" | "arrow" | "feather" => Ok(FileFormat::Ipc),
+            _ => bail!("unknown file format `{name}` (expected csv, parquet or ipc)"),
+        }
+    }
+}
+
+/// Codec for Parquet pages or IPC buffers. IPC supports only `lz4` and
+/// `zstd`; CSV is never compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Lz4,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "uncompressed" | "none" => Ok(Compression::Uncompressed),
+            "snappy" => Ok(Compression::Snappy),
+            "gzip" => Ok(Compression::Gzip),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => bail!(
+                "unknown compression `{name}` (expected uncompressed, snappy, gzip, lz4 or zstd)"
+            ),
+        }
+    }
+}
+
+impl Compression {
+    fn parquet(self) -> ParquetCompression {
+        match self {
+            Compression::Uncompressed => ParquetCompression::Uncompressed,
+            Compression::Snappy => ParquetCompression::Snappy,
+            Compression::Gzip => ParquetCompression::Gzip(None),
+            Compression::Lz4 => ParquetCompression::Lz4Raw,
+            Compression::Zstd => ParquetCompression::Zstd(None),
+        }
+    }
+
+    fn ipc(self) -> anyhow::Result<Option<IpcCompression>> {
+        match self {
+            Compression::Uncompressed => Ok(None),
+            Compression::Lz4 => Ok(Some(IpcCompression::LZ4)),
+            Compression::Zstd => Ok(Some(IpcCompression::ZSTD)),
+            Compression::Snappy | Compression::Gzip => {
+                bail!("Arrow IPC files support only lz4 or zstd compression")
+            }
+        }
+    }
+}
+
+/// How [`write_file`] encodes a frame. Parquet defaults to zstd with
+/// Polars' row group size; IPC defaults to uncompressed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileOptions {
+    pub format: Option<FileFormat>,
+    pub compression: Option<Compression>,
+    /// Rows per Parquet row group. Smaller groups let readers skip more
+    /// data; larger ones compress better.
+    pub row_group_size: Option<usize>,
+}
+
+impl FileOptions {
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn with_row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = Some(rows);
+        self
+    }
+
+    /// Build options from script parameters; the format follows the output
+    /// path's extension.
+    pub fn parse(compression: Option<&str>, row_group_size: Option<usize>) -> anyhow::Result<Self> {
+        Ok(FileOptions {
+            format: None,
+            compression: compression.map(str::parse).transpose()?,
+            row_group_size,
+        })
+    }
+
+    fn format_for(&self, path: &Path) -> anyhow::Result<FileFormat> {
+        match self.format {
+            Some(format) => Ok(format),
+            None => FileFormat::from_path(path),
+        }
+    }
+}
+
+/// What [`write_file`] wrote.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WrittenFile {
+    pub path: String,
+    pub format: FileFormat,
+    pub rows: usize,
+    pub bytes: u64,
+}
+
+/// Read a CSV (with header), Parquet or IPC file, choosing the format from
+/// the extension unless `format` is given.
+pub fn read_file(path: impl AsRef<Path>, format: Option<FileFormat>) -> anyhow::Result<DataFrame> {
+    let path = path.as_ref();
+    let format = match format {
+        Some(format) => format,
+        None => FileFormat::from_path(path)?,
+    };
+    let context = || format!("failed to read `{}`", path.display());
+    let df = match format {
+        FileFormat::Csv => CsvReadOptions::default()
+            .with_has_header(true)
+            .try_into_reader_with_file_path(Some(path.to_path_buf()))
+            .and_then(|reader| reader.finish()),
+        FileFormat::Parquet => {
+            let file = File::open(path).with_context(context)?;
+            ParquetReader::new(file).finish()
+        }
+        FileFormat::Ipc => {
+            let file = File::open(path).with_context(context)?;
+            IpcReader::new(file).finish()
+        }
+    };
+    df.with_context(context)
+}
+
+/// Load a script's input from pasted CSV text or a file path; exactly one
+/// must be given.
+pub fn read_input(csv_data: Option<&str>, input_path: Option<&str>) -> anyhow::Result<DataFrame> {
+    match (csv_data, input_path) {
+        (Some(csv_data), None) => Ok(CsvReadOptions::default()
+            .with_has_header(true)
+            .into_reader_with_file_handle(Cursor::new(csv_data.as_bytes()))
+            .finish()?),
+        (None, Some(path)) => read_file(path, None),
+        (Some(_), Some(_)) => bail!("pass either CSV data or an input path, not both"),
+        (None, None) => bail!("no input: pass CSV data or an input path"),
+    }
+}
+
+/// Write `df` to `path`, creating missing parent directories.
+pub fn write_file(
+    df: &mut DataFrame,
+    path: impl AsRef<Path>,
+    options: &FileOptions,
+) -> anyhow::Result<WrittenFile> {
+    let path = path.as_ref();
+    let format = options.format_for(path)?;
+    if format == FileFormat::Csv && options.compression.is_some() {
+        bail!("CSV output cannot be compressed; use parquet or ipc");
+    }
+    if format != FileFormat::Parquet && options.row_group_size.is_some() {
+        bail!("row groups only apply to Parquet output");
+    }
+    let ipc_compression = match (format, options.compression) {
+        (FileFormat::Ipc, Some(compression)) => compression.ipc()?,
+        _ => None,
+    };
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let context = || format!("failed to write `{}`", path.display());
+    let mut file = File::create(path).with_context(context)?;
+    match format {
+        FileFormat::Csv => CsvWriter::new(&mut file)
+            .include_header(true)
+            .finish(df)
+            .with_context(context)?,
+        FileFormat::Parquet => {
+            ParquetWriter::new(&mut file)
+                .with_compression(options.compression.unwrap_or(Compression::Zstd).parquet())
+                .with_row_group_size(options.row_group_size)
+                .finish(df)
+                .with_context(context)?;
+        }
+        FileFormat::Ipc => IpcWriter::new(&mut file)
+            .with_compression(ipc_compression)
+            .finish(df)
+            .with_context(context)?,
+    }
+    Ok(WrittenFile {
+        path: path.display().to_string(),
+        format,
+        rows: df.height(),
+        bytes: file.metadata()?.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DataFrame {
+        df! {
+            "id" => &[1i64, 2, 3],
+            "name" => &[Some("Alice"), None, Some("Charlie")],
+            "salary" => &[75000.5, 65000.0, 82000.25],
+            "active" => &[true, false, true],
+        }
+        .unwrap()
+        .lazy()
+        .with_column(lit(19_723).cast(DataType::Date).alias("hired"))
+        .collect()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_keeps_types() {
+        let dir = std::env::temp_dir().join(format!("rustetl-io-{}", std::process::id()));
+        let cases = [
+            ("out.parquet", FileOptions::default().with_row_group_size(2)),
+            (
+                "out.pq",
+                FileOptions::default().with_compression(Compression::Snappy),
+            ),
+            (
+                "out.arrow",
+                FileOptions::default().with_compression(Compression::Lz4),
+            ),
+            ("nested/out.feather", FileOptions::default()),
+            (
+                "out.bin",
+                FileOptions::default().with_format(FileFormat::Ipc),
+            ),
+        ];
+        for (name, options) in cases {
+            let path = dir.join(name);
+            let written = write_file(&mut sample(), &path, &options).unwrap();
+            assert_eq!(written.rows, 3);
+            assert!(written.bytes > 0);
+            let back = read_file(&path, options.format).unwrap();
+            assert!(back.equals_missing(&sample()), "{name}");
+        }
+
+        // CSV is text: the date comes back as a string
+        let path = dir.join("out.csv");
+        write_file(&mut sample(), &path, &FileOptions::default()).unwrap();
+        let back = read_file(&path, None).unwrap();
+        assert_eq!(back.shape(), (3, 5));
+        assert_eq!(back.column("id").unwrap().dtype(), &DataType::Int64);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let dir = std::env::temp_dir();
+        let gzip_ipc = FileOptions::default().with_compression(Compression::Gzip);
+        assert!(write_file(&mut sample(), dir.join("x.arrow"), &gzip_ipc).is_err());
+        let csv_groups = FileOptions::default().with_row_group_size(10);
+        assert!(write_file(&mut sample(), dir.join("x.csv"), &csv_groups).is_err());
+        assert!(FileFormat::from_path(Path::new("data.txt")).is_err());
+        assert!(FileOptions::parse(Some("brotli"), None).is_err());
+        assert_eq!(
+            FileOptions::parse(Some("ZSTD"), Some(1000)).unwrap(),
+            FileOptions::default()
+                .with_compression(Compression::Zstd)
+                .with_row_group_size(1000)
+        );
+        assert!(read_input(None, None).is_err());
+        assert!(read_input(Some("a\n1"), Some("data.parquet")).is_err());
+        assert_eq!(read_input(Some("a,b\n1,2"), None).unwrap().shape(), (1, 2));
+    }
+}
@@ -0,0 +1,45 @@
+//! Shared building blocks for the Windmill ETL scripts.
+//!
+//! The scripts in `windmill-scripts/` are thin wrappers around this crate:
+//! they parse Windmill parameters, call into these functions and format the
+//! JSON result. `local-testing` depends on the same crate, so what runs
+//! locally is exactly what ships.
+//!
+//! Database helpers are behind the `postgres` (sync `postgres::Client`) and
+//! `sqlx` (async `sqlx::PgPool`) features, both enabled by default.
+
+pub mod anonymize;
+pub mod contract;
+pub mod customers;
+pub mod dataset;
+pub mod dp;
+pub mod faker;
+pub mod generalize;
+pub mod ingest;
+pub mod io;
+pub mod pii;
+pub mod pipeline;
+pub mod policy;
+pub mod privacy;
+pub mod pseudonym;
+pub mod records;
+pub mod salary;
+pub mod schema;
+pub mod shop;
+pub mod synth;
+pub mod tokenize;
+
+#[cfg(any(feature = "postgres", feature = "sqlx"))]
+pub mod db;
+
+pub use anonymize::anonymize_customers;
+pub use contract::Contract;
+pub use customers::Customer;
+pub use dataset::DatasetPolicy;
+pub use dp::DpQuery;
+pub use pipeline::PipelineResult;
+pub use policy::{Policy, Strategy};
+pub use privacy::PrivacyCheck;
+pub use pseudonym::Pseudonymizer;
+pub use synth::TableSpec;
+pub use tokenize::{TokenKind, tokenize};
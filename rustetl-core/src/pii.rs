@@ -0,0 +1,499 @@
+//! Finding columns that hold personal data.
+//!
+//! [`scan`] looks at each column of a sample two ways: its name (`email`,
+//! `phone_number`, `dob`, ...) and its values (the share of non-null values
+//! that look like an email, phone number, SSN, Luhn-valid card number, IP
+//! address or plausible date of birth). Both feed a confidence per column:
+//!
+//! - values only: `0.9 × match rate`
+//! - name and values: `0.5 + 0.5 × match rate`
+//! - name only, for names and addresses that values cannot confirm: `0.6`,
+//!   or `0.4` for `*_name` columns not about a person (`product_name`,
+//!   `company_name`), below [`DEFAULT_MIN_CONFIDENCE`]
+//!
+//! Dates of birth need a matching name, since most date columns are not
+//! birthdays. [`ScanReport::policy`] turns the findings into a starter
+//! [`Policy`] for review.
+
+use crate::generalize::DateUnit;
+use crate::policy::{ColumnRule, Policy, Strategy};
+use crate::tokenize::TokenKind;
+use chrono::{DateTime, NaiveDate};
+use polars::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::net::IpAddr;
+use std::sync::LazyLock;
+
+/// Rows [`scan`] looks at when the caller does not choose.
+pub const DEFAULT_SAMPLE_SIZE: usize = 1000;
+
+/// Findings below this confidence are left out of [`ScanReport::policy`].
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PiiKind {
+    Email,
+    Phone,
+    Ssn,
+    CreditCard,
+    IpAddress,
+    DateOfBirth,
+    Name,
+    Address,
+}
+
+impl PiiKind {
+    const ALL: [PiiKind; 8] = [
+        PiiKind::Email,
+        PiiKind::Phone,
+        PiiKind::Ssn,
+        PiiKind::CreditCard,
+        PiiKind::IpAddress,
+        PiiKind::DateOfBirth,
+        PiiKind::Name,
+        PiiKind::Address,
+    ];
+
+    /// Column name tokens that suggest this kind.
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            PiiKind::Email => &["email", "mail"],
+            PiiKind::Phone => &["phone", "tel", "telephone", "mobile", "cell", "fax"],
+            PiiKind::Ssn => &["ssn", "socialsecurity"],
+            PiiKind::CreditCard => &["cc", "card", "creditcard", "pan"],
+            PiiKind::IpAddress => &["ip", "ipaddress", "ipv4", "ipv6"],
+            PiiKind::DateOfBirth => &["dob", "birth", "birthday", "birthdate", "born"],
+            PiiKind::Name => &["name", "firstname", "lastname", "fullname", "surname"],
+            PiiKind::Address => &["address", "addr", "street"],
+        }
+    }
+
+    /// Whether values alone can be recognized.
+    fn has_values(self) -> bool {
+        !matches!(self, PiiKind::Name | PiiKind::Address)
+    }
+
+    fn matches(self, value: &str) -> bool {
+        let value = value.trim();
+        match self {
+            PiiKind::Email => EMAIL.is_match(value),
+            PiiKind::Phone => PHONE.is_match(value),
+            PiiKind::Ssn => is_ssn(value),
+            PiiKind::CreditCard => is_card_number(value),
+            PiiKind::IpAddress => value.parse::<IpAddr>().is_ok(),
+            PiiKind::DateOfBirth => is_birth_date(value),
+            PiiKind::Name | PiiKind::Address => false,
+        }
+    }
+
+    /// Suggested rule for a column of this kind.
+    fn strategy(self, dtype: &DataType) -> Strategy {
+        match self {
+            PiiKind::Email => Strategy::Tokenize {
+                kind: TokenKind::Email,
+            },
+            PiiKind::Phone => Strategy::Tokenize {
+                kind: TokenKind::Phone,
+            },
+            PiiKind::Ssn => Strategy::Tokenize {
+                kind: TokenKind::Ssn,
+            },
+            PiiKind::CreditCard => Strategy::Mask {
+                char: '*',
+                keep_last: 4,
+            },
+            PiiKind::IpAddress | PiiKind::Name => Strategy::Hash {
+                prefix: String::new(),
+                suffix: String::new(),
+                length: None,
+            },
+            PiiKind::DateOfBirth if dtype.is_temporal() => Strategy::Truncate {
+                unit: DateUnit::Year,
+            },
+            PiiKind::DateOfBirth | PiiKind::Address => Strategy::Redact {
+                value: "REDACTED".to_string(),
+            },
+        }
+    }
+}
+
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}$").unwrap());
+
+/// North American numbers in the usual layouts, with an optional extension,
+/// or any `+`-prefixed international number.
+static PHONE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:(?:\+?1[\s.-]?)?(?:\(\d{3}\)\s?|\d{3}[\s.-]?)\d{3}[\s.-]?\d{4}(?:\s*(?:x|ext\.?)\s*\d{1,6})?|\+\d{1,3}(?:[\s.-]?\d){6,13})$",
+    )
+    .unwrap()
+});
+
+static SSN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{3})-(\d{2})-(\d{4})$").unwrap());
+
+/// `AAA-GG-SSSS` with the never-issued area 000 and 666, group 00 and
+/// serial 0000 ruled out.
+fn is_ssn(value: &str) -> bool {
+    SSN.captures(value).is_some_and(|parts| {
+        !matches!(&parts[1], "000" | "666") && &parts[2] != "00" && &parts[3] != "0000"
+    })
+}
+
+/// 13 to 19 digits, optionally grouped by spaces or dashes, passing the
+/// Luhn check.
+fn is_card_number(value: &str) -> bool {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == ' ' || c == '-')
+    {
+        return false;
+    }
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// An ISO or US-style date (or the date part of a timestamp) between 1900
+/// and today.
+fn is_birth_date(value: &str) -> bool {
+    let date = value.get(..10).unwrap_or(value);
+    let Some(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%m/%d/%Y"))
+        .ok()
+    else {
+        return false;
+    };
+    let today = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|elapsed| DateTime::from_timestamp(elapsed.as_secs() as i64, 0))
+        .map(|now| now.date_naive());
+    date >= NaiveDate::from_ymd_opt(1900, 1, 1).unwrap() && today.is_none_or(|today| date <= today)
+}
+
+/// Lowercase name tokens, plus the whole name without separators so
+/// `emailAddress` and `e_mail` still match.
+fn name_tokens(name: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        let boundary = !c.is_ascii_alphanumeric() || (c.is_ascii_uppercase() && previous_lower);
+        if boundary && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        if c.is_ascii_alphanumeric() {
+            current.push(c.to_ascii_lowercase());
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens.push(tokens.concat());
+    tokens
+}
+
+fn name_suggests(tokens: &[String], kind: PiiKind) -> bool {
+    let joined = tokens.last().map(String::as_str).unwrap_or_default();
+    kind.keywords().iter().any(|keyword| {
+        tokens.iter().any(|token| token == keyword)
+            // Longer keywords may sit inside a compound name
+            || (keyword.len() >= 5 && joined.contains(keyword))
+    })
+}
+
+/// Words that make a `name` column a person's name (`first_name`,
+/// `customerName`); a bare `name` column counts too.
+const PERSON_NAME_WORDS: [&str; 10] = [
+    "first", "last", "full", "middle", "given", "family", "customer", "user", "person", "contact",
+];
+
+/// Confidence of a column whose name suggests [`PiiKind::Name`].
+fn name_confidence(tokens: &[String]) -> f64 {
+    let Some((joined, words)) = tokens.split_last() else {
+        return 0.0;
+    };
+    let person = words == ["name"]
+        || ["firstname", "lastname", "fullname", "surname"]
+            .iter()
+            .any(|keyword| joined.contains(keyword))
+        || words
+            .iter()
+            .any(|word| PERSON_NAME_WORDS.contains(&word.as_str()));
+    if person { 0.6 } else { 0.4 }
+}
+
+/// What [`scan`] found in one column.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnFinding {
+    pub column: String,
+    pub dtype: String,
+    /// Most likely kind, if any signal was found.
+    pub kind: Option<PiiKind>,
+    pub confidence: f64,
+    pub name_match: bool,
+    /// Share of sampled non-null values that look like `kind`.
+    pub value_match_rate: f64,
+    pub sampled_values: usize,
+    /// Rule [`ScanReport::policy`] uses for this column.
+    pub suggestion: Strategy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScanReport {
+    pub rows_sampled: usize,
+    pub columns: Vec<ColumnFinding>,
+}
+
+impl ScanReport {
+    /// Columns whose confidence reaches `min_confidence`.
+    pub fn pii_columns(&self, min_confidence: f64) -> Vec<&ColumnFinding> {
+        self.columns
+            .iter()
+            .filter(|finding| finding.kind.is_some() && finding.confidence >= min_confidence)
+            .collect()
+    }
+
+    /// A starter policy: the suggested rule for every column found with at
+    /// least `min_confidence`, `keep` for the rest. Review it before use;
+    /// columns that identify people in combination (age, zip code) are not
+    /// detected.
+    pub fn policy(&self, min_confidence: f64) -> Policy {
+        let columns = self
+            .columns
+            .iter()
+            .map(|finding| {
+                let strategy = if finding.kind.is_some() && finding.confidence >= min_confidence {
+                    finding.suggestion.clone()
+                } else {
+                    Strategy::Keep
+                };
+                (finding.column.clone(), ColumnRule::new(strategy))
+            })
+            .collect();
+        Policy {
+            columns,
+            ..Policy::default()
+        }
+    }
+
+    /// [`Self::policy`] as TOML, with each finding as a comment above it.
+    pub fn policy_toml(&self, min_confidence: f64) -> anyhow::Result<String> {
+        let mut text = String::from("# Starter policy from a PII scan; review before use.\n#\n");
+        for finding in &self.columns {
+            let found = match finding.kind {
+                Some(kind) => format!(
+                    "{} (confidence {:.2})",
+                    serde_json::to_value(kind)?.as_str().unwrap_or_default(),
+                    finding.confidence
+                ),
+                None => "no personal data found".to_string(),
+            };
+            text.push_str(&format!("# {}: {found}\n", finding.column));
+        }
+        text.push('\n');
+        text.push_str(&toml::to_string_pretty(&self.policy(min_confidence))?);
+        Ok(text)
+    }
+}
+
+/// Classify every column of the first `sample_size` rows of `df`.
+pub fn scan(df: &DataFrame, sample_size: usize) -> PolarsResult<ScanReport> {
+    let sample = df.head(Some(sample_size));
+    let mut columns = Vec::with_capacity(sample.width());
+    for column in sample.get_columns() {
+        columns.push(scan_column(column)?);
+    }
+    Ok(ScanReport {
+        rows_sampled: sample.height(),
+        columns,
+    })
+}
+
+fn scan_column(column: &Column) -> PolarsResult<ColumnFinding> {
+    let dtype = column.dtype();
+    let values: Vec<String> = match dtype {
+        DataType::String
+        | DataType::Date
+        | DataType::Datetime(_, _)
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt32
+        | DataType::UInt64 => column
+            .cast(&DataType::String)?
+            .str()?
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    let tokens = name_tokens(column.name());
+
+    let mut best: Option<(PiiKind, f64, bool, f64)> = None;
+    for kind in PiiKind::ALL {
+        let name_match = name_suggests(&tokens, kind);
+        let rate = if kind.has_values() && !values.is_empty() {
+            values.iter().filter(|v| kind.matches(v)).count() as f64 / values.len() as f64
+        } else {
+            0.0
+        };
+        let confidence = match (name_match, kind) {
+            (true, PiiKind::Name) => name_confidence(&tokens),
+            (true, PiiKind::Address) => 0.6,
+            (true, _) => 0.5 + 0.5 * rate,
+            (false, PiiKind::DateOfBirth) => 0.0,
+            (false, _) => 0.9 * rate,
+        };
+        if confidence > best.map_or(0.0, |(_, c, _, _)| c) {
+            best = Some((kind, confidence, name_match, rate));
+        }
+    }
+
+    let (kind, confidence, name_match, value_match_rate) = match best {
+        Some((kind, confidence, name_match, rate)) => (Some(kind), confidence, name_match, rate),
+        None => (None, 0.0, false, 0.0),
+    };
+    Ok(ColumnFinding {
+        column: column.name().to_string(),
+        dtype: dtype.to_string(),
+        kind,
+        confidence: (confidence * 100.0).round() / 100.0,
+        name_match,
+        value_match_rate: (value_match_rate * 100.0).round() / 100.0,
+        sampled_values: values.len(),
+        suggestion: kind.map_or(Strategy::Keep, |kind| kind.strategy(dtype)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding<'a>(report: &'a ScanReport, column: &str) -> &'a ColumnFinding {
+        report.columns.iter().find(|f| f.column == column).unwrap()
+    }
+
+    #[test]
+    fn test_value_patterns() {
+        assert!(PiiKind::Email.matches("john.smith@mail.example.com"));
+        assert!(!PiiKind::Email.matches("a1b2c3d4@anonymized"));
+        for phone in [
+            "555-123-4567",
+            "(483) 812-8361 x56717",
+            "1-492-779-1068",
+            "+44 20 7946 0958",
+        ] {
+            assert!(PiiKind::Phone.matches(phone), "{phone}");
+        }
+        assert!(!PiiKind::Phone.matches("***-***-4567"));
+        assert!(PiiKind::Ssn.matches("123-45-6789"));
+        assert!(!PiiKind::Ssn.matches("666-45-6789") && !PiiKind::Ssn.matches("***-**-6789"));
+        assert!(PiiKind::CreditCard.matches("4111 1111 1111 1111"));
+        assert!(!PiiKind::CreditCard.matches("4111 1111 1111 1112"));
+        assert!(PiiKind::IpAddress.matches("192.168.0.1") && PiiKind::IpAddress.matches("::1"));
+        assert!(
+            PiiKind::DateOfBirth.matches("1984-02-29")
+                && PiiKind::DateOfBirth.matches("07/04/1990")
+        );
+        assert!(!PiiKind::DateOfBirth.matches("1850-01-01"));
+        assert_eq!(
+            name_tokens("customerEmail_2"),
+            ["customer", "email", "2", "customeremail2"]
+        );
+    }
+
+    #[test]
+    fn test_scan_classifies_columns() {
+        let df = df! {
+            "id" => &[1, 2, 3, 4],
+            "full_name" => &["Ann Lee", "Bo Chan", "Cy Diaz", "Di Eno"],
+            "product_name" => &["Lamp", "Desk", "Chair", "Shelf"],
+            "contact" => &["ann@example.com", "bo@example.org", "cy@example.net", "not an email"],
+            "phone" => &[Some("555-123-4567"), Some("555.987.6543"), None, Some("(555) 222-3333")],
+            "ssn" => &["***-**-6789", "***-**-1234", "***-**-5555", "***-**-0001"],
+            "card" => &["4111111111111111", "5500 0000 0000 0004", "340000000000009", "6011000000000004"],
+            "last_login_ip" => &["10.0.0.1", "10.0.0.2", "192.168.1.20", "::1"],
+            "dob" => &["1980-01-31", "1975-12-01", "2001-06-15", "1990-02-28"],
+            "ordered_on" => &["2024-01-31", "2024-12-01", "2024-06-15", "2024-02-28"],
+            "amount" => &[9.5, 12.0, 3.25, 8.0],
+        }
+        .unwrap();
+        let report = scan(&df, 100).unwrap();
+        assert_eq!(report.rows_sampled, 4);
+
+        let expect = [
+            ("full_name", PiiKind::Name, 0.6),
+            ("product_name", PiiKind::Name, 0.4),
+            ("contact", PiiKind::Email, 0.68),
+            ("phone", PiiKind::Phone, 1.0),
+            ("ssn", PiiKind::Ssn, 0.5),
+            ("card", PiiKind::CreditCard, 1.0),
+            ("last_login_ip", PiiKind::IpAddress, 1.0),
+            ("dob", PiiKind::DateOfBirth, 1.0),
+        ];
+        for (column, kind, confidence) in expect {
+            let found = finding(&report, column);
+            assert_eq!(found.kind, Some(kind), "{column}");
+            assert_eq!(found.confidence, confidence, "{column}");
+        }
+        for column in ["id", "ordered_on", "amount"] {
+            assert_eq!(finding(&report, column).kind, None, "{column}");
+        }
+        assert_eq!(finding(&report, "phone").sampled_values, 3);
+        assert_eq!(report.pii_columns(0.7).len(), 4);
+        let default: Vec<_> = report
+            .pii_columns(DEFAULT_MIN_CONFIDENCE)
+            .iter()
+            .map(|f| f.column.as_str())
+            .collect();
+        assert!(default.contains(&"full_name") && !default.contains(&"product_name"));
+        assert_eq!(name_confidence(&name_tokens("name")), 0.6);
+        assert_eq!(name_confidence(&name_tokens("customerName")), 0.6);
+        assert_eq!(name_confidence(&name_tokens("company_name")), 0.4);
+    }
+
+    #[test]
+    fn test_starter_policy() {
+        let df = df! {
+            "id" => &[1, 2],
+            "email" => &["ann@example.com", "bo@example.org"],
+            "address" => &["1 Main St", "2 High St"],
+        }
+        .unwrap();
+        let report = scan(&df, DEFAULT_SAMPLE_SIZE).unwrap();
+        let text = report.policy_toml(DEFAULT_MIN_CONFIDENCE).unwrap();
+        assert!(text.contains("# email: email (confidence 1.00)"));
+        assert!(text.contains("# id: no personal data found"));
+
+        let policy = Policy::parse(&text).unwrap();
+        assert_eq!(policy, report.policy(DEFAULT_MIN_CONFIDENCE));
+        assert_eq!(policy.columns["id"].strategy, Strategy::Keep);
+        assert_eq!(
+            policy.columns["email"].strategy,
+            Strategy::Tokenize {
+                kind: TokenKind::Email
+            }
+        );
+        // Address is only suggested by its name
+        assert_eq!(
+            report.policy(0.7).columns["address"].strategy,
+            Strategy::Keep
+        );
+    }
+}
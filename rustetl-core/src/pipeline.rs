@@ -0,0 +1,239 @@
+//! The result every Windmill script returns.
+//!
+//! Flows and dashboards read the same fields from every script: `status`,
+//! `rows_in`/`rows_out`, the output `schema`, time spent per stage,
+//! `warnings`, `errors` and the `artifacts` (tables, files) the run
+//! produced. Anything specific to one script, like a privacy report, goes
+//! in `details`.
+//!
+//! Stages are timed like laps: [`PipelineResult::stage`] ends the running
+//! stage and starts the next, so it works the same in sync and async code.
+//! Entering a stage again (e.g. once per chunk) adds to its time.
+//!
+//! A script that stops early on a problem the caller should see, like a
+//! missing source table, returns a result with `status: "error"` from
+//! [`PipelineResult::fail`]; unexpected failures still return `Err` and fail
+//! the Windmill job.
+
+use crate::io::{FileFormat, WrittenFile};
+use crate::records::{self, ColumnSchema, FrameJson};
+use polars::prelude::DataFrame;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Success,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub name: String,
+    pub duration_ms: f64,
+}
+
+/// Something a run left behind for the next step.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Artifact {
+    Table {
+        name: String,
+        rows: usize,
+    },
+    File {
+        path: String,
+        format: FileFormat,
+        rows: usize,
+        bytes: u64,
+    },
+}
+
+impl Artifact {
+    pub fn table(name: &str, rows: usize) -> Self {
+        Artifact::Table {
+            name: name.to_string(),
+            rows,
+        }
+    }
+}
+
+impl From<WrittenFile> for Artifact {
+    fn from(file: WrittenFile) -> Self {
+        Artifact::File {
+            path: file.path,
+            format: file.format,
+            rows: file.rows,
+            bytes: file.bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineResult {
+    /// Script name, e.g. `salary_calculator`.
+    pub pipeline: String,
+    pub status: Status,
+    pub rows_in: usize,
+    pub rows_out: usize,
+    pub schema: Vec<ColumnSchema>,
+    pub stages: Vec<StageTiming>,
+    pub duration_ms: f64,
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+    pub artifacts: Vec<Artifact>,
+    /// Output rows for the next flow step, if the script returns them.
+    pub data: Option<FrameJson>,
+    pub details: Map<String, Value>,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    running: Option<(usize, Instant)>,
+}
+
+impl PipelineResult {
+    pub fn new(pipeline: &str) -> Self {
+        PipelineResult {
+            pipeline: pipeline.to_string(),
+            status: Status::Success,
+            rows_in: 0,
+            rows_out: 0,
+            schema: Vec::new(),
+            stages: Vec::new(),
+            duration_ms: 0.0,
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            artifacts: Vec::new(),
+            data: None,
+            details: Map::new(),
+            started: Instant::now(),
+            running: None,
+        }
+    }
+
+    /// End the running stage, if any, and start timing `name`.
+    pub fn stage(&mut self, name: &str) {
+        self.end_stage();
+        let index = match self.stages.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                self.stages.push(StageTiming {
+                    name: name.to_string(),
+                    duration_ms: 0.0,
+                });
+                self.stages.len() - 1
+            }
+        };
+        self.running = Some((index, Instant::now()));
+    }
+
+    fn end_stage(&mut self) {
+        if let Some((index, started)) = self.running.take() {
+            self.stages[index].duration_ms += started.elapsed().as_secs_f64() * 1000.0;
+        }
+    }
+
+    /// Count and schema of the output, without returning its rows.
+    pub fn output(&mut self, df: &DataFrame) {
+        self.rows_out = df.height();
+        self.schema = records::schema(df);
+    }
+
+    /// [`Self::output`], plus the first rows as records in `data`.
+    pub fn output_with_rows(&mut self, df: &DataFrame) {
+        self.output(df);
+        self.data = Some(records::records(df));
+    }
+
+    pub fn warn(&mut self, warning: impl Into<String>) {
+        self.warnings.push(warning.into());
+    }
+
+    /// Record an error and mark the run failed.
+    pub fn fail(&mut self, error: impl Into<String>) {
+        self.errors.push(error.into());
+        self.status = Status::Error;
+    }
+
+    pub fn artifact(&mut self, artifact: impl Into<Artifact>) {
+        self.artifacts.push(artifact.into());
+    }
+
+    pub fn detail(&mut self, key: &str, value: impl Serialize) -> anyhow::Result<()> {
+        self.details
+            .insert(key.to_string(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    /// End the running stage and stamp the total duration.
+    pub fn finish(mut self) -> Self {
+        self.end_stage();
+        self.duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stages_accumulate() {
+        let mut result = PipelineResult::new("test");
+        for _ in 0..2 {
+            result.stage("read");
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            result.stage("write");
+        }
+        let result = result.finish();
+        let names: Vec<_> = result.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["read", "write"]);
+        assert!(result.stages[0].duration_ms >= 4.0);
+        assert!(result.duration_ms >= result.stages[0].duration_ms);
+    }
+
+    #[test]
+    fn test_serialized_contract() {
+        let df = df! { "id" => &[1i64, 2], "name" => &["a", "b"] }.unwrap();
+        let mut result = PipelineResult::new("export");
+        result.rows_in = 3;
+        result.output_with_rows(&df);
+        result.warn("1 row dropped");
+        result.artifact(Artifact::table("people", 2));
+        result.detail("mode", "full").unwrap();
+        result.fail("target is read-only");
+
+        let value = serde_json::to_value(result.finish()).unwrap();
+        let keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(
+            keys,
+            [
+                "pipeline",
+                "status",
+                "rows_in",
+                "rows_out",
+                "schema",
+                "stages",
+                "duration_ms",
+                "warnings",
+                "errors",
+                "artifacts",
+                "data",
+                "details"
+            ]
+        );
+        assert_eq!(value["status"], "error");
+        assert_eq!(value["rows_out"], 2);
+        assert_eq!(value["schema"][1], json!({"name": "name", "dtype": "str"}));
+        assert_eq!(
+            value["artifacts"],
+            json!([{"kind": "table", "name": "people", "rows": 2}])
+        );
+        assert_eq!(value["data"]["data"][0], json!({"id": 1, "name": "a"}));
+        assert_eq!(value["details"], json!({"mode": "full"}));
+    }
+}
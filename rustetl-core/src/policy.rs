@@ -0,0 +1,611 @@
+//! Declarative column-level anonymization policies.
+//!
+//! A [`Policy`] maps column names to a [`Strategy`] and is applied to any
+//! `DataFrame` with [`Policy::apply`]. Policies are written in TOML or JSON
+//! so Windmill scripts can take them as a parameter:
+//!
+//! ```toml
+//! [columns.id]
+//! strategy = "keep"
+//!
+//! [columns.email]
+//! strategy = "hash"
+//! suffix = "@anonymized.local"
+//! rename = "email_hash"
+//!
+//! [columns.salary]
+//! strategy = "bucket"
+//! breaks = [50000, 100000]
+//! labels = ["low", "mid", "high"]
+//! ```
+//!
+//! Columns not listed in the policy are dropped unless `unlisted = "keep"`.
+//! Policies that hash or tokenize anything also get a `pseudonym_key_id`
+//! column (see `key_id_column`) recording which key produced the output.
+
+use crate::faker::FakeKind;
+use crate::generalize::{Buckets, DateUnit};
+use crate::pseudonym::Pseudonymizer;
+use crate::tokenize::{TokenKind, tokenize};
+use anyhow::{Context, bail};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What happens to columns the policy does not mention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unlisted {
+    #[default]
+    Drop,
+    Keep,
+}
+
+/// How a single column is anonymized. Nulls stay null for every strategy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Strategy {
+    /// Pass the value through unchanged.
+    Keep,
+    /// Replace with `prefix + HMAC pseudonym + suffix`. `length` overrides
+    /// the pseudonymizer's length for this column.
+    Hash {
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        suffix: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        length: Option<usize>,
+    },
+    /// Replace letters and digits with `char`, keeping separators and the
+    /// last `keep_last` characters.
+    Mask {
+        #[serde(default = "default_mask_char")]
+        char: char,
+        #[serde(default)]
+        keep_last: usize,
+    },
+    /// Replace with a constant.
+    Redact {
+        #[serde(default = "default_redacted")]
+        value: String,
+    },
+    /// Generalize a numeric value to a range label (see [`Buckets`]).
+    Bucket(Buckets),
+    /// Generalize a date or timestamp to the start of its `unit`.
+    Truncate { unit: DateUnit },
+    /// Replace with null.
+    Null,
+    /// Replace with a freshly generated fake value.
+    Fake { kind: FakeKind },
+    /// Replace with a keyed token of the same shape (see [`crate::tokenize`]).
+    Tokenize {
+        #[serde(default)]
+        kind: TokenKind,
+    },
+}
+
+fn default_mask_char() -> char {
+    '*'
+}
+
+fn default_redacted() -> String {
+    "REDACTED".to_string()
+}
+
+/// Strategy for one column, optionally written under a new name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnRule {
+    #[serde(flatten)]
+    pub strategy: Strategy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+}
+
+impl ColumnRule {
+    pub fn new(strategy: Strategy) -> Self {
+        ColumnRule {
+            strategy,
+            rename: None,
+        }
+    }
+
+    pub fn renamed(strategy: Strategy, rename: &str) -> Self {
+        ColumnRule {
+            strategy,
+            rename: Some(rename.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub unlisted: Unlisted,
+    /// Output column holding the pseudonym key id.
+    #[serde(default = "default_key_id_column")]
+    pub key_id_column: String,
+    #[serde(default)]
+    pub columns: BTreeMap<String, ColumnRule>,
+}
+
+fn default_key_id_column() -> String {
+    "pseudonym_key_id".to_string()
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            unlisted: Unlisted::default(),
+            key_id_column: default_key_id_column(),
+            columns: BTreeMap::new(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let policy: Policy = toml::from_str(text).context("invalid TOML policy")?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let policy: Policy = serde_json::from_str(text).context("invalid JSON policy")?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Parse a policy given as a Windmill parameter: JSON if it starts with
+    /// `{`, TOML otherwise.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, rule) in &self.columns {
+            if let Strategy::Bucket(buckets) = &rule.strategy
+                && let Err(err) = buckets.validate()
+            {
+                bail!("column `{name}`: {err}");
+            }
+            if let Strategy::Hash {
+                length: Some(length),
+                ..
+            } = rule.strategy
+                && !(1..=crate::pseudonym::MAX_LENGTH).contains(&length)
+            {
+                bail!("column `{name}`: hash length must be between 1 and 64, got {length}");
+            }
+        }
+        Ok(())
+    }
+
+    /// The policy behind `customers` → `customers_anonymized`.
+    pub fn customers() -> Self {
+        use crate::anonymize::{
+            AGE_BREAKS, AGE_LABELS, MASKED_PHONE, MASKED_SSN, REDACTED, SALARY_BREAKS,
+            SALARY_LABELS,
+        };
+
+        let redact = |value: &str| Strategy::Redact {
+            value: value.to_string(),
+        };
+        let columns = BTreeMap::from([
+            ("id".to_string(), ColumnRule::new(Strategy::Keep)),
+            (
+                "name".to_string(),
+                ColumnRule::renamed(
+                    Strategy::Hash {
+                        prefix: "Customer_".to_string(),
+                        suffix: String::new(),
+                        length: None,
+                    },
+                    "name_hash",
+                ),
+            ),
+            (
+                "email".to_string(),
+                ColumnRule::renamed(
+                    Strategy::Hash {
+                        prefix: String::new(),
+                        suffix: "@anonymized.local".to_string(),
+                        length: None,
+                    },
+                    "email_hash",
+                ),
+            ),
+            ("phone".to_string(), ColumnRule::new(redact(MASKED_PHONE))),
+            ("address".to_string(), ColumnRule::new(redact(REDACTED))),
+            (
+                "age".to_string(),
+                ColumnRule::new(Strategy::Bucket(Buckets::with_labels(
+                    &AGE_BREAKS,
+                    &AGE_LABELS,
+                ))),
+            ),
+            (
+                "salary".to_string(),
+                ColumnRule::renamed(
+                    Strategy::Bucket(Buckets::with_labels(&SALARY_BREAKS, &SALARY_LABELS)),
+                    "salary_bucket",
+                ),
+            ),
+            ("ssn".to_string(), ColumnRule::new(redact(MASKED_SSN))),
+        ]);
+        Policy {
+            columns,
+            ..Policy::default()
+        }
+    }
+
+    /// Columns passed through with [`Strategy::Keep`].
+    pub fn kept_columns(&self) -> Vec<&str> {
+        self.columns
+            .iter()
+            .filter(|(_, rule)| rule.strategy == Strategy::Keep)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Whether any column depends on the pseudonym key.
+    pub fn uses_key(&self) -> bool {
+        self.columns.values().any(|rule| {
+            matches!(
+                rule.strategy,
+                Strategy::Hash { .. } | Strategy::Tokenize { .. }
+            )
+        })
+    }
+
+    /// Apply the policy, hashing with `pseudonymizer`. Output columns keep
+    /// the input order; every column named in the policy must exist in `df`.
+    pub fn apply(&self, df: &DataFrame, pseudonymizer: &Pseudonymizer) -> PolarsResult<DataFrame> {
+        for name in self.columns.keys() {
+            df.column(name)?;
+        }
+
+        let mut exprs = Vec::with_capacity(df.width());
+        for column in df.get_columns() {
+            let name = column.name().as_str();
+            match self.columns.get(name) {
+                Some(rule) => {
+                    let alias = rule.rename.as_deref().unwrap_or(name);
+                    let expr = strategy_expr(column, &rule.strategy, pseudonymizer)?;
+                    exprs.push(expr.alias(alias));
+                }
+                None if self.unlisted == Unlisted::Keep => exprs.push(col(name)),
+                None => {}
+            }
+        }
+
+        if self.uses_key() {
+            exprs.push(lit(pseudonymizer.key_id().to_string()).alias(self.key_id_column.as_str()));
+        }
+
+        df.clone().lazy().select(exprs).collect()
+    }
+}
+
+fn strategy_expr(
+    column: &Column,
+    strategy: &Strategy,
+    pseudonymizer: &Pseudonymizer,
+) -> PolarsResult<Expr> {
+    let (name, dtype) = (column.name().as_str(), column.dtype());
+    let c = col(name);
+    Ok(match strategy {
+        Strategy::Keep => c,
+        Strategy::Null => lit(Null {}).cast(dtype.clone()),
+        Strategy::Redact { value } => when(c.clone().is_null())
+            .then(lit(Null {}).cast(DataType::String))
+            .otherwise(lit(value.clone())),
+        Strategy::Hash {
+            prefix,
+            suffix,
+            length,
+        } => {
+            let (prefix, suffix) = (prefix.clone(), suffix.clone());
+            let length = length.unwrap_or(pseudonymizer.length());
+            let pseudonymizer = pseudonymizer.clone();
+            map_strings(c, move |v| {
+                format!(
+                    "{prefix}{}{suffix}",
+                    pseudonymizer.pseudonymize_to(v, length)
+                )
+            })
+        }
+        Strategy::Mask { char, keep_last } => {
+            let (char, keep_last) = (*char, *keep_last);
+            map_strings(c, move |v| mask(v, char, keep_last))
+        }
+        Strategy::Fake { kind } => {
+            let kind = *kind;
+            map_strings(c, move |_| kind.generate())
+        }
+        Strategy::Tokenize { kind } => {
+            let kind = *kind;
+            let pseudonymizer = pseudonymizer.clone();
+            map_strings(c, move |v| tokenize(&pseudonymizer, v, kind))
+        }
+        Strategy::Bucket(buckets) => buckets.expr(name, column)?,
+        Strategy::Truncate { unit } => unit.expr(name, dtype)?,
+    })
+}
+
+/// Cast to string and map every non-null value through `f`.
+fn map_strings(expr: Expr, f: impl Fn(&str) -> String + Send + Sync + 'static) -> Expr {
+    expr.cast(DataType::String).map(
+        move |column| {
+            let out: StringChunked = column.str()?.into_iter().map(|v| v.map(&f)).collect();
+            Ok(Some(out.with_name(column.name().clone()).into_column()))
+        },
+        GetOutput::from_type(DataType::String),
+    )
+}
+
+fn mask(value: &str, mask_char: char, keep_last: usize) -> String {
+    let total = value.chars().count();
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, ch)| {
+            if ch.is_alphanumeric() && i + keep_last < total {
+                mask_char
+            } else {
+                ch
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudonymizer() -> Pseudonymizer {
+        Pseudonymizer::new(b"policy-test-key-0000", Some("test")).unwrap()
+    }
+
+    fn sample() -> DataFrame {
+        df! {
+            "id" => &[1, 2, 3],
+            "email" => &[Some("a@example.com"), Some("b@example.com"), None],
+            "phone" => &["555-123-4567", "(555) 987-6543", "555.000.1111"],
+            "salary" => &[Some(40000), Some(80000), None],
+            "notes" => &["x", "y", "z"],
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_toml_and_json() {
+        let toml = r#"
+            unlisted = "keep"
+
+            [columns.email]
+            strategy = "hash"
+            suffix = "@anon"
+            rename = "email_hash"
+
+            [columns.phone]
+            strategy = "mask"
+            keep_last = 4
+        "#;
+        let from_toml = Policy::parse(toml).unwrap();
+        let json = serde_json::to_string(&from_toml).unwrap();
+        assert_eq!(Policy::parse(&json).unwrap(), from_toml);
+        assert_eq!(from_toml.unlisted, Unlisted::Keep);
+        assert_eq!(
+            from_toml.columns["phone"].strategy,
+            Strategy::Mask {
+                char: '*',
+                keep_last: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_bucket_labels_validated() {
+        let err = Policy::parse(
+            r#"{"columns": {"salary": {"strategy": "bucket", "breaks": [1, 2], "labels": ["a", "b"]}}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("3 labels"));
+    }
+
+    #[test]
+    fn test_apply_strategies() {
+        let policy = Policy::parse(
+            r#"
+            [columns.id]
+            strategy = "keep"
+
+            [columns.email]
+            strategy = "hash"
+            prefix = "u_"
+
+            [columns.phone]
+            strategy = "mask"
+            keep_last = 2
+
+            [columns.salary]
+            strategy = "bucket"
+            breaks = [50000]
+            labels = ["low", "high"]
+            rename = "salary_bucket"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.kept_columns(), ["id"]);
+        let out = policy.apply(&sample(), &pseudonymizer()).unwrap();
+        assert_eq!(
+            out.get_column_names_str(),
+            ["id", "email", "phone", "salary_bucket", "pseudonym_key_id"]
+        );
+
+        let emails = out.column("email").unwrap().str().unwrap();
+        assert_eq!(
+            emails.get(0),
+            Some(format!("u_{}", pseudonymizer().pseudonymize("a@example.com")).as_str())
+        );
+        assert_eq!(emails.get(2), None);
+
+        let phones = out.column("phone").unwrap().str().unwrap();
+        assert_eq!(phones.get(0), Some("***-***-**67"));
+        assert_eq!(phones.get(1), Some("(***) ***-**43"));
+
+        let buckets = out.column("salary_bucket").unwrap().str().unwrap();
+        assert_eq!(
+            buckets.into_iter().collect::<Vec<_>>(),
+            [Some("low"), Some("high"), None]
+        );
+
+        let key_ids = out.column("pseudonym_key_id").unwrap().str().unwrap();
+        assert_eq!(key_ids.get(2), Some("test"));
+    }
+
+    #[test]
+    fn test_hash_length_override() {
+        let policy = Policy::parse(
+            r#"{"columns": {"email": {"strategy": "hash", "length": 32}}, "key_id_column": "kid"}"#,
+        )
+        .unwrap();
+        let out = policy.apply(&sample(), &pseudonymizer()).unwrap();
+        assert_eq!(out.get_column_names_str(), ["email", "kid"]);
+        let emails = out.column("email").unwrap().str().unwrap();
+        assert_eq!(emails.get(0).unwrap().len(), 32);
+
+        assert!(
+            Policy::parse(r#"{"columns": {"email": {"strategy": "hash", "length": 65}}}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_null_redact_and_fake() {
+        let policy = Policy::parse(
+            r#"
+            unlisted = "keep"
+            [columns.id]
+            strategy = "null"
+            [columns.email]
+            strategy = "redact"
+            [columns.phone]
+            strategy = "fake"
+            kind = "phone"
+            "#,
+        )
+        .unwrap();
+
+        let out = policy.apply(&sample(), &pseudonymizer()).unwrap();
+        assert_eq!(out.width(), 5);
+        assert_eq!(out.column("id").unwrap().null_count(), 3);
+        assert_eq!(out.column("id").unwrap().dtype(), &DataType::Int32);
+        let emails = out.column("email").unwrap().str().unwrap();
+        assert_eq!(emails.get(0), Some("REDACTED"));
+        assert_eq!(emails.get(2), None);
+        assert_ne!(
+            out.column("phone").unwrap().str().unwrap().get(0),
+            Some("555-123-4567")
+        );
+    }
+
+    #[test]
+    fn test_generalize_strategies() {
+        let policy = Policy::parse(
+            r#"
+            [columns.age]
+            strategy = "bucket"
+            width = 10
+            label = "{lower}s"
+
+            [columns.joined]
+            strategy = "truncate"
+            unit = "year"
+            "#,
+        )
+        .unwrap();
+        let df = df! {
+            "age" => &[Some(34), None, Some(71)],
+            "joined" => &["2024-05-17", "2021-01-01", "2019-12-31"],
+        }
+        .unwrap()
+        .lazy()
+        .with_column(col("joined").cast(DataType::Date))
+        .collect()
+        .unwrap();
+        let out = policy.apply(&df, &pseudonymizer()).unwrap();
+        let ages = out.column("age").unwrap().str().unwrap();
+        assert_eq!(
+            ages.into_iter().collect::<Vec<_>>(),
+            [Some("30s"), None, Some("70s")]
+        );
+        let years = out
+            .column("joined")
+            .unwrap()
+            .cast(&DataType::String)
+            .unwrap();
+        assert_eq!(years.str().unwrap().get(2), Some("2019-01-01"));
+
+        let text =
+            Policy::parse(r#"{"columns": {"name": {"strategy": "truncate", "unit": "month"}}}"#);
+        let strings = df!("name" => ["a"]).unwrap();
+        assert!(text.unwrap().apply(&strings, &pseudonymizer()).is_err());
+    }
+
+    #[test]
+    fn test_bundled_customers_policy_matches_builtin() {
+        let text = include_str!("../../windmill-scripts/04-database/policies/customers.toml");
+        assert_eq!(Policy::parse(text).unwrap(), Policy::customers());
+    }
+
+    #[test]
+    fn test_tokenize_keeps_format() {
+        let policy = Policy::parse(
+            r#"
+            [columns.phone]
+            strategy = "tokenize"
+            kind = "phone"
+            [columns.notes]
+            strategy = "tokenize"
+            "#,
+        )
+        .unwrap();
+        let out = policy.apply(&sample(), &pseudonymizer()).unwrap();
+        assert_eq!(
+            out.get_column_names_str(),
+            ["phone", "notes", "pseudonym_key_id"]
+        );
+        let phones = out.column("phone").unwrap().str().unwrap();
+        assert_eq!(
+            phones.get(1),
+            Some(tokenize(&pseudonymizer(), "(555) 987-6543", TokenKind::Phone).as_str())
+        );
+        assert_eq!(phones.get(0).unwrap().len(), "555-123-4567".len());
+    }
+
+    #[test]
+    fn test_bundled_tokenized_policy_parses() {
+        let text =
+            include_str!("../../windmill-scripts/04-database/policies/customers_tokenized.toml");
+        let policy = Policy::parse(text).unwrap();
+        assert!(policy.uses_key());
+        assert_eq!(
+            policy.columns["ssn"].strategy,
+            Strategy::Tokenize {
+                kind: TokenKind::Ssn
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_column_is_an_error() {
+        let policy = Policy::parse("[columns.ssn]\nstrategy = \"null\"").unwrap();
+        assert!(policy.apply(&sample(), &pseudonymizer()).is_err());
+    }
+}
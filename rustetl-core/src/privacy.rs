@@ -0,0 +1,344 @@
+//! Measuring k-anonymity and l-diversity of anonymized output.
+//!
+//! Quasi-identifiers are columns that are harmless alone but identify people
+//! in combination, like an age range, a salary bucket and a city. Rows that
+//! share every quasi-identifier value form an equivalence class:
+//!
+//! - **k-anonymity**: every class has at least `k` rows, so each row hides
+//!   among `k - 1` others.
+//! - **l-diversity**: every class has at least `l` distinct non-null values
+//!   in a sensitive column, so membership alone does not reveal it.
+//!
+//! [`PrivacyCheck::measure`] computes both with one group-by and lists the
+//! classes that fall short; [`PrivacyCheck::suppress`] drops their rows.
+
+use crate::schema::quote_ident;
+use anyhow::bail;
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Violating classes listed in a [`PrivacyReport`]; the counts cover all.
+pub const MAX_REPORTED_VIOLATIONS: usize = 100;
+
+/// Thresholds a frame must meet before it is shared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivacyCheck {
+    pub quasi_identifiers: Vec<String>,
+    pub k: usize,
+    pub sensitive: Option<String>,
+    pub l: usize,
+}
+
+impl PrivacyCheck {
+    /// k-anonymity only; add l-diversity with [`Self::with_l_diversity`].
+    pub fn new(quasi_identifiers: &[&str], k: usize) -> Self {
+        PrivacyCheck {
+            quasi_identifiers: quasi_identifiers.iter().map(|c| c.to_string()).collect(),
+            k,
+            sensitive: None,
+            l: 1,
+        }
+    }
+
+    pub fn with_l_diversity(mut self, sensitive: &str, l: usize) -> Self {
+        self.sensitive = Some(sensitive.to_string());
+        self.l = l;
+        self
+    }
+
+    /// The check for `customers_anonymized`: every age range and salary
+    /// bucket combination holds at least 5 customers.
+    pub fn customers() -> Self {
+        PrivacyCheck::new(&["age", "salary_bucket"], 5)
+    }
+
+    /// Build a check from script parameters. Quasi-identifiers and `k`
+    /// default to [`Self::customers`]; `l` defaults to 2 once a sensitive
+    /// column is given.
+    pub fn resolve(
+        quasi_identifiers: Option<Vec<String>>,
+        k: Option<usize>,
+        sensitive: Option<String>,
+        l: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let mut check = PrivacyCheck::customers();
+        if let Some(columns) = quasi_identifiers {
+            check.quasi_identifiers = columns;
+        }
+        check.k = k.unwrap_or(check.k);
+        if let Some(sensitive) = sensitive {
+            check = check.with_l_diversity(&sensitive, l.unwrap_or(2));
+        } else if l.is_some() {
+            bail!("l-diversity needs a sensitive column");
+        }
+        check.validate()?;
+        Ok(check)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.quasi_identifiers.is_empty() {
+            bail!("at least one quasi-identifier column is required");
+        }
+        if self.k == 0 || self.l == 0 {
+            bail!("k and l must be at least 1");
+        }
+        if let Some(sensitive) = &self.sensitive
+            && self.quasi_identifiers.contains(sensitive)
+        {
+            bail!("sensitive column `{sensitive}` cannot also be a quasi-identifier");
+        }
+        Ok(())
+    }
+
+    /// Columns the check reads, plus `key` (e.g. `id`) to find rows by.
+    pub fn select(&self, table: &str, key: &str) -> String {
+        let columns: Vec<String> = std::iter::once(key)
+            .chain(self.quasi_identifiers.iter().map(String::as_str))
+            .chain(self.sensitive.as_deref())
+            .map(quote_ident)
+            .collect();
+        format!("SELECT {} FROM {table}", columns.join(", "))
+    }
+
+    fn partition(&self) -> Vec<Expr> {
+        self.quasi_identifiers.iter().map(col).collect()
+    }
+
+    fn diversity(&self) -> Expr {
+        match &self.sensitive {
+            Some(sensitive) => col(sensitive)
+                .drop_nulls()
+                .n_unique()
+                .cast(DataType::UInt32),
+            None => lit(1).cast(DataType::UInt32),
+        }
+    }
+
+    /// True for rows whose class meets both thresholds.
+    fn passes(&self) -> Expr {
+        let partition = self.partition();
+        len()
+            .over(&partition)
+            .gt_eq(lit(self.k as u32))
+            .and(self.diversity().over(&partition).gt_eq(lit(self.l as u32)))
+    }
+
+    /// Group `df` into equivalence classes and compare them to the thresholds.
+    pub fn measure(&self, df: &DataFrame) -> PolarsResult<PrivacyReport> {
+        if let Err(err) = self.validate() {
+            polars_bail!(InvalidOperation: "{err}");
+        }
+        let classes = df
+            .clone()
+            .lazy()
+            .group_by(self.partition())
+            .agg([len().alias("rows"), self.diversity().alias("diversity")])
+            .collect()?;
+        let sizes = classes.column("rows")?.u32()?;
+        let diversity = classes.column("diversity")?.u32()?;
+
+        let mut report = PrivacyReport {
+            rows: df.height(),
+            classes: classes.height(),
+            k: sizes.min().unwrap_or(0) as usize,
+            l: self
+                .sensitive
+                .as_ref()
+                .map(|_| diversity.min().unwrap_or(0) as usize),
+            required_k: self.k,
+            required_l: self.sensitive.as_ref().map(|_| self.l),
+            passed: true,
+            violating_classes: 0,
+            violating_rows: 0,
+            violations: Vec::new(),
+        };
+        let values: Vec<Column> = self
+            .quasi_identifiers
+            .iter()
+            .map(|name| classes.column(name)?.cast(&DataType::String))
+            .collect::<PolarsResult<_>>()?;
+        for i in 0..classes.height() {
+            let rows = sizes.get(i).unwrap_or(0) as usize;
+            let distinct = diversity.get(i).unwrap_or(0) as usize;
+            if rows >= self.k && distinct >= self.l {
+                continue;
+            }
+            report.violating_classes += 1;
+            report.violating_rows += rows;
+            if report.violations.len() < MAX_REPORTED_VIOLATIONS {
+                let mut class = BTreeMap::new();
+                for (name, column) in self.quasi_identifiers.iter().zip(&values) {
+                    let value = column.str()?.get(i).map(str::to_string);
+                    class.insert(name.clone(), value);
+                }
+                report.violations.push(Violation {
+                    class,
+                    rows,
+                    distinct_sensitive: report.l.map(|_| distinct),
+                });
+            }
+        }
+        report.passed = report.violating_classes == 0;
+        Ok(report)
+    }
+
+    /// `df` without the rows of classes that fail the thresholds.
+    pub fn suppress(&self, df: &DataFrame) -> PolarsResult<DataFrame> {
+        df.clone().lazy().filter(self.passes()).collect()
+    }
+
+    /// Only the rows [`Self::suppress`] would drop.
+    pub fn violating(&self, df: &DataFrame) -> PolarsResult<DataFrame> {
+        df.clone().lazy().filter(self.passes().not()).collect()
+    }
+}
+
+/// A class that is too small or not diverse enough.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// Quasi-identifier values of the class, as text.
+    pub class: BTreeMap<String, Option<String>>,
+    pub rows: usize,
+    pub distinct_sensitive: Option<usize>,
+}
+
+/// Measured `k` (smallest class) and `l` (fewest distinct sensitive values
+/// in a class), next to the required ones.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrivacyReport {
+    pub rows: usize,
+    pub classes: usize,
+    pub k: usize,
+    pub l: Option<usize>,
+    pub required_k: usize,
+    pub required_l: Option<usize>,
+    pub passed: bool,
+    pub violating_classes: usize,
+    pub violating_rows: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl PrivacyReport {
+    /// Error out unless every class meets the thresholds.
+    pub fn ensure(&self) -> anyhow::Result<()> {
+        if self.passed {
+            return Ok(());
+        }
+        let mut shortfall = format!("fewer than {} rows", self.required_k);
+        if let Some(l) = self.required_l {
+            shortfall.push_str(&format!(" or fewer than {l} distinct sensitive values"));
+        }
+        bail!(
+            "privacy check failed: {} of {} classes ({} of {} rows) have {shortfall}; \
+             generalize the quasi-identifiers further or suppress these rows",
+            self.violating_classes,
+            self.classes,
+            self.violating_rows,
+            self.rows
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DataFrame {
+        df! {
+            "id" => &[1, 2, 3, 4, 5, 6],
+            "age" => &["25-34", "25-34", "25-34", "35-44", "35-44", "45-54"],
+            "city" => &["Oslo", "Oslo", "Oslo", "Oslo", "Oslo", "Oslo"],
+            "diagnosis" => &["flu", "cold", "flu", "flu", "flu", "cold"],
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn test_measure_k_and_l() {
+        let check = PrivacyCheck::new(&["age", "city"], 2).with_l_diversity("diagnosis", 2);
+        let report = check.measure(&sample()).unwrap();
+        assert_eq!(report.classes, 3);
+        assert_eq!(report.k, 1);
+        assert_eq!(report.l, Some(1));
+        assert!(!report.passed);
+        // 35-44 has two rows but one diagnosis; 45-54 has one row
+        assert_eq!(report.violating_classes, 2);
+        assert_eq!(report.violating_rows, 3);
+        let ages: Vec<_> = report
+            .violations
+            .iter()
+            .map(|v| v.class["age"].clone().unwrap())
+            .collect();
+        assert_eq!(ages.len(), 2);
+        assert!(ages.contains(&"35-44".to_string()) && ages.contains(&"45-54".to_string()));
+        assert!(
+            report
+                .ensure()
+                .unwrap_err()
+                .to_string()
+                .contains("2 of 3 classes")
+        );
+
+        let report = PrivacyCheck::new(&["city"], 6).measure(&sample()).unwrap();
+        assert_eq!((report.k, report.l, report.passed), (6, None, true));
+        assert!(report.ensure().is_ok());
+    }
+
+    #[test]
+    fn test_nulls_do_not_count_as_sensitive_values() {
+        let df = df! {
+            "id" => &[1, 2, 3, 4],
+            "age" => &["25-34", "25-34", "35-44", "35-44"],
+            "diagnosis" => &[None, Some("flu"), Some("flu"), Some("cold")],
+        }
+        .unwrap();
+        let check = PrivacyCheck::new(&["age"], 2).with_l_diversity("diagnosis", 2);
+        let report = check.measure(&df).unwrap();
+        assert_eq!(report.l, Some(1));
+        assert_eq!(report.violating_classes, 1);
+        assert_eq!(report.violations[0].class["age"].as_deref(), Some("25-34"));
+        let ids: Vec<_> = check
+            .suppress(&df)
+            .unwrap()
+            .column("id")
+            .unwrap()
+            .i32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(ids, [3, 4]);
+    }
+
+    #[test]
+    fn test_suppress_violating_classes() {
+        let check = PrivacyCheck::new(&["age"], 2).with_l_diversity("diagnosis", 2);
+        let kept = check.suppress(&sample()).unwrap();
+        let ids: Vec<_> = kept
+            .column("id")
+            .unwrap()
+            .i32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert!(check.measure(&kept).unwrap().passed);
+        assert_eq!(check.violating(&sample()).unwrap().height(), 3);
+    }
+
+    #[test]
+    fn test_invalid_check() {
+        assert!(PrivacyCheck::new(&[], 5).validate().is_err());
+        assert!(PrivacyCheck::new(&["age"], 0).validate().is_err());
+        assert!(PrivacyCheck::resolve(None, None, None, Some(2)).is_err());
+        let check = PrivacyCheck::resolve(None, Some(10), Some("ssn".into()), None).unwrap();
+        assert_eq!((check.k, check.l), (10, 2));
+        assert_eq!(check.quasi_identifiers, ["age", "salary_bucket"]);
+        let check = PrivacyCheck::new(&["age"], 2).with_l_diversity("age", 2);
+        assert!(check.measure(&sample()).is_err());
+        assert_eq!(
+            PrivacyCheck::customers().select("customers_anonymized", "id"),
+            r#"SELECT "id", "age", "salary_bucket" FROM customers_anonymized"#
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Keyed pseudonymization with HMAC-SHA256.
+//!
+//! A pseudonym is the first `length` hex characters of
+//! `HMAC-SHA256(key, value)`. Without the key, pseudonyms cannot be
+//! recomputed from a dictionary of names or emails, and the same value always
+//! maps to the same pseudonym under one key, so joins keep working.
+//!
+//! Every pseudonymizer has a key id that pipelines store next to their
+//! output. Rotating the key means supplying a new secret (and id); rows
+//! written under the old key stay identifiable by their old id.
+//!
+//! # Collision probability
+//!
+//! `length` hex characters give `2^(4 * length)` possible pseudonyms. For `n`
+//! distinct inputs the birthday bound is
+//! `p ≈ 1 - exp(-n² / 2^(4 * length + 1))`:
+//!
+//! | length | bits | n = 10⁶ | n = 10⁹ |
+//! |--------|------|---------|---------|
+//! | 8      | 32   | ≈ 1     | ≈ 1     |
+//! | 12     | 48   | 1.8e-3  | ≈ 1     |
+//! | 16     | 64   | 2.7e-8  | 2.7e-2  |
+//! | 24     | 96   | 6.3e-18 | 6.3e-12 |
+//! | 32     | 128  | 1.5e-27 | 1.5e-21 |
+//!
+//! [`Pseudonymizer::collision_probability`] computes the same bound.
+
+use anyhow::{Context, bail};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Env var holding the secret key when no parameter is given.
+pub const KEY_ENV: &str = "RUSTETL_PSEUDONYM_KEY";
+/// Env var holding the key id when no parameter is given.
+pub const KEY_ID_ENV: &str = "RUSTETL_PSEUDONYM_KEY_ID";
+
+/// Shortest accepted secret, in bytes.
+pub const MIN_KEY_LEN: usize = 16;
+/// Default pseudonym length in hex characters (64 bits).
+pub const DEFAULT_LENGTH: usize = 16;
+/// Longest pseudonym: the full SHA-256 output.
+pub const MAX_LENGTH: usize = 64;
+
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    key_id: String,
+    mac: Hmac<Sha256>,
+    length: usize,
+}
+
+impl std::fmt::Debug for Pseudonymizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pseudonymizer")
+            .field("key_id", &self.key_id)
+            .field("length", &self.length)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Pseudonymizer {
+    /// Create a pseudonymizer. Without a `key_id` one is derived from the
+    /// key (first 8 hex characters of its SHA-256).
+    pub fn new(key: &[u8], key_id: Option<&str>) -> anyhow::Result<Self> {
+        if key.len() < MIN_KEY_LEN {
+            bail!(
+                "pseudonym key must be at least {MIN_KEY_LEN} bytes, got {}",
+                key.len()
+            );
+        }
+        let key_id = match key_id {
+            Some(id) if !id.trim().is_empty() => id.trim().to_string(),
+            _ => format!("{:x}", Sha256::digest(key))[..8].to_string(),
+        };
+        let mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        Ok(Pseudonymizer {
+            key_id,
+            mac,
+            length: DEFAULT_LENGTH,
+        })
+    }
+
+    /// Take the key from the Windmill parameter if given, else from
+    /// [`KEY_ENV`] / [`KEY_ID_ENV`].
+    pub fn resolve(key: Option<String>, key_id: Option<String>) -> anyhow::Result<Self> {
+        let key = match key.filter(|k| !k.is_empty()) {
+            Some(key) => key,
+            None => std::env::var(KEY_ENV)
+                .with_context(|| format!("no pseudonym key: pass one or set {KEY_ENV}"))?,
+        };
+        let key_id = key_id.or_else(|| std::env::var(KEY_ID_ENV).ok());
+        Self::new(key.as_bytes(), key_id.as_deref())
+    }
+
+    /// Set the pseudonym length in hex characters (`1..=64`).
+    pub fn with_length(mut self, length: usize) -> anyhow::Result<Self> {
+        if !(1..=MAX_LENGTH).contains(&length) {
+            bail!("pseudonym length must be between 1 and {MAX_LENGTH}, got {length}");
+        }
+        self.length = length;
+        Ok(self)
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn pseudonymize(&self, value: &str) -> String {
+        self.pseudonymize_to(value, self.length)
+    }
+
+    /// Like [`pseudonymize`](Self::pseudonymize) with an explicit length,
+    /// clamped to `1..=64`.
+    pub fn pseudonymize_to(&self, value: &str, length: usize) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(value.as_bytes());
+        let hex = format!("{:x}", mac.finalize().into_bytes());
+        hex[..length.clamp(1, MAX_LENGTH)].to_string()
+    }
+
+    /// `len` pseudo-random values from `HMAC(key, tag || 0 || counter || seed)`,
+    /// used by [`tokenize`](crate::tokenize) to pick replacement characters.
+    pub(crate) fn keystream(&self, tag: &str, seed: &str, len: usize) -> Vec<u16> {
+        let mut out = Vec::with_capacity(len);
+        let mut counter = 0u32;
+        while out.len() < len {
+            let mut mac = self.mac.clone();
+            mac.update(tag.as_bytes());
+            mac.update(&[0]);
+            mac.update(&counter.to_be_bytes());
+            mac.update(seed.as_bytes());
+            let block = mac.finalize().into_bytes();
+            out.extend(
+                block
+                    .chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]])),
+            );
+            counter += 1;
+        }
+        out.truncate(len);
+        out
+    }
+
+    /// Birthday-bound probability that `n` distinct values produce at least
+    /// one colliding pseudonym at the configured length.
+    pub fn collision_probability(&self, n: u64) -> f64 {
+        collision_probability(n, self.length)
+    }
+}
+
+/// `1 - exp(-n² / 2^(4 * length + 1))`.
+pub fn collision_probability(n: u64, length: usize) -> f64 {
+    let n = n as f64;
+    let space = 2f64.powi(4 * length as i32 + 1);
+    -(-(n * n) / space).exp_m1()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"0123456789abcdef-test-key";
+
+    #[test]
+    fn test_matches_hmac_sha256() {
+        // echo -n alice | openssl dgst -sha256 -hmac key-key-key-key-key
+        let p = Pseudonymizer::new(b"key-key-key-key-key", None).unwrap();
+        assert_eq!(
+            p.pseudonymize_to("alice", 64),
+            "cdd8e80d4b66874ed810ad0ec1168c3bd055c5ecb890b1f8af9743a29b721376"
+        );
+        assert_eq!(p.pseudonymize("alice"), "cdd8e80d4b66874e");
+        assert_eq!(p.key_id(), "9ffa89e0");
+    }
+
+    #[test]
+    fn test_deterministic_and_keyed() {
+        let a = Pseudonymizer::new(KEY, None).unwrap();
+        let b = Pseudonymizer::new(b"another-secret-key-0000", None).unwrap();
+        assert_eq!(a.pseudonymize("alice"), a.pseudonymize("alice"));
+        assert_eq!(a.pseudonymize("alice").len(), DEFAULT_LENGTH);
+        assert_ne!(a.pseudonymize("alice"), a.pseudonymize("bob"));
+        assert_ne!(a.pseudonymize("alice"), b.pseudonymize("alice"));
+        assert_ne!(a.key_id(), b.key_id());
+    }
+
+    #[test]
+    fn test_key_and_length_validation() {
+        assert!(Pseudonymizer::new(b"short", None).is_err());
+        let p = Pseudonymizer::new(KEY, Some("2026-10")).unwrap();
+        assert_eq!(p.key_id(), "2026-10");
+        assert!(p.clone().with_length(0).is_err());
+        assert!(p.clone().with_length(65).is_err());
+        assert_eq!(p.with_length(24).unwrap().pseudonymize("x").len(), 24);
+    }
+
+    #[test]
+    fn test_collision_probability() {
+        assert!((collision_probability(1_000_000, 16) - 2.71e-8).abs() < 1e-9);
+        assert!(collision_probability(1_000_000, 8) > 0.99);
+        assert_eq!(collision_probability(0, 16), 0.0);
+    }
+}
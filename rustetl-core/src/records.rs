@@ -0,0 +1,242 @@
+//! Frames as JSON that a following Windmill flow step can consume.
+//!
+//! A pretty-printed table is truncated and cannot be parsed, so scripts
+//! return a [`FrameJson`] instead: the first rows as records (one object per
+//! row) or columns (one array per column), plus the schema and total row
+//! count. Numbers stay numbers, nulls stay `null`, dates become ISO 8601
+//! strings; NaN and infinity become `null` since JSON has no such numbers.
+
+use anyhow::bail;
+use chrono::{DateTime, Days, NaiveDate};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::str::FromStr;
+
+/// Rows scripts return unless told otherwise.
+pub const DEFAULT_ROW_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orient {
+    /// `[{"name": "Alice", "age": 25}, ...]`
+    #[default]
+    Records,
+    /// `{"name": ["Alice", ...], "age": [25, ...]}`
+    Columns,
+}
+
+impl FromStr for Orient {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "records" => Ok(Orient::Records),
+            "columns" => Ok(Orient::Columns),
+            _ => bail!("unknown orient `{name}` (expected records or columns)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    /// Polars dtype, e.g. `i64`, `str`, `date`, `datetime[μs]`.
+    pub dtype: String,
+}
+
+/// The first rows of a frame as JSON, with enough metadata to tell whether
+/// anything was cut off.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameJson {
+    pub total_rows: usize,
+    pub returned_rows: usize,
+    pub truncated: bool,
+    pub schema: Vec<ColumnSchema>,
+    pub data: Value,
+}
+
+/// Serialize at most `limit` rows of `df` (all rows if `None`).
+pub fn to_json(df: &DataFrame, orient: Orient, limit: Option<usize>) -> FrameJson {
+    let head = match limit {
+        Some(limit) if limit < df.height() => df.head(Some(limit)),
+        _ => df.clone(),
+    };
+    let columns = head.get_columns();
+    // Series::iter needs one contiguous chunk
+    let series: Vec<Series> = columns
+        .iter()
+        .map(|c| c.as_materialized_series().rechunk())
+        .collect();
+    let data = match orient {
+        Orient::Records => {
+            let mut iters: Vec<_> = series.iter().map(|s| s.iter()).collect();
+            let rows = (0..head.height())
+                .map(|_| {
+                    let row: Map<String, Value> = series
+                        .iter()
+                        .zip(iters.iter_mut())
+                        .map(|(s, values)| {
+                            (
+                                s.name().to_string(),
+                                values.next().map_or(Value::Null, value),
+                            )
+                        })
+                        .collect();
+                    Value::Object(row)
+                })
+                .collect();
+            Value::Array(rows)
+        }
+        Orient::Columns => Value::Object(
+            series
+                .iter()
+                .map(|s| {
+                    let values = s.iter().map(value).collect();
+                    (s.name().to_string(), Value::Array(values))
+                })
+                .collect(),
+        ),
+    };
+    FrameJson {
+        total_rows: df.height(),
+        returned_rows: head.height(),
+        truncated: head.height() < df.height(),
+        schema: schema(df),
+        data,
+    }
+}
+
+/// Column names and dtypes of `df`.
+pub fn schema(df: &DataFrame) -> Vec<ColumnSchema> {
+    df.get_columns()
+        .iter()
+        .map(|c| ColumnSchema {
+            name: c.name().to_string(),
+            dtype: c.dtype().to_string(),
+        })
+        .collect()
+}
+
+/// [`to_json`] as records, capped at [`DEFAULT_ROW_LIMIT`].
+pub fn records(df: &DataFrame) -> FrameJson {
+    to_json(df, Orient::Records, Some(DEFAULT_ROW_LIMIT))
+}
+
+fn float(v: f64) -> Value {
+    Number::from_f64(v).map_or(Value::Null, Value::Number)
+}
+
+fn value(av: AnyValue) -> Value {
+    match av {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(b) => Value::Bool(b),
+        AnyValue::Float32(v) => float(v as f64),
+        AnyValue::Float64(v) => float(v),
+        AnyValue::Date(days) => date(days).map_or(Value::Null, |d| Value::String(d.to_string())),
+        AnyValue::Datetime(v, unit, tz) => datetime(v, unit, tz.is_some()),
+        AnyValue::DatetimeOwned(v, unit, tz) => datetime(v, unit, tz.is_some()),
+        AnyValue::List(values) => Value::Array(values.iter().map(value).collect()),
+        other if other.is_signed_integer() => {
+            other.extract::<i64>().map_or(Value::Null, Value::from)
+        }
+        other if other.is_unsigned_integer() => {
+            other.extract::<u64>().map_or(Value::Null, Value::from)
+        }
+        other => match other.get_str() {
+            Some(s) => Value::String(s.to_string()),
+            None => Value::String(other.to_string()),
+        },
+    }
+}
+
+fn date(days: i32) -> Option<NaiveDate> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)?;
+    if days >= 0 {
+        epoch.checked_add_days(Days::new(days as u64))
+    } else {
+        epoch.checked_sub_days(Days::new(days.unsigned_abs() as u64))
+    }
+}
+
+/// ISO 8601; zoned values are stored as UTC instants, so they get a `Z`.
+fn datetime(v: i64, unit: TimeUnit, zoned: bool) -> Value {
+    let utc = match unit {
+        TimeUnit::Nanoseconds => Some(DateTime::from_timestamp_nanos(v)),
+        TimeUnit::Microseconds => DateTime::from_timestamp_micros(v),
+        TimeUnit::Milliseconds => DateTime::from_timestamp_millis(v),
+    };
+    match utc {
+        Some(utc) if zoned => Value::String(utc.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string()),
+        Some(utc) => Value::String(utc.naive_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> DataFrame {
+        df! {
+            "name" => &[Some("Alice"), None, Some("Charlie")],
+            "age" => &[25i32, 30, 35],
+            "score" => &[1.5, f64::NAN, 3.0],
+            "active" => &[true, false, true],
+        }
+        .unwrap()
+        .lazy()
+        .with_columns([
+            (lit(19_723) + col("age"))
+                .cast(DataType::Date)
+                .alias("joined"),
+            lit(1_700_000_000_123_456i64)
+                .cast(DataType::Datetime(TimeUnit::Microseconds, None))
+                .alias("seen"),
+        ])
+        .collect()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_records_keep_types() {
+        let out = to_json(&sample(), Orient::Records, Some(2));
+        assert_eq!(
+            (out.total_rows, out.returned_rows, out.truncated),
+            (3, 2, true)
+        );
+        assert_eq!(
+            out.data,
+            json!([
+                {"name": "Alice", "age": 25, "score": 1.5, "active": true,
+                 "joined": "2024-01-26", "seen": "2023-11-14T22:13:20.123456"},
+                {"name": null, "age": 30, "score": null, "active": false,
+                 "joined": "2024-01-31", "seen": "2023-11-14T22:13:20.123456"},
+            ])
+        );
+        // Keys keep column order
+        let keys: Vec<_> = out.data[0].as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, ["name", "age", "score", "active", "joined", "seen"]);
+        assert_eq!(out.schema[4].dtype, "date");
+    }
+
+    #[test]
+    fn test_columns_orient() {
+        let out = to_json(&sample(), Orient::Columns, None);
+        assert!(!out.truncated);
+        assert_eq!(out.data["age"], json!([25, 30, 35]));
+        assert_eq!(out.data["name"], json!(["Alice", null, "Charlie"]));
+
+        // Frames built from several batches have several chunks
+        let stacked = sample().vstack(&sample()).unwrap();
+        let out = to_json(&stacked, Orient::Columns, Some(4));
+        assert_eq!(out.data["age"], json!([25, 30, 35, 25]));
+        assert_eq!(
+            serde_json::to_value(records(&DataFrame::empty())).unwrap(),
+            json!({"total_rows": 0, "returned_rows": 0, "truncated": false, "schema": [], "data": []})
+        );
+        assert_eq!("Columns".parse::<Orient>().unwrap(), Orient::Columns);
+        assert!("table".parse::<Orient>().is_err());
+    }
+}
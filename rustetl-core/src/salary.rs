@@ -0,0 +1,89 @@
+//! Salary raise calculation behind `salary_calculator.rs`.
+
+use polars::prelude::*;
+use std::io::Cursor;
+
+/// Totals over a frame produced by [`apply_raise`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaiseSummary {
+    pub total_old: f64,
+    pub total_new: f64,
+    pub total_raise: f64,
+}
+
+/// Parse CSV text with a header row.
+pub fn read_csv(csv_data: &str) -> PolarsResult<DataFrame> {
+    CsvReadOptions::default()
+        .with_has_header(true)
+        .into_reader_with_file_handle(Cursor::new(csv_data.as_bytes()))
+        .finish()
+}
+
+/// Keep rows with `age > min_age` (if given) and add `old_salary`,
+/// `new_salary` and `raise_amount` columns for a `raise_percent` raise.
+pub fn apply_raise(
+    df: DataFrame,
+    raise_percent: f64,
+    min_age: Option<i32>,
+) -> PolarsResult<DataFrame> {
+    let mut lazy_df = df.lazy();
+
+    if let Some(min) = min_age {
+        lazy_df = lazy_df.filter(col("age").gt(lit(min)));
+    }
+
+    let raise_multiplier = 1.0 + (raise_percent / 100.0);
+
+    lazy_df
+        .with_columns([col("salary").alias("old_salary")])
+        .with_columns([
+            (col("salary") * lit(raise_multiplier)).alias("new_salary"),
+            (col("salary") * lit(raise_percent / 100.0)).alias("raise_amount"),
+        ])
+        .collect()
+}
+
+fn column_sum(df: &DataFrame, name: &str) -> PolarsResult<f64> {
+    Ok(df
+        .column(name)?
+        .as_materialized_series()
+        .cast(&DataType::Float64)?
+        .sum::<f64>()
+        .unwrap_or(0.0))
+}
+
+pub fn summarize(df: &DataFrame) -> PolarsResult<RaiseSummary> {
+    let total_old = column_sum(df, "old_salary")?;
+    let total_new = column_sum(df, "new_salary")?;
+    Ok(RaiseSummary {
+        total_old,
+        total_new,
+        total_raise: total_new - total_old,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "name,age,department,salary
+Alice,28,Engineering,75000
+Bob,34,Sales,65000
+Charlie,29,Engineering,82000";
+
+    #[test]
+    fn test_apply_raise() {
+        let df = apply_raise(read_csv(CSV).unwrap(), 10.0, None).unwrap();
+        assert_eq!(df.height(), 3);
+        let summary = summarize(&df).unwrap();
+        assert_eq!(summary.total_old, 222000.0);
+        assert!((summary.total_raise - 22200.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_apply_raise_min_age() {
+        let df = apply_raise(read_csv(CSV).unwrap(), 10.0, Some(30)).unwrap();
+        assert_eq!(df.height(), 1);
+        assert_eq!(summarize(&df).unwrap().total_old, 65000.0);
+    }
+}
@@ -0,0 +1,371 @@
+//! SQL for the `customers` table and the rest of the shopping dataset
+//! (`products`, `orders`, `order_items`).
+//!
+//! Every function takes the (optionally schema-qualified) table name so the
+//! same statements serve `customers` and `shopping.customers`.
+
+use crate::shop::ShopTables;
+
+/// Column list selected from `customers`.
+pub const CUSTOMER_COLUMNS: &str = "id, name, email, phone, address, age, salary, ssn";
+
+/// Drop `table` with everything that depends on it (views, foreign keys),
+/// for tables the generators rebuild from scratch.
+pub fn drop_table(table: &str) -> String {
+    format!("DROP TABLE IF EXISTS {table} CASCADE")
+}
+
+/// Drop `table`, failing while views or foreign keys depend on it.
+pub fn drop_table_restrict(table: &str) -> String {
+    format!("DROP TABLE IF EXISTS {table} RESTRICT")
+}
+
+pub fn create_customers(table: &str) -> String {
+    format!(
+        "CREATE TABLE {table} (
+            id SERIAL PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            email VARCHAR(255) NOT NULL,
+            phone VARCHAR(50),
+            address TEXT,
+            age INTEGER CHECK (age >= 18 AND age <= 100),
+            salary INTEGER CHECK (salary >= 0),
+            ssn VARCHAR(20),
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+}
+
+/// Index and trigger statements created alongside `customers`.
+pub fn customers_indexes(table: &str) -> Vec<String> {
+    let base = index_base(table);
+    let mut statements = vec![
+        format!("CREATE INDEX idx_{base}_age ON {table}(age)"),
+        format!("CREATE INDEX idx_{base}_created_at ON {table}(created_at)"),
+    ];
+    statements.extend(track_updates(table));
+    statements
+}
+
+/// Name of the trigger made by [`touch_trigger`].
+pub const TOUCH_TRIGGER: &str = "touch_updated_at";
+
+/// Add `updated_at` to a `customers` table made before it existed, with its
+/// index and a trigger that stamps it on every insert and update, so
+/// incremental runs see changed rows.
+///
+/// Every statement locks `table` against writers even when there is nothing
+/// to do; `db::*::track_updates` checks [`column_exists`] and
+/// [`trigger_exists`] first and only runs the missing part.
+pub fn track_updates(table: &str) -> Vec<String> {
+    let mut statements = add_updated_at(table);
+    statements.extend(touch_trigger(table));
+    statements
+}
+
+/// The `updated_at` column of [`track_updates`] and its index.
+pub fn add_updated_at(table: &str) -> Vec<String> {
+    let base = index_base(table);
+    vec![
+        format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS \
+             updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP"
+        ),
+        format!("CREATE INDEX IF NOT EXISTS idx_{base}_updated_at ON {table}(updated_at, id)"),
+    ]
+}
+
+/// The trigger of [`track_updates`]. It uses `clock_timestamp()`, the time
+/// of the write, rather than the writer's transaction start (see
+/// [`watermark`](crate::db::watermark)).
+pub fn touch_trigger(table: &str) -> Vec<String> {
+    vec![
+        "CREATE OR REPLACE FUNCTION rustetl_touch_updated_at() RETURNS trigger AS $$
+         BEGIN
+             NEW.updated_at := clock_timestamp();
+             RETURN NEW;
+         END
+         $$ LANGUAGE plpgsql"
+            .to_string(),
+        format!("DROP TRIGGER IF EXISTS {TOUCH_TRIGGER} ON {table}"),
+        format!(
+            "CREATE TRIGGER {TOUCH_TRIGGER} BEFORE INSERT OR UPDATE ON {table} \
+             FOR EACH ROW EXECUTE FUNCTION rustetl_touch_updated_at()"
+        ),
+    ]
+}
+
+pub fn create_products(table: &str) -> String {
+    format!(
+        "CREATE TABLE {table} (
+            id SERIAL PRIMARY KEY,
+            sku VARCHAR(32) NOT NULL UNIQUE,
+            name VARCHAR(255) NOT NULL,
+            category VARCHAR(50) NOT NULL,
+            price NUMERIC(10,2) NOT NULL CHECK (price >= 0)
+        )"
+    )
+}
+
+/// `orders`, each referencing a row of `customers`.
+pub fn create_orders(table: &str, customers: &str) -> String {
+    format!(
+        "CREATE TABLE {table} (
+            id SERIAL PRIMARY KEY,
+            customer_id INTEGER NOT NULL REFERENCES {customers}(id),
+            ordered_at TIMESTAMP NOT NULL,
+            status VARCHAR(20) NOT NULL,
+            total NUMERIC(12,2) NOT NULL CHECK (total >= 0)
+        )"
+    )
+}
+
+/// `order_items`, each referencing one of `orders` and one of `products`.
+pub fn create_order_items(table: &str, orders: &str, products: &str) -> String {
+    format!(
+        "CREATE TABLE {table} (
+            id SERIAL PRIMARY KEY,
+            order_id INTEGER NOT NULL REFERENCES {orders}(id) ON DELETE CASCADE,
+            product_id INTEGER NOT NULL REFERENCES {products}(id),
+            quantity INTEGER NOT NULL CHECK (quantity > 0),
+            unit_price NUMERIC(10,2) NOT NULL CHECK (unit_price >= 0)
+        )"
+    )
+}
+
+/// Foreign keys are not indexed by Postgres itself.
+pub fn orders_indexes(table: &str) -> Vec<String> {
+    let base = index_base(table);
+    vec![
+        format!("CREATE INDEX idx_{base}_customer_id ON {table}(customer_id)"),
+        format!("CREATE INDEX idx_{base}_ordered_at ON {table}(ordered_at)"),
+    ]
+}
+
+pub fn order_items_indexes(table: &str) -> Vec<String> {
+    let base = index_base(table);
+    vec![
+        format!("CREATE INDEX idx_{base}_order_id ON {table}(order_id)"),
+        format!("CREATE INDEX idx_{base}_product_id ON {table}(product_id)"),
+    ]
+}
+
+/// Drop and recreate `products`, `orders` and `order_items` with their
+/// foreign keys and indexes. `customers` must already exist.
+pub fn recreate_shop(tables: &ShopTables) -> Vec<String> {
+    let mut statements = vec![
+        drop_table(&tables.order_items),
+        drop_table(&tables.orders),
+        drop_table(&tables.products),
+        create_products(&tables.products),
+        create_orders(&tables.orders, &tables.customers),
+        create_order_items(&tables.order_items, &tables.orders, &tables.products),
+    ];
+    statements.extend(orders_indexes(&tables.orders));
+    statements.extend(order_items_indexes(&tables.order_items));
+    statements
+}
+
+/// Move the `SERIAL` sequence of `table.id` past rows loaded with explicit
+/// ids, so later inserts do not collide with them.
+pub fn sync_id_sequence(table: &str) -> String {
+    format!(
+        "SELECT setval(pg_get_serial_sequence({}, 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {table}",
+        quote_literal(table)
+    )
+}
+
+/// Up to `limit` random rows of `table`, quoted with [`quote_table`].
+/// Sorting by `random()` reads the whole table, which is fine for
+/// occasional scans.
+pub fn sample_rows(table: &str, limit: usize) -> String {
+    format!(
+        "SELECT * FROM {} ORDER BY random() LIMIT {limit}",
+        quote_table(table)
+    )
+}
+
+/// Delete the rows whose `id` is in the `$1` array of `BIGINT`s; `id` can
+/// be any integer type.
+pub fn delete_ids(table: &str) -> String {
+    format!("DELETE FROM {table} WHERE id = ANY($1::bigint[])")
+}
+
+pub fn select_customers(table: &str) -> String {
+    format!("SELECT {CUSTOMER_COLUMNS} FROM {table} ORDER BY id")
+}
+
+/// `EXISTS` query for an unqualified or `schema.table` name.
+pub fn table_exists(table: &str) -> String {
+    format!(
+        "SELECT EXISTS (SELECT FROM information_schema.tables WHERE {})",
+        match_table(table)
+    )
+}
+
+/// `EXISTS` query for `column` of `table`, named as in [`table_exists`].
+pub fn column_exists(table: &str, column: &str) -> String {
+    format!(
+        "SELECT EXISTS (SELECT FROM information_schema.columns \
+         WHERE {} AND column_name = {})",
+        match_table(table),
+        quote_literal(column)
+    )
+}
+
+/// `column_name, data_type` of every column of `table`, named as in
+/// [`table_exists`].
+pub fn column_types(table: &str) -> String {
+    format!(
+        "SELECT column_name::text, data_type::text FROM information_schema.columns \
+         WHERE {} ORDER BY ordinal_position",
+        match_table(table)
+    )
+}
+
+/// `EXISTS` query for `trigger` on the existing `table`.
+pub fn trigger_exists(table: &str, trigger: &str) -> String {
+    format!(
+        "SELECT EXISTS (SELECT FROM pg_trigger \
+         WHERE tgrelid = {}::regclass AND tgname = {})",
+        quote_literal(table),
+        quote_literal(trigger)
+    )
+}
+
+/// `information_schema` condition on `table_schema` and `table_name`.
+fn match_table(table: &str) -> String {
+    match table.split_once('.') {
+        Some((schema, name)) => format!(
+            "table_schema = {} AND table_name = {}",
+            quote_literal(schema),
+            quote_literal(name)
+        ),
+        None => format!("table_name = {}", quote_literal(table)),
+    }
+}
+
+/// `COPY ... FROM STDIN` in CSV format for the given columns, in the order
+/// the CSV rows list them.
+pub fn copy_from_stdin(table: &str, columns: &[&str]) -> String {
+    let columns: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+    format!(
+        "COPY {table} ({}) FROM STDIN WITH (FORMAT csv)",
+        columns.join(", ")
+    )
+}
+
+/// Open a forward-only cursor; must run inside a transaction.
+pub fn declare_cursor(name: &str, query: &str) -> String {
+    format!("DECLARE {name} NO SCROLL CURSOR FOR {query}")
+}
+
+pub fn fetch_cursor(name: &str, rows: usize) -> String {
+    format!("FETCH FORWARD {rows} FROM {name}")
+}
+
+/// Single-quote a string literal.
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Double-quote an identifier so frame column names need not be valid SQL.
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote an unqualified or `schema.table` name part by part, for names
+/// taken from script parameters.
+pub fn quote_table(table: &str) -> String {
+    table
+        .split('.')
+        .map(quote_ident)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Index names are schema-scoped, so drop the schema prefix.
+pub(crate) fn index_base(table: &str) -> &str {
+    table.rsplit('.').next().unwrap_or(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualified_table_names() {
+        assert_eq!(
+            customers_indexes("shopping.customers")[0],
+            "CREATE INDEX idx_customers_age ON shopping.customers(age)"
+        );
+        assert!(table_exists("shopping.customers").contains("table_schema = 'shopping'"));
+        assert!(table_exists("customers").contains("table_name = 'customers'"));
+        assert!(table_exists("it's").contains("table_name = 'it''s'"));
+        assert!(column_exists("shopping.customers", "updated_at").ends_with(
+            "WHERE table_schema = 'shopping' AND table_name = 'customers' \
+                 AND column_name = 'updated_at')"
+        ));
+        assert!(
+            column_types("shopping.customers")
+                .ends_with("AND table_name = 'customers' ORDER BY ordinal_position")
+        );
+        assert!(
+            trigger_exists("shopping.customers", TOUCH_TRIGGER).ends_with(
+                "WHERE tgrelid = 'shopping.customers'::regclass AND tgname = 'touch_updated_at')"
+            )
+        );
+        assert_eq!(
+            quote_table("shopping.customers"),
+            r#""shopping"."customers""#
+        );
+        assert_eq!(quote_table("t; DROP TABLE x"), r#""t; DROP TABLE x""#);
+        assert_eq!(
+            sample_rows("user", 10),
+            r#"SELECT * FROM "user" ORDER BY random() LIMIT 10"#
+        );
+    }
+
+    #[test]
+    fn test_shop_tables_reference_each_other() {
+        let statements = recreate_shop(&ShopTables::in_schema("shopping"));
+        assert_eq!(
+            statements[0],
+            "DROP TABLE IF EXISTS shopping.order_items CASCADE"
+        );
+        assert!(statements[4].contains("REFERENCES shopping.customers(id)"));
+        assert!(statements[5].contains("REFERENCES shopping.orders(id) ON DELETE CASCADE"));
+        assert!(statements[5].contains("REFERENCES shopping.products(id)"));
+        assert!(
+            statements.contains(
+                &"CREATE INDEX idx_order_items_product_id ON shopping.order_items(product_id)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            sync_id_sequence("shopping.orders"),
+            "SELECT setval(pg_get_serial_sequence('shopping.orders', 'id'), \
+             COALESCE(MAX(id), 0) + 1, false) FROM shopping.orders"
+        );
+    }
+
+    #[test]
+    fn test_copy_from_stdin_quotes_columns() {
+        assert_eq!(
+            copy_from_stdin("shopping.customers", &["id", "odd \"name\""]),
+            r#"COPY shopping.customers ("id", "odd ""name""") FROM STDIN WITH (FORMAT csv)"#
+        );
+    }
+
+    #[test]
+    fn test_cursor_statements() {
+        assert_eq!(
+            declare_cursor("c", &select_customers("customers")),
+            format!(
+                "DECLARE c NO SCROLL CURSOR FOR SELECT {CUSTOMER_COLUMNS} FROM customers ORDER BY id"
+            )
+        );
+        assert_eq!(fetch_cursor("c", 500), "FETCH FORWARD 500 FROM c");
+    }
+}
@@ -0,0 +1,454 @@
+//! The rest of the `shopping` database: `products`, `orders` and
+//! `order_items`, generated around existing customer ids.
+//!
+//! Cardinalities follow a typical store rather than a uniform spread:
+//! orders per customer are geometric (many customers with none or one
+//! order, a long tail of regulars), orders mostly hold one or two distinct
+//! products, and product popularity is Zipf-like. Every `customer_id`,
+//! `order_id` and `product_id` points at a generated or given row, and
+//! `orders.total` is the sum of its items.
+
+use anyhow::ensure;
+use polars::prelude::*;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// Orders are placed between 2023-01-01 and 2024-12-31 (UTC).
+const ORDERS_FROM: i64 = 1_672_531_200;
+const ORDERS_UNTIL: i64 = 1_735_689_599;
+
+/// `(category, lowest price, highest price)` in whole dollars.
+const CATEGORIES: [(&str, u32, u32); 8] = [
+    ("Electronics", 20, 1500),
+    ("Home", 10, 400),
+    ("Books", 5, 60),
+    ("Clothing", 10, 200),
+    ("Sports", 10, 500),
+    ("Toys", 5, 120),
+    ("Grocery", 1, 40),
+    ("Beauty", 5, 90),
+];
+
+const ADJECTIVES: [&str; 10] = [
+    "Classic",
+    "Compact",
+    "Deluxe",
+    "Eco",
+    "Ergonomic",
+    "Essential",
+    "Premium",
+    "Smart",
+    "Ultra",
+    "Vintage",
+];
+
+const NOUNS: [(&str, [&str; 4]); 8] = [
+    (
+        "Electronics",
+        ["Headphones", "Speaker", "Monitor", "Charger"],
+    ),
+    ("Home", ["Lamp", "Blanket", "Cookware Set", "Chair"]),
+    ("Books", ["Cookbook", "Novel", "Atlas", "Guide"]),
+    ("Clothing", ["Jacket", "Sneakers", "T-Shirt", "Scarf"]),
+    (
+        "Sports",
+        ["Yoga Mat", "Bicycle Helmet", "Tennis Racket", "Dumbbells"],
+    ),
+    (
+        "Toys",
+        ["Puzzle", "Building Set", "Plush Bear", "Board Game"],
+    ),
+    (
+        "Grocery",
+        ["Coffee Beans", "Olive Oil", "Green Tea", "Granola"],
+    ),
+    ("Beauty", ["Face Cream", "Shampoo", "Perfume", "Lip Balm"]),
+];
+
+/// Order statuses and how often they occur.
+const STATUSES: [(&str, u32); 5] = [
+    ("delivered", 80),
+    ("shipped", 8),
+    ("processing", 4),
+    ("cancelled", 5),
+    ("returned", 3),
+];
+
+/// Weights of quantities 1, 2, 3, 4 per order line.
+const QUANTITY_WEIGHTS: [u32; 4] = [70, 20, 7, 3];
+
+/// Highest accepted [`ShopOptions::orders_per_customer`].
+pub const MAX_ORDERS_PER_CUSTOMER: f64 = 1000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Product {
+    pub id: i32,
+    pub sku: String,
+    pub name: String,
+    pub category: String,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: i32,
+    pub customer_id: i32,
+    /// Seconds since the Unix epoch.
+    pub ordered_at: i64,
+    pub status: String,
+    pub total: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderItem {
+    pub id: i32,
+    pub order_id: i32,
+    pub product_id: i32,
+    pub quantity: i32,
+    pub unit_price: f64,
+}
+
+/// How much data to generate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShopOptions {
+    pub products: usize,
+    /// Average orders per customer; the spread is geometric.
+    pub orders_per_customer: f64,
+    /// Upper bound on distinct products in one order.
+    pub max_items_per_order: usize,
+}
+
+impl Default for ShopOptions {
+    fn default() -> Self {
+        ShopOptions {
+            products: 200,
+            orders_per_customer: 3.0,
+            max_items_per_order: 5,
+        }
+    }
+}
+
+/// One generated dataset. Ids start at 1 in every table.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Shop {
+    pub products: Vec<Product>,
+    pub orders: Vec<Order>,
+    pub order_items: Vec<OrderItem>,
+}
+
+/// Table names of one dataset, `customers` included since orders
+/// reference it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShopTables {
+    pub customers: String,
+    pub products: String,
+    pub orders: String,
+    pub order_items: String,
+}
+
+impl Default for ShopTables {
+    fn default() -> Self {
+        ShopTables {
+            customers: "customers".to_string(),
+            products: "products".to_string(),
+            orders: "orders".to_string(),
+            order_items: "order_items".to_string(),
+        }
+    }
+}
+
+impl ShopTables {
+    /// The default names qualified with `schema`.
+    pub fn in_schema(schema: &str) -> Self {
+        let defaults = ShopTables::default();
+        let qualify = |table: String| format!("{schema}.{table}");
+        ShopTables {
+            customers: qualify(defaults.customers),
+            products: qualify(defaults.products),
+            orders: qualify(defaults.orders),
+            order_items: qualify(defaults.order_items),
+        }
+    }
+}
+
+impl ShopOptions {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mean = self.orders_per_customer;
+        ensure!(
+            mean.is_finite() && (0.0..=MAX_ORDERS_PER_CUSTOMER).contains(&mean),
+            "orders_per_customer must be between 0 and {MAX_ORDERS_PER_CUSTOMER}, got {mean}"
+        );
+        Ok(())
+    }
+}
+
+/// Products, orders for `customer_ids` and their items. The same ids,
+/// options and `seed` always give the same dataset.
+pub fn generate(
+    customer_ids: &[i32],
+    options: &ShopOptions,
+    seed: Option<u64>,
+) -> anyhow::Result<Shop> {
+    options.validate()?;
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let products = generate_products(options.products, &mut rng);
+    if products.is_empty() {
+        return Ok(Shop {
+            products,
+            ..Shop::default()
+        });
+    }
+
+    // Zipf-like popularity over a shuffled ranking, so the best sellers are
+    // not simply the lowest ids.
+    let mut ranking: Vec<usize> = (0..products.len()).collect();
+    ranking.shuffle(&mut rng);
+    let mut weights = vec![0.0; products.len()];
+    for (rank, &index) in ranking.iter().enumerate() {
+        weights[index] = 1.0 / (rank + 1) as f64;
+    }
+    let popularity = WeightedIndex::new(&weights).expect("weights are positive");
+    let statuses = WeightedIndex::new(STATUSES.map(|(_, w)| w)).expect("weights are positive");
+    let quantities = WeightedIndex::new(QUANTITY_WEIGHTS).expect("weights are positive");
+    let max_items = options.max_items_per_order.clamp(1, products.len());
+    let repeat = geometric_ratio(options.orders_per_customer);
+
+    let mut orders = Vec::new();
+    let mut order_items = Vec::new();
+    for &customer_id in customer_ids {
+        let mut count = 0;
+        while rng.gen_bool(repeat) {
+            count += 1;
+        }
+        for _ in 0..count {
+            let order_id = orders.len() as i32 + 1;
+            // Each extra product with probability 0.4: 60% single-item orders.
+            let mut items = 1;
+            while items < max_items && rng.gen_bool(0.4) {
+                items += 1;
+            }
+            let mut chosen = Vec::with_capacity(items);
+            while chosen.len() < items {
+                let index = popularity.sample(&mut rng);
+                if !chosen.contains(&index) {
+                    chosen.push(index);
+                }
+            }
+            let mut total = 0.0;
+            for index in chosen {
+                let product = &products[index];
+                let quantity = quantities.sample(&mut rng) as i32 + 1;
+                total += product.price * quantity as f64;
+                order_items.push(OrderItem {
+                    id: order_items.len() as i32 + 1,
+                    order_id,
+                    product_id: product.id,
+                    quantity,
+                    unit_price: product.price,
+                });
+            }
+            orders.push(Order {
+                id: order_id,
+                customer_id,
+                ordered_at: rng.gen_range(ORDERS_FROM..=ORDERS_UNTIL),
+                status: STATUSES[statuses.sample(&mut rng)].0.to_string(),
+                total: cents(total),
+            });
+        }
+    }
+
+    Ok(Shop {
+        products,
+        orders,
+        order_items,
+    })
+}
+
+/// `q` such that a geometric count `P(k) = (1 - q) q^k` has mean `mean`.
+fn geometric_ratio(mean: f64) -> f64 {
+    mean / (1.0 + mean)
+}
+
+fn generate_products(count: usize, rng: &mut StdRng) -> Vec<Product> {
+    (0..count)
+        .map(|i| {
+            let (category, low, high) = CATEGORIES[rng.gen_range(0..CATEGORIES.len())];
+            let nouns = NOUNS
+                .iter()
+                .find(|(c, _)| *c == category)
+                .map(|(_, nouns)| nouns)
+                .expect("every category has nouns");
+            let name = format!(
+                "{} {}",
+                ADJECTIVES[rng.gen_range(0..ADJECTIVES.len())],
+                nouns[rng.gen_range(0..nouns.len())]
+            );
+            Product {
+                id: i as i32 + 1,
+                sku: format!("SKU-{:06}", i + 1),
+                name,
+                category: category.to_string(),
+                price: cents(rng.gen_range(low..=high) as f64 - 0.01),
+            }
+        })
+        .collect()
+}
+
+fn cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Frame of `products`: `id, sku, name, category, price`.
+pub fn products_frame(products: &[Product]) -> PolarsResult<DataFrame> {
+    df! {
+        "id" => products.iter().map(|p| p.id).collect::<Vec<_>>(),
+        "sku" => products.iter().map(|p| p.sku.as_str()).collect::<Vec<_>>(),
+        "name" => products.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+        "category" => products.iter().map(|p| p.category.as_str()).collect::<Vec<_>>(),
+        "price" => products.iter().map(|p| p.price).collect::<Vec<_>>(),
+    }
+}
+
+/// Frame of `orders`: `id, customer_id, ordered_at, status, total`.
+pub fn orders_frame(orders: &[Order]) -> PolarsResult<DataFrame> {
+    let ordered_at = Series::new(
+        "ordered_at".into(),
+        orders
+            .iter()
+            .map(|o| o.ordered_at * 1_000_000)
+            .collect::<Vec<_>>(),
+    )
+    .cast(&DataType::Datetime(TimeUnit::Microseconds, None))?;
+    let mut df = df! {
+        "id" => orders.iter().map(|o| o.id).collect::<Vec<_>>(),
+        "customer_id" => orders.iter().map(|o| o.customer_id).collect::<Vec<_>>(),
+        "status" => orders.iter().map(|o| o.status.as_str()).collect::<Vec<_>>(),
+        "total" => orders.iter().map(|o| o.total).collect::<Vec<_>>(),
+    }?;
+    df.insert_column(2, ordered_at)?;
+    Ok(df)
+}
+
+/// Frame of `order_items`: `id, order_id, product_id, quantity, unit_price`.
+pub fn order_items_frame(items: &[OrderItem]) -> PolarsResult<DataFrame> {
+    df! {
+        "id" => items.iter().map(|i| i.id).collect::<Vec<_>>(),
+        "order_id" => items.iter().map(|i| i.order_id).collect::<Vec<_>>(),
+        "product_id" => items.iter().map(|i| i.product_id).collect::<Vec<_>>(),
+        "quantity" => items.iter().map(|i| i.quantity).collect::<Vec<_>>(),
+        "unit_price" => items.iter().map(|i| i.unit_price).collect::<Vec<_>>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    fn shop(seed: u64) -> Shop {
+        let customer_ids: Vec<i32> = (1..=500).collect();
+        generate(&customer_ids, &ShopOptions::default(), Some(seed)).unwrap()
+    }
+
+    #[test]
+    fn test_referential_integrity() {
+        let shop = shop(1);
+        let products: HashMap<i32, &Product> = shop.products.iter().map(|p| (p.id, p)).collect();
+        let orders: HashSet<i32> = shop.orders.iter().map(|o| o.id).collect();
+        assert_eq!(products.len(), 200);
+        assert!(
+            shop.orders
+                .iter()
+                .all(|o| (1..=500).contains(&o.customer_id))
+        );
+
+        let mut totals: HashMap<i32, f64> = HashMap::new();
+        for item in &shop.order_items {
+            assert!(orders.contains(&item.order_id));
+            assert_eq!(item.unit_price, products[&item.product_id].price);
+            *totals.entry(item.order_id).or_default() += item.unit_price * item.quantity as f64;
+        }
+        for order in &shop.orders {
+            assert!((totals[&order.id] - order.total).abs() < 0.005, "{order:?}");
+            assert!((ORDERS_FROM..=ORDERS_UNTIL).contains(&order.ordered_at));
+        }
+    }
+
+    #[test]
+    fn test_cardinalities_are_skewed() {
+        let shop = shop(2);
+        let mean = shop.orders.len() as f64 / 500.0;
+        assert!((2.4..3.6).contains(&mean), "{mean} orders per customer");
+
+        let buyers: HashSet<i32> = shop.orders.iter().map(|o| o.customer_id).collect();
+        assert!(buyers.len() < 450, "some customers never order");
+
+        let mut sales: HashMap<i32, usize> = HashMap::new();
+        for item in &shop.order_items {
+            *sales.entry(item.product_id).or_default() += 1;
+        }
+        let mut counts: Vec<usize> = sales.into_values().collect();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        assert!(counts[0] > 10 * counts[counts.len() / 2], "{counts:?}");
+
+        let items_per_order = shop.order_items.len() as f64 / shop.orders.len() as f64;
+        assert!((1.3..2.0).contains(&items_per_order), "{items_per_order}");
+    }
+
+    #[test]
+    fn test_seeded_shop_repeats() {
+        assert_eq!(shop(3), shop(3));
+        assert_ne!(shop(3).orders, shop(4).orders);
+        assert!(
+            generate(&[], &ShopOptions::default(), Some(3))
+                .unwrap()
+                .orders
+                .is_empty()
+        );
+        let none = ShopOptions {
+            products: 0,
+            ..ShopOptions::default()
+        };
+        assert_eq!(generate(&[1, 2], &none, None).unwrap(), Shop::default());
+    }
+
+    #[test]
+    fn test_orders_per_customer_is_checked() {
+        for mean in [f64::NAN, f64::INFINITY, -1.0, MAX_ORDERS_PER_CUSTOMER * 2.0] {
+            let options = ShopOptions {
+                orders_per_customer: mean,
+                ..ShopOptions::default()
+            };
+            assert!(options.validate().is_err(), "{mean}");
+            assert!(generate(&[1], &options, Some(1)).is_err(), "{mean}");
+        }
+        let none = ShopOptions {
+            orders_per_customer: 0.0,
+            ..ShopOptions::default()
+        };
+        assert!(generate(&[1, 2], &none, Some(1)).unwrap().orders.is_empty());
+    }
+
+    #[test]
+    fn test_frames() {
+        let shop = shop(5);
+        let orders = orders_frame(&shop.orders).unwrap();
+        assert_eq!(
+            orders.get_column_names_str(),
+            ["id", "customer_id", "ordered_at", "status", "total"]
+        );
+        assert!(matches!(
+            orders.column("ordered_at").unwrap().dtype(),
+            DataType::Datetime(..)
+        ));
+        assert_eq!(products_frame(&shop.products).unwrap().height(), 200);
+        assert_eq!(
+            order_items_frame(&shop.order_items).unwrap().height(),
+            shop.order_items.len()
+        );
+    }
+}
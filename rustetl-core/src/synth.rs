@@ -0,0 +1,716 @@
+//! Synthetic data for arbitrary tables, described by a [`TableSpec`].
+//!
+//! A spec lists the columns in table order, each with a [`Generator`], an
+//! optional Postgres `type`, a `null_ratio` and a `unique` flag. Like
+//! policies, specs are TOML or JSON so Windmill scripts can take them as a
+//! parameter:
+//!
+//! ```toml
+//! primary_key = ["id"]
+//!
+//! [[columns]]
+//! name = "id"
+//! generator = "sequence"
+//! type = "integer"
+//!
+//! [[columns]]
+//! name = "email"
+//! generator = "fake"
+//! faker = "email"
+//! unique = true
+//!
+//! [[columns]]
+//! name = "age"            # CHECK (age >= 18 AND age <= 100)
+//! generator = "int"
+//! min = 18
+//! max = 100
+//! type = "integer"
+//! null_ratio = 0.1
+//! ```
+//!
+//! [`TableSpec::generate`] builds a `DataFrame`, which
+//! `db::postgres::write_dataframe` / `db::sqlx::write_dataframe` load into
+//! Postgres. The same spec, row count and seed always give the same frame.
+
+use crate::faker::FakeKind;
+use anyhow::{Context, bail, ensure};
+use chrono::{NaiveDate, NaiveDateTime};
+use polars::prelude::*;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Draws per value before a `unique` column gives up.
+const MAX_ATTEMPTS: usize = 1000;
+
+/// How the values of one column are drawn. Ranges are inclusive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "generator", rename_all = "snake_case")]
+pub enum Generator {
+    /// `start`, `start + step`, ... by row number.
+    Sequence {
+        #[serde(default = "default_one")]
+        start: i64,
+        #[serde(default = "default_one")]
+        step: i64,
+    },
+    /// Uniform integers.
+    Int { min: i64, max: i64 },
+    /// Uniform floats, rounded to `decimals` if given.
+    Float {
+        min: f64,
+        max: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        decimals: Option<u32>,
+    },
+    /// Normally distributed floats, clamped to `min`/`max` if given.
+    Normal {
+        mean: f64,
+        std_dev: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        decimals: Option<u32>,
+    },
+    /// `true` with the given probability.
+    Bool {
+        #[serde(default = "default_probability")]
+        probability: f64,
+    },
+    /// One of `values`, uniformly or by `weights`.
+    Choice {
+        values: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weights: Option<Vec<f64>>,
+    },
+    /// A fake name, email, phone, ...
+    Fake { faker: FakeKind },
+    /// `pattern` with each `#` replaced by a digit and each `?` by an
+    /// uppercase letter, e.g. `ORD-#####`.
+    Pattern { pattern: String },
+    /// Uniform dates between `YYYY-MM-DD` bounds.
+    Date { min: String, max: String },
+    /// Uniform timestamps (to the second) between `YYYY-MM-DD[ HH:MM:SS]`
+    /// bounds.
+    Timestamp { min: String, max: String },
+}
+
+fn default_one() -> i64 {
+    1
+}
+
+fn default_probability() -> f64 {
+    0.5
+}
+
+/// Postgres type a column is stored as, when it differs from the
+/// generator's natural one (`int`/`sequence` → `bigint`, `float`/`normal` →
+/// `double`, text generators → `text`, `bool`, `date`, `timestamp`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    Smallint,
+    Integer,
+    Bigint,
+    Real,
+    #[serde(alias = "double_precision")]
+    Double,
+    Text,
+    Boolean,
+    Date,
+    Timestamp,
+}
+
+impl ColumnType {
+    pub fn dtype(self) -> DataType {
+        match self {
+            ColumnType::Smallint => DataType::Int16,
+            ColumnType::Integer => DataType::Int32,
+            ColumnType::Bigint => DataType::Int64,
+            ColumnType::Real => DataType::Float32,
+            ColumnType::Double => DataType::Float64,
+            ColumnType::Text => DataType::String,
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::Date => DataType::Date,
+            ColumnType::Timestamp => DataType::Datetime(TimeUnit::Microseconds, None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSpec {
+    pub name: String,
+    #[serde(flatten)]
+    pub generator: Generator,
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub column_type: Option<ColumnType>,
+    /// Share of rows left null, from 0 to 1.
+    #[serde(default)]
+    pub null_ratio: f64,
+    /// No two non-null values are equal.
+    #[serde(default)]
+    pub unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSpec {
+    /// Written as the table's primary key. A single key column is
+    /// generated as `unique`.
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    pub columns: Vec<ColumnSpec>,
+}
+
+impl TableSpec {
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let spec: TableSpec = toml::from_str(text).context("invalid TOML table spec")?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let spec: TableSpec = serde_json::from_str(text).context("invalid JSON table spec")?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Parse a spec given as a Windmill parameter: JSON if it starts with
+    /// `{`, TOML otherwise.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Self::from_json(text)
+        } else {
+            Self::from_toml(text)
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(!self.columns.is_empty(), "table spec has no columns");
+        let mut names = HashSet::new();
+        for column in &self.columns {
+            let name = &column.name;
+            ensure!(
+                !name.trim().is_empty(),
+                "table spec has a column without a name"
+            );
+            ensure!(
+                names.insert(name.as_str()),
+                "column `{name}` is listed twice"
+            );
+            ensure!(
+                (0.0..=1.0).contains(&column.null_ratio),
+                "column `{name}`: null_ratio must be between 0 and 1, got {}",
+                column.null_ratio
+            );
+            column
+                .generator
+                .validate()
+                .with_context(|| format!("column `{name}`"))?;
+        }
+        for key in &self.primary_key {
+            let Some(column) = self.columns.iter().find(|c| &c.name == key) else {
+                bail!("primary key column `{key}` is not in the spec");
+            };
+            ensure!(
+                column.null_ratio == 0.0,
+                "primary key column `{key}` must have null_ratio 0"
+            );
+        }
+        Ok(())
+    }
+
+    /// `rows` rows of synthetic data. Without a `seed` every call differs.
+    pub fn generate(&self, rows: usize, seed: Option<u64>) -> anyhow::Result<DataFrame> {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| {
+                // One stream per column, so editing a column leaves the
+                // others' values alone.
+                let mut column_rng = StdRng::seed_from_u64(rng.r#gen());
+                let unique = column.unique || self.primary_key == [column.name.as_str()];
+                column
+                    .generate(rows, unique, &mut column_rng)
+                    .with_context(|| format!("column `{}`", column.name))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(DataFrame::new(columns)?)
+    }
+}
+
+impl ColumnSpec {
+    fn generate(&self, rows: usize, unique: bool, rng: &mut StdRng) -> anyhow::Result<Column> {
+        let sampler = Sampler::new(&self.generator)?;
+        let mut seen = HashSet::new();
+        let mut values = Vec::with_capacity(rows);
+        for row in 0..rows {
+            if self.null_ratio > 0.0 && rng.gen_bool(self.null_ratio) {
+                values.push(None);
+                continue;
+            }
+            let mut attempts = 0;
+            let value = loop {
+                let value = sampler.sample(row, rng)?;
+                if !unique || seen.insert(value.key()) {
+                    break value;
+                }
+                attempts += 1;
+                ensure!(
+                    attempts < MAX_ATTEMPTS,
+                    "could not draw {rows} unique values; widen the range or lower the row count"
+                );
+            };
+            values.push(Some(value));
+        }
+
+        let name = PlSmallStr::from(self.name.as_str());
+        let series = sampler.series(name, values)?;
+        match self.column_type {
+            Some(column_type) => series
+                .strict_cast(&column_type.dtype())
+                .with_context(|| format!("values do not fit type {column_type:?}")),
+            None => Ok(series),
+        }
+        .map(Column::from)
+    }
+}
+
+impl Generator {
+    fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            Generator::Int { min, max } => ensure!(min <= max, "min {min} is above max {max}"),
+            Generator::Float { min, max, .. } => {
+                ensure!(
+                    min.is_finite() && max.is_finite() && min <= max,
+                    "min {min} and max {max} must be finite with min <= max"
+                )
+            }
+            Generator::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+                ..
+            } => {
+                ensure!(
+                    mean.is_finite() && std_dev.is_finite() && *std_dev >= 0.0,
+                    "normal needs a finite mean and std_dev >= 0"
+                );
+                if let (Some(min), Some(max)) = (min, max) {
+                    ensure!(min <= max, "min {min} is above max {max}");
+                }
+            }
+            Generator::Bool { probability } => ensure!(
+                (0.0..=1.0).contains(probability),
+                "probability must be between 0 and 1, got {probability}"
+            ),
+            Generator::Choice { values, weights } => {
+                ensure!(!values.is_empty(), "choice needs at least one value");
+                if let Some(weights) = weights {
+                    ensure!(
+                        weights.len() == values.len(),
+                        "choice has {} values but {} weights",
+                        values.len(),
+                        weights.len()
+                    );
+                    WeightedIndex::new(weights).context("invalid choice weights")?;
+                }
+            }
+            Generator::Date { min, max } => {
+                ensure!(
+                    parse_date(min)? <= parse_date(max)?,
+                    "min {min} is after max {max}"
+                )
+            }
+            Generator::Timestamp { min, max } => ensure!(
+                parse_timestamp(min)? <= parse_timestamp(max)?,
+                "min {min} is after max {max}"
+            ),
+            Generator::Sequence { .. } | Generator::Fake { .. } | Generator::Pattern { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// A [`Generator`] with its bounds parsed.
+enum Sampler<'a> {
+    Sequence(i64, i64),
+    Int(i64, i64),
+    Float(f64, f64, Option<u32>),
+    Normal(f64, f64, f64, f64, Option<u32>),
+    Bool(f64),
+    Choice(&'a [String], Option<WeightedIndex<f64>>),
+    Fake(FakeKind),
+    Pattern(&'a str),
+    Date(i32, i32),
+    Timestamp(i64, i64),
+}
+
+/// One drawn value.
+enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+/// Hashable form of a [`Value`] for uniqueness checks.
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    fn key(&self) -> Key {
+        match self {
+            Value::Int(v) => Key::Int(*v),
+            Value::Float(v) => Key::Int(v.to_bits() as i64),
+            Value::Bool(v) => Key::Int(*v as i64),
+            Value::Str(v) => Key::Str(v.clone()),
+        }
+    }
+}
+
+impl<'a> Sampler<'a> {
+    fn new(generator: &'a Generator) -> anyhow::Result<Self> {
+        generator.validate()?;
+        Ok(match generator {
+            Generator::Sequence { start, step } => Sampler::Sequence(*start, *step),
+            Generator::Int { min, max } => Sampler::Int(*min, *max),
+            Generator::Float { min, max, decimals } => Sampler::Float(*min, *max, *decimals),
+            Generator::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+                decimals,
+            } => Sampler::Normal(
+                *mean,
+                *std_dev,
+                min.unwrap_or(f64::NEG_INFINITY),
+                max.unwrap_or(f64::INFINITY),
+                *decimals,
+            ),
+            Generator::Bool { probability } => Sampler::Bool(*probability),
+            Generator::Choice { values, weights } => Sampler::Choice(
+                values,
+                weights.as_ref().map(WeightedIndex::new).transpose()?,
+            ),
+            Generator::Fake { faker } => Sampler::Fake(*faker),
+            Generator::Pattern { pattern } => Sampler::Pattern(pattern),
+            Generator::Date { min, max } => Sampler::Date(parse_date(min)?, parse_date(max)?),
+            Generator::Timestamp { min, max } => {
+                Sampler::Timestamp(parse_timestamp(min)?, parse_timestamp(max)?)
+            }
+        })
+    }
+
+    fn sample(&self, row: usize, rng: &mut StdRng) -> anyhow::Result<Value> {
+        Ok(match self {
+            Sampler::Sequence(start, step) => Value::Int(
+                i64::try_from(row)
+                    .ok()
+                    .and_then(|row| step.checked_mul(row))
+                    .and_then(|offset| start.checked_add(offset))
+                    .with_context(|| {
+                        format!("sequence from {start} by {step} overflows at row {row}")
+                    })?,
+            ),
+            Sampler::Int(min, max) => Value::Int(rng.gen_range(*min..=*max)),
+            Sampler::Float(min, max, decimals) => {
+                Value::Float(round(rng.gen_range(*min..=*max), *decimals))
+            }
+            Sampler::Normal(mean, std_dev, min, max, decimals) => {
+                // Box-Muller
+                let u: f64 = 1.0 - rng.r#gen::<f64>();
+                let v: f64 = rng.r#gen();
+                let z = (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+                Value::Float(round((mean + std_dev * z).clamp(*min, *max), *decimals))
+            }
+            Sampler::Bool(probability) => Value::Bool(rng.gen_bool(*probability)),
+            Sampler::Choice(values, weights) => {
+                let index = match weights {
+                    Some(weights) => weights.sample(rng),
+                    None => rng.gen_range(0..values.len()),
+                };
+                Value::Str(values[index].clone())
+            }
+            Sampler::Fake(kind) => Value::Str(kind.generate_with(rng)),
+            Sampler::Pattern(pattern) => Value::Str(
+                pattern
+                    .chars()
+                    .map(|c| match c {
+                        '#' => char::from(b'0' + rng.gen_range(0..10)),
+                        '?' => char::from(b'A' + rng.gen_range(0..26)),
+                        c => c,
+                    })
+                    .collect(),
+            ),
+            Sampler::Date(min, max) => Value::Int(rng.gen_range(*min..=*max) as i64),
+            Sampler::Timestamp(min, max) => Value::Int(rng.gen_range(*min..=*max) * 1_000_000),
+        })
+    }
+
+    /// The values as a series of the generator's natural type.
+    fn series(&self, name: PlSmallStr, values: Vec<Option<Value>>) -> PolarsResult<Series> {
+        let ints = || {
+            values
+                .iter()
+                .map(|v| match v {
+                    Some(Value::Int(v)) => Some(*v),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        Ok(match self {
+            Sampler::Sequence(..) | Sampler::Int(..) => Series::new(name, ints()),
+            Sampler::Float(..) | Sampler::Normal(..) => Series::new(
+                name,
+                values
+                    .iter()
+                    .map(|v| match v {
+                        Some(Value::Float(v)) => Some(*v),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Sampler::Bool(_) => Series::new(
+                name,
+                values
+                    .iter()
+                    .map(|v| match v {
+                        Some(Value::Bool(v)) => Some(*v),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Sampler::Choice(..) | Sampler::Fake(_) | Sampler::Pattern(_) => Series::new(
+                name,
+                values
+                    .into_iter()
+                    .map(|v| match v {
+                        Some(Value::Str(v)) => Some(v),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Sampler::Date(..) => Series::new(name, ints()).cast(&DataType::Date)?,
+            Sampler::Timestamp(..) => {
+                Series::new(name, ints()).cast(&DataType::Datetime(TimeUnit::Microseconds, None))?
+            }
+        })
+    }
+}
+
+fn round(value: f64, decimals: Option<u32>) -> f64 {
+    match decimals {
+        Some(decimals) => {
+            let scale = 10f64.powi(decimals as i32);
+            (value * scale).round() / scale
+        }
+        None => value,
+    }
+}
+
+/// Days since 1970-01-01.
+fn parse_date(text: &str) -> anyhow::Result<i32> {
+    let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+        .with_context(|| format!("invalid date `{text}`, expected YYYY-MM-DD"))?;
+    Ok((date - NaiveDate::default()).num_days() as i32)
+}
+
+/// Seconds since 1970-01-01 00:00:00.
+fn parse_timestamp(text: &str) -> anyhow::Result<i64> {
+    let text = text.trim();
+    let timestamp = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .with_context(|| format!("invalid timestamp `{text}`, expected YYYY-MM-DD[ HH:MM:SS]"))?;
+    Ok(timestamp.and_utc().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> TableSpec {
+        TableSpec::parse(
+            r#"
+            primary_key = ["id"]
+
+            [[columns]]
+            name = "id"
+            generator = "sequence"
+            start = 100
+            type = "integer"
+
+            [[columns]]
+            name = "age"
+            generator = "int"
+            min = 18
+            max = 100
+            type = "smallint"
+            null_ratio = 0.2
+
+            [[columns]]
+            name = "code"
+            generator = "pattern"
+            pattern = "C-##"
+            unique = true
+
+            [[columns]]
+            name = "tier"
+            generator = "choice"
+            values = ["gold", "silver"]
+            weights = [1, 0]
+
+            [[columns]]
+            name = "joined"
+            generator = "date"
+            min = "2024-02-28"
+            max = "2024-03-01"
+
+            [[columns]]
+            name = "score"
+            generator = "normal"
+            mean = 50
+            std_dev = 20
+            min = 0
+            max = 100
+            decimals = 1
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_generate_follows_spec() {
+        let df = spec().generate(90, Some(7)).unwrap();
+        assert_eq!(
+            df.get_column_names_str(),
+            ["id", "age", "code", "tier", "joined", "score"]
+        );
+        assert_eq!(df.height(), 90);
+
+        let ids = df.column("id").unwrap().i32().unwrap();
+        assert_eq!((ids.get(0), ids.get(89)), (Some(100), Some(189)));
+
+        let ages = df.column("age").unwrap().i16().unwrap();
+        assert!(ages.into_iter().flatten().all(|a| (18..=100).contains(&a)));
+        let nulls = ages.null_count();
+        assert!((5..=35).contains(&nulls), "{nulls} nulls");
+
+        let codes = df.column("code").unwrap();
+        assert_eq!(codes.as_materialized_series().n_unique().unwrap(), 90);
+        assert!(
+            codes
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .all(|c| c.len() == 4)
+        );
+
+        let tiers = df.column("tier").unwrap().str().unwrap();
+        assert!(tiers.into_no_null_iter().all(|t| t == "gold"));
+
+        let joined = df.column("joined").unwrap().date().unwrap();
+        let days = parse_date("2024-02-28").unwrap()..=parse_date("2024-03-01").unwrap();
+        assert!(joined.into_no_null_iter().all(|d| days.contains(&d)));
+        assert_eq!(joined.n_unique().unwrap(), 3);
+
+        let scores = df.column("score").unwrap().f64().unwrap();
+        assert!(
+            scores
+                .into_no_null_iter()
+                .all(|s| (0.0..=100.0).contains(&s))
+        );
+    }
+
+    #[test]
+    fn test_seed_repeats() {
+        let spec = spec();
+        let a = spec.generate(50, Some(1)).unwrap();
+        assert!(a.equals_missing(&spec.generate(50, Some(1)).unwrap()));
+        assert!(!a.equals_missing(&spec.generate(50, Some(2)).unwrap()));
+    }
+
+    #[test]
+    fn test_unique_runs_out() {
+        let spec = TableSpec::parse(
+            r#"{"columns": [{"name": "flag", "generator": "int", "min": 0, "max": 1, "unique": true}]}"#,
+        )
+        .unwrap();
+        assert!(spec.generate(2, Some(0)).is_ok());
+        let err = spec.generate(3, Some(0)).unwrap_err();
+        assert!(format!("{err:#}").contains("column `flag`"));
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        let column = |extra: &str| format!("[[columns]]\nname = \"x\"\n{extra}");
+        for bad in [
+            column("generator = \"int\"\nmin = 5\nmax = 1"),
+            column("generator = \"bool\"\nnull_ratio = 1.5"),
+            column("generator = \"choice\"\nvalues = []"),
+            column("generator = \"date\"\nmin = \"2024-02-30\"\nmax = \"2024-03-01\""),
+            column("generator = \"fake\"\nfaker = \"unicorn\""),
+            format!(
+                "primary_key = [\"y\"]\n{}",
+                column("generator = \"sequence\"")
+            ),
+        ] {
+            assert!(TableSpec::parse(&bad).is_err(), "{bad}");
+        }
+
+        let overflow = TableSpec::parse(&column(
+            "generator = \"int\"\nmin = 0\nmax = 100000\ntype = \"smallint\"",
+        ))
+        .unwrap();
+        assert!(overflow.generate(100, Some(0)).is_err());
+
+        let sequence = TableSpec::parse(&column(&format!(
+            "generator = \"sequence\"\nstart = {}\nstep = 1",
+            i64::MAX - 1
+        )))
+        .unwrap();
+        assert!(sequence.generate(2, Some(0)).is_ok());
+        let err = sequence.generate(3, Some(0)).unwrap_err();
+        assert!(format!("{err:#}").contains("overflows at row 2"));
+    }
+
+    #[test]
+    fn test_bundled_customers_spec() {
+        let text = include_str!("../../windmill-scripts/04-database/specs/customers.toml");
+        let df = TableSpec::parse(text)
+            .unwrap()
+            .generate(200, Some(3))
+            .unwrap();
+        let schema = df.schema();
+        assert_eq!(schema.get("id"), Some(&DataType::Int32));
+        assert_eq!(schema.get("age"), Some(&DataType::Int32));
+        assert_eq!(
+            df.column("email")
+                .unwrap()
+                .as_materialized_series()
+                .n_unique()
+                .unwrap(),
+            200
+        );
+        let ages = df.column("age").unwrap().i32().unwrap();
+        assert!(ages.into_iter().flatten().all(|a| (18..=100).contains(&a)));
+    }
+}
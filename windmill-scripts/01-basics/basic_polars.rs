@@ -7,15 +7,37 @@
 //! 4. Windmill will automatically handle dependencies
 //!
 //! Dependencies (add in Windmill UI):
-//! polars = { version = "0.44", features = ["lazy", "json"] }
-//! serde_json = "1.0"
+//! polars = { version = "0.44", features = ["lazy", "json", "parquet", "ipc"] }
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false }
 
 use polars::prelude::*;
-use serde_json::{json, Value};
+use rustetl_core::PipelineResult;
+use rustetl_core::io::{self, FileOptions};
+use rustetl_core::records::{self, Orient};
 
 /// Basic Polars operations
-pub fn main() -> Result<Value, String> {
+///
+/// `orient`: return `data` as `records` (default, one object per row) or
+/// `columns` (one array per column). `row_limit`: rows to return (default 1000).
+/// `output_path`: also write the result to a `.parquet`, `.arrow`/`.feather`
+/// or `.csv` file, tuned by `compression` and `row_group_size`.
+pub fn main(
+    orient: Option<String>,
+    row_limit: Option<usize>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+) -> Result<PipelineResult, String> {
+    let mut run = PipelineResult::new("basic_polars");
+    let file_options =
+        FileOptions::parse(compression.as_deref(), row_group_size).map_err(|e| e.to_string())?;
+    let orient: Orient = match orient {
+        Some(orient) => orient.parse().map_err(|e: anyhow::Error| e.to_string())?,
+        None => Orient::Records,
+    };
+
     // Create a simple DataFrame
+    run.stage("create");
     let df = df! {
         "name" => ["Alice", "Bob", "Charlie", "Diana", "Eve"],
         "age" => [25, 30, 35, 28, 42],
@@ -26,9 +48,11 @@ pub fn main() -> Result<Value, String> {
 
     println!("Original DataFrame:");
     println!("{}", df);
+    run.rows_in = df.height();
 
     // Transform: Filter employees over 25 and group by department
-    let result = df
+    run.stage("transform");
+    let mut result = df
         .lazy()
         .filter(col("age").gt(25))
         .group_by([col("department")])
@@ -37,20 +61,28 @@ pub fn main() -> Result<Value, String> {
             col("salary").mean().alias("avg_salary"),
             col("age").mean().alias("avg_age"),
         ])
-        .sort("avg_salary", SortOptions::default().with_order_descending(true))
+        .sort(["avg_salary"], SortMultipleOptions::default().with_order_descending(true))
         .collect()
         .map_err(|e| e.to_string())?;
 
     println!("\nAggregated Results:");
     println!("{}", result);
 
-    // Convert to JSON for Windmill output
-    let json_str = format!("{}", result);
+    if let Some(path) = &output_path {
+        run.stage("load");
+        let written =
+            io::write_file(&mut result, path, &file_options).map_err(|e| e.to_string())?;
+        println!("Wrote {} rows to {} ({} bytes)", written.rows, written.path, written.bytes);
+        run.artifact(written);
+    }
+
+    // Return typed rows the next flow step can read
+    run.output(&result);
+    run.data = Some(records::to_json(
+        &result,
+        orient,
+        Some(row_limit.unwrap_or(records::DEFAULT_ROW_LIMIT)),
+    ));
 
-    Ok(json!({
-        "status": "success",
-        "row_count": result.height(),
-        "columns": result.get_column_names(),
-        "preview": json_str,
-    }))
+    Ok(run.finish())
 }
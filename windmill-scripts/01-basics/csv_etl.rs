@@ -1,36 +1,99 @@
 //! CSV ETL Pipeline - Read, Transform, Export
 //!
 //! Dependencies:
-//! polars = { version = "0.44", features = ["lazy", "csv", "json"] }
-//! serde = { version = "1.0", features = ["derive"] }
-//! serde_json = "1.0"
+//! polars = { version = "0.44", features = ["lazy", "csv", "json", "parquet", "ipc"] }
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false }
 
 use polars::prelude::*;
-use serde::{Deserialize, Serialize};
+use rustetl_core::{Contract, PipelineResult};
+use rustetl_core::ingest::{self, BadRows};
+use rustetl_core::io::{self, FileOptions};
+use rustetl_core::records;
 
-#[derive(Serialize, Deserialize)]
-pub struct EtlResult {
-    total_rows: usize,
-    filtered_rows: usize,
-    summary: String,
-}
+/// Columns the transform below reads. Pass `contract` to check your own.
+const DEFAULT_CONTRACT: &str = r#"
+[[columns]]
+name = "age"
+type = "integer"
+nullable = false
+min = 0
+
+[[columns]]
+name = "salary"
+type = "float"
+min = 0
+"#;
 
 /// ETL Pipeline: Read CSV data, transform, and return results
 ///
-/// In Windmill, you can pass CSV content as a parameter
-pub fn main(csv_content: String) -> Result<EtlResult, String> {
-    // Extract: Parse CSV from string
-    let df = CsvReadOptions::default()
-        .try_into_reader_with_file_handle(std::io::Cursor::new(csv_content.as_bytes()))
-        .map_err(|e| e.to_string())?
-        .finish()
+/// In Windmill, you can pass CSV content as a parameter, or read a
+/// `.csv`, `.parquet` or `.arrow`/`.feather` file with `input_path`.
+/// Set `output_path` to also write the result; its extension picks the
+/// format, and `compression`/`row_group_size` tune Parquet output.
+/// The result comes back in `data` as typed records (first 1000 rows).
+///
+/// The input is checked against `contract` (TOML or JSON, see
+/// `rustetl_core::contract`) before the transform; on violations the run
+/// stops with `status: "error"` and the report in `details.contract`.
+/// With `bad_rows: quarantine`, rows that break it (or have the wrong
+/// number of fields, or unparsable numbers) are set aside instead and come
+/// back with their line number and reason in `details.quarantine`.
+pub fn main(
+    csv_content: Option<String>,
+    input_path: Option<String>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    contract: Option<String>,
+    bad_rows: Option<String>,
+) -> Result<PipelineResult, String> {
+    let mut run = PipelineResult::new("csv_etl");
+    let file_options =
+        FileOptions::parse(compression.as_deref(), row_group_size).map_err(|e| e.to_string())?;
+    let contract = Contract::parse(contract.as_deref().unwrap_or(DEFAULT_CONTRACT))
         .map_err(|e| e.to_string())?;
+    let bad_rows: BadRows = match bad_rows {
+        Some(mode) => mode.parse().map_err(|e: anyhow::Error| e.to_string())?,
+        None => BadRows::default(),
+    };
+
+    // Extract: Parse CSV from string or read the input file
+    run.stage("extract");
+    let ingested = ingest::read_input(
+        csv_content.as_deref(),
+        input_path.as_deref(),
+        Some(&contract),
+        bad_rows,
+    )
+    .map_err(|e| e.to_string())?;
+    let df = ingested.data;
 
     println!("Loaded {} rows", df.height());
-    let total_rows = df.height();
+    run.rows_in = ingested.report.rows_read;
+    if bad_rows == BadRows::Quarantine {
+        let quarantined = ingested.report.rows_quarantined;
+        if quarantined > 0 {
+            println!("Quarantined {} rows:\n{}", quarantined, ingested.quarantine);
+            run.warn(format!("{} rows quarantined", quarantined));
+        }
+        run.detail("ingest", &ingested.report).map_err(|e| e.to_string())?;
+        run.detail("quarantine", records::records(&ingested.quarantine))
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Validate: Check the input before relying on its columns
+    run.stage("validate");
+    let report = contract.check(&df).map_err(|e| e.to_string())?;
+    run.detail("contract", &report).map_err(|e| e.to_string())?;
+    if let Err(err) = report.ensure() {
+        println!("Input does not match the contract: {}", err);
+        run.fail(err.to_string());
+        return Ok(run.finish());
+    }
 
     // Transform: Apply business logic
-    let transformed = df
+    run.stage("transform");
+    let mut transformed = df
         .lazy()
         // Example transformations:
         .filter(col("age").gt(18)) // Filter adults only
@@ -41,18 +104,24 @@ pub fn main(csv_content: String) -> Result<EtlResult, String> {
         .collect()
         .map_err(|e| e.to_string())?;
 
-    let filtered_rows = transformed.height();
-    println!("After filtering: {} rows", filtered_rows);
+    println!("After filtering: {} rows", transformed.height());
+    let dropped = ingested.report.rows_loaded - transformed.height();
+    if dropped > 0 {
+        run.warn(format!("{} rows filtered out (age 18 or under)", dropped));
+    }
 
-    // Load: In Windmill, you can return the data or store it
-    // Here we'll just return a summary
-    let summary = format!("{}", transformed);
+    // Load: Write the result to a file if asked, and return the rows
+    if let Some(path) = &output_path {
+        run.stage("load");
+        let written =
+            io::write_file(&mut transformed, path, &file_options).map_err(|e| e.to_string())?;
+        println!("Wrote {} rows to {} ({} bytes)", written.rows, written.path, written.bytes);
+        run.artifact(written);
+    }
+    println!("{}", transformed);
+    run.output_with_rows(&transformed);
 
-    Ok(EtlResult {
-        total_rows,
-        filtered_rows,
-        summary,
-    })
+    Ok(run.finish())
 }
 
 // Default example for testing
@@ -64,7 +133,7 @@ Bob,17,30000
 Charlie,30,60000
 Diana,22,45000"#;
 
-    match main(sample_csv.to_string()) {
+    match main(Some(sample_csv.to_string()), None, None, None, None, None, None) {
         Ok(result) => println!("{:#?}", result),
         Err(e) => eprintln!("Error: {}", e),
     }
@@ -1,15 +1,37 @@
 //! Advanced Polars Transformations
 //!
 //! Dependencies:
-//! polars = { version = "0.44", features = ["lazy", "dtype-date", "strings"] }
-//! serde_json = "1.0"
+//! polars = { version = "0.44", features = ["lazy", "dtype-date", "strings", "parquet", "ipc"] }
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false }
 
 use polars::prelude::*;
-use serde_json::{json, Value};
+use rustetl_core::PipelineResult;
+use rustetl_core::io::{self, FileOptions};
+use rustetl_core::records::{self, Orient};
 
 /// Advanced data transformations with Polars
-pub fn main() -> Result<Value, String> {
+///
+/// `orient`: return `data` as `records` (default) or `columns`.
+/// `row_limit`: rows to return (default 1000).
+/// `output_path`: also write the result to a `.parquet`, `.arrow`/`.feather`
+/// or `.csv` file, tuned by `compression` and `row_group_size`.
+pub fn main(
+    orient: Option<String>,
+    row_limit: Option<usize>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+) -> Result<PipelineResult, String> {
+    let mut run = PipelineResult::new("advanced_transformations");
+    let file_options =
+        FileOptions::parse(compression.as_deref(), row_group_size).map_err(|e| e.to_string())?;
+    let orient: Orient = match orient {
+        Some(orient) => orient.parse().map_err(|e: anyhow::Error| e.to_string())?,
+        None => Orient::Records,
+    };
+
     // Create sample sales data
+    run.stage("create");
     let df = df! {
         "product" => ["Laptop", "Mouse", "Keyboard", "Laptop", "Mouse", "Monitor"],
         "category" => ["Electronics", "Accessories", "Accessories", "Electronics", "Accessories", "Electronics"],
@@ -21,8 +43,10 @@ pub fn main() -> Result<Value, String> {
 
     println!("Original Data:");
     println!("{}", df);
+    run.rows_in = df.height();
 
     // Complex transformation pipeline
+    run.stage("aggregate");
     let result = df
         .lazy()
         // Add calculated columns
@@ -36,7 +60,7 @@ pub fn main() -> Result<Value, String> {
             col("price").mean().alias("avg_price"),
         ])
         // Sort by revenue descending
-        .sort("total_revenue", SortOptions::default().with_order_descending(true))
+        .sort(["total_revenue"], SortMultipleOptions::default().with_order_descending(true))
         .collect()
         .map_err(|e| e.to_string())?;
 
@@ -44,7 +68,8 @@ pub fn main() -> Result<Value, String> {
     println!("{}", result);
 
     // Window functions example
-    let with_windows = result
+    run.stage("window");
+    let mut with_windows = result
         .lazy()
         .with_column(
             col("total_revenue")
@@ -62,9 +87,20 @@ pub fn main() -> Result<Value, String> {
     println!("\nWith Window Functions:");
     println!("{}", with_windows);
 
-    Ok(json!({
-        "status": "success",
-        "rows": with_windows.height(),
-        "preview": format!("{}", with_windows),
-    }))
+    if let Some(path) = &output_path {
+        run.stage("load");
+        let written =
+            io::write_file(&mut with_windows, path, &file_options).map_err(|e| e.to_string())?;
+        println!("Wrote {} rows to {} ({} bytes)", written.rows, written.path, written.bytes);
+        run.artifact(written);
+    }
+
+    run.output(&with_windows);
+    run.data = Some(records::to_json(
+        &with_windows,
+        orient,
+        Some(row_limit.unwrap_or(records::DEFAULT_ROW_LIMIT)),
+    ));
+
+    Ok(run.finish())
 }
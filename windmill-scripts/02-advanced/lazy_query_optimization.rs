@@ -4,12 +4,26 @@
 //! allows it to optimize the entire query plan before execution
 //!
 //! Dependencies:
-//! polars = { version = "0.44", features = ["lazy", "csv"] }
+//! polars = { version = "0.44", features = ["lazy", "csv", "parquet", "ipc"] }
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false }
 
 use polars::prelude::*;
+use rustetl_core::PipelineResult;
+use rustetl_core::io::{self, FileOptions};
+
+/// `output_path`: also write the top 10 to a `.parquet`, `.arrow`/`.feather`
+/// or `.csv` file, tuned by `compression` and `row_group_size`.
+pub fn main(
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+) -> Result<PipelineResult, String> {
+    let mut run = PipelineResult::new("lazy_query_optimization");
+    let file_options =
+        FileOptions::parse(compression.as_deref(), row_group_size).map_err(|e| e.to_string())?;
 
-pub fn main() -> Result<String, String> {
     // Create a large-ish dataset
+    run.stage("create");
     let n = 1_000_000;
     let df = df! {
         "id" => (1..=n).collect::<Vec<_>>(),
@@ -19,6 +33,7 @@ pub fn main() -> Result<String, String> {
     .map_err(|e| e.to_string())?;
 
     println!("Created DataFrame with {} rows", df.height());
+    run.rows_in = df.height();
 
     // Build a lazy query
     let lazy_query = df
@@ -33,26 +48,31 @@ pub fn main() -> Result<String, String> {
             col("value").sum().alias("total_value"),
             col("value").count().alias("count"),
         ])
-        .sort("total_value", SortOptions::default().with_order_descending(true))
+        .sort(["total_value"], SortMultipleOptions::default().with_order_descending(true))
         .limit(10);
 
     // Show the optimized query plan
+    run.stage("plan");
     let plan = format!("{}", lazy_query.describe_optimized_plan().map_err(|e| e.to_string())?);
     println!("\nOptimized Query Plan:\n{}", plan);
 
     // Execute the query
-    let start = std::time::Instant::now();
-    let result = lazy_query.collect().map_err(|e| e.to_string())?;
-    let elapsed = start.elapsed();
+    run.stage("execute");
+    let mut result = lazy_query.collect().map_err(|e| e.to_string())?;
+    if let Some(path) = &output_path {
+        run.stage("load");
+        let written =
+            io::write_file(&mut result, path, &file_options).map_err(|e| e.to_string())?;
+        println!("Wrote {} rows to {} ({} bytes)", written.rows, written.path, written.bytes);
+        run.artifact(written);
+    }
+    run.output_with_rows(&result);
+    run.detail("optimized_plan", &plan).map_err(|e| e.to_string())?;
+    let run = run.finish();
 
-    println!("\nQuery executed in {:?}", elapsed);
+    println!("\nQuery executed in {:.1} ms", run.stages[2].duration_ms);
     println!("\nTop 10 Results:");
     println!("{}", result);
 
-    Ok(format!(
-        "Processed {} rows in {:?}\nReturned {} results",
-        n,
-        elapsed,
-        result.height()
-    ))
+    Ok(run)
 }
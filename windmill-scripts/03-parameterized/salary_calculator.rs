@@ -1,34 +1,57 @@
 //! Parameterized Salary Calculator with Polars
 //!
 //! This script demonstrates:
-//! - CSV input parameter
+//! - CSV input parameter, or a CSV/Parquet/Arrow IPC file path
 //! - Numeric parameter (raise percentage)
 //! - Optional parameter (age filter)
 //! - Data transformation with Polars
 //! - Summary statistics
+//! - Optional Parquet/Arrow IPC/CSV output file
 //!
 //! ```cargo
 //! [dependencies]
-//! polars = { version = "0.44", features = ["lazy", "csv"] }
+//! polars = { version = "0.44", features = ["lazy", "csv", "parquet", "ipc"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false }
 //! ```
 
-use polars::prelude::*;
-use serde_json::{json, Value};
-use std::io::Cursor;
+use rustetl_core::{Contract, PipelineResult};
+use rustetl_core::ingest::{self, BadRows};
+use rustetl_core::io::{self, FileOptions};
+use rustetl_core::{records, salary};
+use serde_json::json;
 
 /// Process employee data with salary adjustment
 ///
 /// # Parameters in Windmill UI:
-/// - csv_data: Paste CSV data with columns: name, age, department, salary
+/// - csv_data: (Optional) Paste CSV data with columns: name, age, department, salary
 /// - raise_percent: Percentage increase (e.g., 10 for 10% raise)
 /// - min_age: (Optional) Only apply raise to employees older than this age
+/// - input_path: (Optional) Read a .csv, .parquet or .arrow/.feather file instead of csv_data
+/// - output_path: (Optional) Write the result; the extension picks the format
+/// - compression: (Optional) uncompressed, snappy, gzip, lz4 or zstd (default zstd for Parquet)
+/// - row_group_size: (Optional) Rows per Parquet row group
+/// - bad_rows: (Optional) fail (default) or quarantine: load the rows that parse and
+///   return the rest with line number and reason in details.quarantine
+///
+/// The input is checked against `Contract::employees()` first. If it does not
+/// match, the result has `status: "error"` and lists each violation in
+/// `details.contract` instead of failing on the first missing column.
 fn main(
-    csv_data: String,
+    csv_data: Option<String>,
     raise_percent: f64,
     min_age: Option<i32>,
-) -> anyhow::Result<Value> {
+    input_path: Option<String>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+    bad_rows: Option<String>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("salary_calculator");
+    let file_options = FileOptions::parse(compression.as_deref(), row_group_size)?;
+    let bad_rows: BadRows = bad_rows.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+
     println!("📊 Salary Raise Calculator");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("Parameters:");
@@ -36,59 +59,66 @@ fn main(
     println!("  • Min Age Filter: {:?}", min_age.unwrap_or(0));
     println!();
 
-    // Parse CSV from string
-    let df = CsvReadOptions::default()
-        .with_has_header(true)
-        .into_reader_with_file_handle(Cursor::new(csv_data.as_bytes()))
-        .finish()?;
+    // Parse pasted CSV or read the input file, setting bad rows aside if asked
+    run.stage("read");
+    let contract = Contract::employees();
+    let ingested = ingest::read_input(
+        csv_data.as_deref(),
+        input_path.as_deref(),
+        Some(&contract),
+        bad_rows,
+    )?;
+    let df = ingested.data;
 
+    run.rows_in = ingested.report.rows_read;
+    if bad_rows == BadRows::Quarantine {
+        let quarantined = ingested.report.rows_quarantined;
+        if quarantined > 0 {
+            println!("🚧 Quarantined {} of {} rows:", quarantined, run.rows_in);
+            println!("{}", ingested.quarantine);
+            println!();
+            run.warn(format!("{} rows quarantined", quarantined));
+        }
+        run.detail("ingest", &ingested.report)?;
+        run.detail("quarantine", records::records(&ingested.quarantine))?;
+    }
     println!("📥 Original Data ({} rows):", df.height());
     println!("{}", df);
     println!();
 
-    // Build the transformation
-    let mut lazy_df = df.lazy();
+    // Check columns, types and ranges before transforming
+    run.stage("validate");
+    let report = contract.check(&df)?;
+    run.detail("contract", &report)?;
+    if let Err(err) = report.ensure() {
+        println!("❌ Input does not match the employee contract:");
+        for violation in &report.violations {
+            match violation.row {
+                Some(row) => println!("  • row {}, {}: {}", row, violation.column, violation.message),
+                None => println!("  • {}", violation.message),
+            }
+        }
+        run.fail(err.to_string());
+        return Ok(run.finish());
+    }
 
-    // Apply age filter if specified
     if let Some(min) = min_age {
         println!("🔍 Filtering: age > {}", min);
-        lazy_df = lazy_df.filter(col("age").gt(lit(min)));
     }
 
-    // Calculate raise
-    let raise_multiplier = 1.0 + (raise_percent / 100.0);
-
-    // Apply transformations
-    let result = lazy_df
-        .with_columns([
-            col("salary").alias("old_salary"),
-        ])
-        .with_columns([
-            (col("salary") * lit(raise_multiplier)).alias("new_salary"),
-            (col("salary") * lit(raise_percent / 100.0)).alias("raise_amount"),
-        ])
-        .collect()?;
+    // Filter and calculate raise
+    run.stage("transform");
+    let mut result = salary::apply_raise(df, raise_percent, min_age)?;
 
     println!("📤 Transformed Data ({} rows):", result.height());
     println!("{}", result);
     println!();
 
     // Calculate summary statistics
-    let total_old = result
-        .column("old_salary")?
-        .as_materialized_series()
-        .cast(&DataType::Float64)?
-        .sum::<f64>()
-        .unwrap_or(0.0);
-
-    let total_new = result
-        .column("new_salary")?
-        .as_materialized_series()
-        .cast(&DataType::Float64)?
-        .sum::<f64>()
-        .unwrap_or(0.0);
-
-    let total_raise = total_new - total_old;
+    let summary = salary::summarize(&result)?;
+    let total_old = summary.total_old;
+    let total_new = summary.total_new;
+    let total_raise = summary.total_raise;
 
     println!("💰 Summary:");
     println!("  • Total Old Salary: ${:.2}", total_old);
@@ -96,15 +126,25 @@ fn main(
     println!("  • Total Raise Cost: ${:.2}", total_raise);
     println!("  • Average Raise: ${:.2}", total_raise / result.height() as f64);
 
-    // Convert to JSON for Windmill output
-    let columns: Vec<String> = result
-        .get_column_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
+    if let Some(path) = &output_path {
+        run.stage("write");
+        let written = io::write_file(&mut result, path, &file_options)?;
+        println!();
+        println!(
+            "💾 Wrote {} rows to {} ({} bytes)",
+            written.rows, written.path, written.bytes
+        );
+        run.artifact(written);
+    }
+    if result.height() == 0 {
+        run.warn("no employees matched the age filter");
+    }
 
-    Ok(json!({
-        "summary": {
+    // Typed rows and totals for Windmill output
+    run.output_with_rows(&result);
+    run.detail(
+        "summary",
+        json!({
             "total_employees": result.height(),
             "raise_percent": raise_percent,
             "min_age_filter": min_age,
@@ -112,8 +152,8 @@ fn main(
             "total_new_salary": total_new,
             "total_raise_cost": total_raise,
             "average_raise": total_raise / result.height() as f64,
-        },
-        "columns": columns,
-        "preview": format!("{}", result),
-    }))
+        }),
+    )?;
+
+    Ok(run.finish())
 }
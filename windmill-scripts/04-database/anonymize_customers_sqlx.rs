@@ -7,217 +7,239 @@
 //! polars = { version = "0.44", features = ["lazy", "strings"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
-//! sha2 = "0.10"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["sqlx"] }
 //! ```
 
-use sqlx::{PgPool, Row};
-use polars::prelude::*;
-use serde_json::json;
-use sha2::{Sha256, Digest};
-
-fn hash_string(input: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(input.as_bytes());
-    format!("{:x}", hasher.finalize())[..16].to_string()
-}
+use rustetl_core::{PipelineResult, Policy, PrivacyCheck, Pseudonymizer};
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::db::sqlx as db;
+use rustetl_core::db::table::{WriteMode, WriteOptions, staging_table};
+use rustetl_core::db::watermark::Watermark;
+use rustetl_core::pipeline::Artifact;
 
 // Wrapper for Windmill
-fn main(db_host: Option<String>) -> anyhow::Result<serde_json::Value> {
-    tokio::runtime::Runtime::new()?.block_on(async_main(db_host))
+//
+// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+// to the DATABASE_URL env var.
+//
+// `policy`: optional TOML or JSON anonymization policy (see
+// `policies/customers.toml`); defaults to the built-in customers policy.
+// Full runs create `customers_anonymized` from its output columns, which
+// must include `id`; incremental runs upsert into the existing table, so
+// do a full run after changing the policy. An incremental run whose output
+// columns no longer fit the table (e.g. `age` labels into an integer `age`
+// from before ages were bucketed) fails before writing anything.
+//
+// `pseudonym_key`: HMAC secret, ideally a Windmill secret variable
+// (`$var:...`); falls back to the RUSTETL_PSEUDONYM_KEY env var.
+// `pseudonym_key_id`: label stored with every row so keys can be rotated.
+// `hash_length`: pseudonym length in hex characters (default 16).
+// `batch_size`: rows per chunk read, anonymized and written (default 10,000).
+// `incremental`: only anonymize customers added or updated since the last
+// run and upsert them (default false: rebuild the table). Falls back to a
+// full run when `customers_anonymized` does not exist yet. Full runs move
+// the mark too, so the next incremental run skips what they read. Adds the
+// `updated_at` column and trigger to `customers` if it predates them;
+// later runs only check for them, without locking `customers`.
+// Customers written in the last minute wait for the next run.
+//
+// `quasi_identifiers`: columns checked for k-anonymity (default `age`,
+// `salary_bucket`); `min_k`: smallest allowed group of rows sharing them
+// (default 5).
+// `sensitive_column` / `min_l`: also require `min_l` distinct values of
+// this column in every group (l-diversity, default 2).
+// `suppress`: delete the rows of groups that fall short instead of failing
+// the run (default false).
+// Incremental runs check the whole table after the upsert.
+fn main(
+    database: Option<serde_json::Value>,
+    policy: Option<String>,
+    pseudonym_key: Option<String>,
+    pseudonym_key_id: Option<String>,
+    hash_length: Option<usize>,
+    batch_size: Option<usize>,
+    incremental: Option<bool>,
+    quasi_identifiers: Option<Vec<String>>,
+    min_k: Option<usize>,
+    sensitive_column: Option<String>,
+    min_l: Option<usize>,
+    suppress: Option<bool>,
+) -> anyhow::Result<PipelineResult> {
+    let config = PgConfig::resolve(database)?;
+    let check = PrivacyCheck::resolve(quasi_identifiers, min_k, sensitive_column, min_l)?;
+    let mut pseudonymizer = Pseudonymizer::resolve(pseudonym_key, pseudonym_key_id)?;
+    if let Some(length) = hash_length {
+        pseudonymizer = pseudonymizer.with_length(length)?;
+    }
+    tokio::runtime::Runtime::new()?.block_on(async_main(
+        config,
+        policy,
+        pseudonymizer,
+        batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+        incremental.unwrap_or(false),
+        check,
+        suppress.unwrap_or(false),
+    ))
 }
 
-async fn async_main(db_host: Option<String>) -> anyhow::Result<serde_json::Value> {
-    let host = db_host.unwrap_or_else(|| "db".to_string());
+async fn async_main(
+    config: PgConfig,
+    policy: Option<String>,
+    pseudonymizer: Pseudonymizer,
+    batch_size: usize,
+    incremental: bool,
+    check: PrivacyCheck,
+    suppress: bool,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("anonymize_customers_sqlx");
+    let policy = match policy {
+        Some(text) => Policy::parse(&text)?,
+        None => Policy::customers(),
+    };
 
     println!("🔐 Async Anonymization Pipeline");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("  Engine: sqlx + Polars");
-    println!("  Database: shopping");
+    println!("  Database: {}", config);
+    println!("  Pseudonym key: {}", pseudonymizer.key_id());
     println!();
 
-    let database_url = format!("postgres://postgres:changeme@{}/shopping", host);
-
+    run.stage("connect");
     println!("🔌 Connecting...");
-    let pool = PgPool::connect(&database_url).await?;
+    let pool = db::connect(&config).await?;
     println!("  ✓ Connected!");
 
     // Check if source table exists
-    let row = sqlx::query("SELECT EXISTS (SELECT FROM information_schema.tables WHERE table_name = 'customers')")
-        .fetch_one(&pool)
-        .await?;
-
-    let exists: bool = row.try_get(0)?;
-
-    if !exists {
+    if !db::table_exists(&pool, "customers").await? {
         pool.close().await;
-        return Ok(json!({
-              "status": "error",
-              "message": "❌ customers table not found!"
-          }));
+        println!("❌ customers table not found!");
+        run.fail("customers table not found");
+        return Ok(run.finish());
     }
 
-    // Read data
-    println!("\n📖 Reading customer data...");
-    let rows = sqlx::query("SELECT id, name, email, phone, address, age, salary, ssn FROM customers ORDER BY id")
-        .fetch_all(&pool)
-        .await?;
-
-    let total = rows.len();
-    println!("  Found {} records", total);
-
-    if total == 0 {
+    // Full runs read every customer but still move the watermark, so the
+    // next incremental run starts after the rebuild
+    let watermark = Watermark::new("customers_anonymized", "updated_at");
+    let incremental = incremental && db::table_exists(&pool, "customers_anonymized").await?;
+    db::track_updates(&pool, "customers").await?;
+    let mode = if incremental { "incremental" } else { "full" };
+    println!("  Mode: {}", mode);
+    run.detail("mode", mode)?;
+
+    // Stream customers through a server-side cursor; the whole run is one
+    // transaction, so the watermark only moves if every chunk is written
+    run.stage("read");
+    println!("\n📖 Reading customer data in chunks of {}...", batch_size);
+    let mut chunks = if incremental {
+        db::read_customers_since(&pool, "customers", &watermark, batch_size).await?
+    } else {
+        db::read_customers_marked(&pool, "customers", &watermark, batch_size).await?
+    };
+
+    let Some(first) = chunks.next_chunk().await? else {
+        drop(chunks);
         pool.close().await;
-        return Ok(json!({
-              "status": "error",
-              "message": "❌ No data found!"
-          }));
-    }
-
-    // Extract to vectors
-    let mut ids: Vec<i32> = Vec::with_capacity(total);
-    let mut names: Vec<String> = Vec::with_capacity(total);
-    let mut emails: Vec<String> = Vec::with_capacity(total);
-    let mut phones: Vec<String> = Vec::with_capacity(total);
-    let mut addresses: Vec<String> = Vec::with_capacity(total);
-    let mut ages: Vec<i32> = Vec::with_capacity(total);
-    let mut salaries: Vec<i32> = Vec::with_capacity(total);
-    let mut ssns: Vec<String> = Vec::with_capacity(total);
-
-    for row in &rows {
-        ids.push(row.try_get("id")?);
-        names.push(row.try_get("name")?);
-        emails.push(row.try_get("email")?);
-        phones.push(row.try_get::<Option<String>, _>("phone")?.unwrap_or_default());
-        addresses.push(row.try_get::<Option<String>, _>("address")?.unwrap_or_default());
-        ages.push(row.try_get::<Option<i32>, _>("age")?.unwrap_or(0));
-        salaries.push(row.try_get::<Option<i32>, _>("salary")?.unwrap_or(0));
-        ssns.push(row.try_get::<Option<String>, _>("ssn")?.unwrap_or_default());
-    }
-
-    // Create Polars DataFrame
-    let df = df! {
-          "id" => &ids,
-          "name" => &names,
-          "email" => &emails,
-          "phone" => &phones,
-          "address" => &addresses,
-          "age" => &ages,
-          "salary" => &salaries,
-          "ssn" => &ssns,
-      }?;
+        if incremental {
+            println!("\n✅ No new or updated customers since the last run");
+            return Ok(run.finish());
+        }
+        println!("❌ No data found!");
+        run.fail("customers table is empty");
+        return Ok(run.finish());
+    };
 
     println!("\n📊 Original Data (first 3):");
-    println!("{}", df.head(Some(3)));
-
-    // Anonymize
-    println!("\n🎭 Anonymizing...");
-
-    let anonymized_names: Vec<String> = names.iter()
-        .map(|name| format!("Customer_{}", hash_string(name)))
-        .collect();
+    println!("{}", first.head(Some(3)));
 
-    let anonymized_emails: Vec<String> = emails.iter()
-        .map(|email| format!("{}@anonymized.local", hash_string(email)))
-        .collect();
+    // Full runs build a staging table that is swapped in on success;
+    // incremental runs upsert into the live table
+    run.stage("write");
+    let staging = staging_table("customers_anonymized");
+    let upsert = WriteOptions::new(WriteMode::Upsert)
+        .with_primary_key(&["id"])
+        .with_batch_size(batch_size);
 
-    let anonymized_phones: Vec<String> = phones.iter()
-        .map(|_| "***-***-****".to_string())
-        .collect();
-
-    let anonymized_addresses: Vec<String> = addresses.iter()
-        .map(|_| "REDACTED".to_string())
-        .collect();
-
-    let anonymized_ssns: Vec<String> = ssns.iter()
-        .map(|_| "***-**-****".to_string())
-        .collect();
-
-    let salary_buckets: Vec<String> = salaries.iter()
-        .map(|s| {
-            if *s < 50000 { "< $50k".to_string() }
-            else if *s < 75000 { "$50k-$75k".to_string() }
-            else if *s < 100000 { "$75k-$100k".to_string() }
-            else if *s < 125000 { "$100k-$125k".to_string() }
-            else { "> $125k".to_string() }
-        })
-        .collect();
-
-    let anonymized_df = df! {
-          "id" => &ids,
-          "name_hash" => &anonymized_names,
-          "email_hash" => &anonymized_emails,
-          "phone" => &anonymized_phones,
-          "address" => &anonymized_addresses,
-          "age" => &ages,
-          "salary_bucket" => &salary_buckets,
-          "ssn" => &anonymized_ssns,
-      }?;
-
-    println!("\n📊 Anonymized (first 3):");
-    println!("{}", anonymized_df.head(Some(3)));
-
-    // Create table
-    println!("\n💾 Creating customers_anonymized...");
-    sqlx::query("DROP TABLE IF EXISTS customers_anonymized")
-        .execute(&pool)
-        .await?;
-
-    sqlx::query(
-        "CREATE TABLE customers_anonymized (
-              id INTEGER PRIMARY KEY,
-              name_hash VARCHAR(255),
-              email_hash VARCHAR(255),
-              phone VARCHAR(50),
-              address TEXT,
-              age INTEGER,
-              salary_bucket VARCHAR(50),
-              ssn VARCHAR(20),
-              anonymized_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-          )"
-    )
-        .execute(&pool)
-        .await?;
-
-    sqlx::query("CREATE INDEX idx_customers_anon_age ON customers_anonymized(age)")
-        .execute(&pool)
-        .await?;
-
-    // Insert
-    println!("📥 Inserting {} records...", total);
-    for i in 0..total {
-        sqlx::query(
-            "INSERT INTO customers_anonymized
-               (id, name_hash, email_hash, phone, address, age, salary_bucket, ssn)
-               VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
-        )
-            .bind(ids[i])
-            .bind(&anonymized_names[i])
-            .bind(&anonymized_emails[i])
-            .bind(&anonymized_phones[i])
-            .bind(&anonymized_addresses[i])
-            .bind(ages[i])
-            .bind(&salary_buckets[i])
-            .bind(&anonymized_ssns[i])
-            .execute(&pool)
+    // Anonymize and insert chunk by chunk
+    println!("\n🎭 Anonymizing...");
+    let mut total = 0;
+    let mut next = Some(first);
+    while let Some(df) = next {
+        run.rows_in += df.height();
+        run.stage("anonymize");
+        let anonymized_df = policy.apply(&df, &pseudonymizer)?;
+        if total == 0 {
+            println!("\n📊 Anonymized (first 3):");
+            println!("{}", anonymized_df.head(Some(3)));
+            run.output(&anonymized_df);
+        }
+        run.stage("write");
+        if incremental && total == 0 {
+            let mismatched = db::mismatched_columns(
+                chunks.transaction(),
+                "customers_anonymized",
+                &anonymized_df,
+            )
             .await?;
-
-        if (i + 1) % 100 == 0 {
-            println!("  ✓ {}/{}", i + 1, total);
+            if !mismatched.is_empty() {
+                drop(chunks);
+                pool.close().await;
+                println!("❌ customers_anonymized cannot take: {}", mismatched.join(", "));
+                run.fail(
+                    "policy output no longer matches customers_anonymized; \
+                     run with incremental: false",
+                );
+                return Ok(run.finish());
+            }
         }
+        total += if incremental {
+            db::write_dataframe(chunks.transaction(), "customers_anonymized", &anonymized_df, &upsert).await?
+        } else if total == 0 {
+            println!("\n💾 Creating customers_anonymized (staging)...");
+            db::create_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, batch_size).await?
+        } else {
+            db::insert_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, batch_size).await?
+        };
+        println!("  ✓ Anonymized {} records", total);
+        run.stage("read");
+        next = chunks.next_chunk().await?;
+    }
+
+    run.stage("privacy");
+    println!("\n🛡️ Checking k-anonymity...");
+    let target = if incremental { "customers_anonymized" } else { staging.as_str() };
+    let (privacy, suppressed) =
+        db::enforce_privacy(chunks.transaction(), target, &check, suppress).await?;
+    println!("  k = {} over {} groups", privacy.k, privacy.classes);
+    if let Some(l) = privacy.l {
+        println!("  l = {}", l);
+    }
+    if suppressed > 0 {
+        println!("  ⚠️ Suppressed {} rows in {} groups", suppressed, privacy.violating_classes);
+        run.warn(format!(
+            "suppressed {} rows in {} groups below k = {}",
+            suppressed, privacy.violating_classes, check.k
+        ));
     }
 
+    run.stage("swap");
+    if !incremental {
+        println!("\n🔁 Swapping in customers_anonymized...");
+        db::swap_table(chunks.transaction(), &staging, "customers_anonymized").await?;
+    }
+    db::save_watermark(chunks.transaction(), &watermark).await?;
+    chunks.commit().await?;
     pool.close().await;
     println!("\n✅ Complete!");
 
-    Ok(json!({
-          "status": "success",
-          "engine": "sqlx + Polars",
-          "database": "shopping",
-          "tables": {
-              "original": "customers",
-              "anonymized": "customers_anonymized"
-          },
-          "records_processed": total,
-          "gdpr_compliant": true
-      }))
-}
\ No newline at end of file
+    // Incremental runs count the rows they upserted, not the whole table
+    let table_rows = privacy.rows - suppressed as usize;
+    run.rows_out = if incremental { total } else { table_rows };
+    run.artifact(Artifact::table("customers_anonymized", table_rows));
+    run.detail("engine", "sqlx + Polars")?;
+    run.detail("database", &config.dbname)?;
+    run.detail("policy", &policy)?;
+    run.detail("pseudonym_key_id", pseudonymizer.key_id())?;
+    run.detail("privacy", &privacy)?;
+    run.detail("suppressed_rows", suppressed)?;
+    Ok(run.finish())
+}
@@ -4,225 +4,159 @@
 //!
 //! ```cargo
 //! [dependencies]
-//! postgres = "0.19"
 //! polars = { version = "0.44", features = ["lazy", "strings"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
-//! sha2 = "0.10"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
 //! ```
 
-use postgres::{Client, NoTls};
-use polars::prelude::*;
-use serde_json::json;
-use sha2::{Sha256, Digest};
-
-fn hash_string(input: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(input.as_bytes());
-    format!("{:x}", hasher.finalize())[..16].to_string()
-}
-
+use rustetl_core::{PipelineResult, Policy, PrivacyCheck, Pseudonymizer};
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::db::postgres as db;
+use rustetl_core::db::table::staging_table;
+use rustetl_core::pipeline::Artifact;
+
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `policy`: optional TOML or JSON anonymization policy (see
+/// `policies/customers.toml`); defaults to the built-in customers policy.
+/// `customers_anonymized` is created from its output columns, which must
+/// include `id`.
+///
+/// `pseudonym_key`: HMAC secret, ideally a Windmill secret variable
+/// (`$var:...`); falls back to the RUSTETL_PSEUDONYM_KEY env var.
+/// `pseudonym_key_id`: label stored with every row so keys can be rotated.
+/// `hash_length`: pseudonym length in hex characters (default 16).
+/// `batch_size`: rows per chunk read, anonymized and written (default 10,000).
+///
+/// `quasi_identifiers`: columns checked for k-anonymity (default `age`,
+/// `salary_bucket`); `min_k`: smallest allowed group of rows sharing them
+/// (default 5).
+/// `sensitive_column` / `min_l`: also require `min_l` distinct values of
+/// this column in every group (l-diversity, default 2).
+/// `suppress`: delete the rows of groups that fall short instead of failing
+/// the run (default false).
 fn main(
-    db_host: Option<String>,
-) -> anyhow::Result<serde_json::Value> {
-    let host = db_host.unwrap_or_else(|| "db".to_string());
+    database: Option<serde_json::Value>,
+    policy: Option<String>,
+    pseudonym_key: Option<String>,
+    pseudonym_key_id: Option<String>,
+    hash_length: Option<usize>,
+    batch_size: Option<usize>,
+    quasi_identifiers: Option<Vec<String>>,
+    min_k: Option<usize>,
+    sensitive_column: Option<String>,
+    min_l: Option<usize>,
+    suppress: Option<bool>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("anonymize_data_shopping_db");
+    let config = PgConfig::resolve(database)?;
+    let mut pseudonymizer = Pseudonymizer::resolve(pseudonym_key, pseudonym_key_id)?;
+    if let Some(length) = hash_length {
+        pseudonymizer = pseudonymizer.with_length(length)?;
+    }
+    let policy = match policy {
+        Some(text) => Policy::parse(&text)?,
+        None => Policy::customers(),
+    };
+    let check = PrivacyCheck::resolve(quasi_identifiers, min_k, sensitive_column, min_l)?;
 
     println!("🔐 Data Anonymization Pipeline");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    println!("  Database: shopping");
+    println!("  Database: {}", config);
     println!("  Source: customers (with PII)");
-    println!("  Target: customers_anonymized (GDPR compliant)");
+    println!("  Target: customers_anonymized");
+    println!("  Quasi-identifiers: {} (k >= {})", check.quasi_identifiers.join(", "), check.k);
+    println!("  Pseudonym key: {}", pseudonymizer.key_id());
     println!();
 
     // Connect to shopping database
-    let connection_string = format!(
-        "host={} user=postgres password=changeme dbname=shopping",
-        host
-    );
-
-    let mut client = Client::connect(&connection_string, NoTls)?;
+    let mut client = db::connect(&config)?;
 
     // Check if source table exists
-    let table_exists = client.query_one(
-        "SELECT EXISTS (
-            SELECT FROM information_schema.tables
-            WHERE table_name = 'customers'
-        )",
-        &[],
-    )?;
-
-    let exists: bool = table_exists.get(0);
-    if !exists {
-        return Ok(json!({
-            "status": "error",
-            "message": "❌ customers table not found. Run generate_fake_data first!"
-        }));
-    }
-
-    // Read data
-    println!("📖 Reading customer data...");
-    let rows = client.query(
-        "SELECT id, name, email, phone, address, age, salary, ssn FROM customers ORDER BY id",
-        &[],
-    )?;
-
-    let total = rows.len();
-    println!("  Found {} records", total);
-
-    if total == 0 {
-        return Ok(json!({
-            "status": "error",
-            "message": "❌ No data in customers table. Run generate_fake_data first!"
-        }));
+    if !db::table_exists(&mut client, "customers")? {
+        println!("❌ customers table not found. Run generate_fake_data first!");
+        run.fail("customers table not found; run generate_fake_data first");
+        return Ok(run.finish());
     }
 
-    // Convert to vectors for Polars
-    let mut ids: Vec<i32> = Vec::with_capacity(total);
-    let mut names: Vec<String> = Vec::with_capacity(total);
-    let mut emails: Vec<String> = Vec::with_capacity(total);
-    let mut phones: Vec<String> = Vec::with_capacity(total);
-    let mut addresses: Vec<String> = Vec::with_capacity(total);
-    let mut ages: Vec<i32> = Vec::with_capacity(total);
-    let mut salaries: Vec<i32> = Vec::with_capacity(total);
-    let mut ssns: Vec<String> = Vec::with_capacity(total);
+    // Stream customers through a cursor; the whole run is one transaction
+    let chunk_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    println!("📖 Reading customer data in chunks of {}...", chunk_size);
+    run.stage("read");
+    let mut chunks = db::read_customers_chunked(&mut client, "customers", chunk_size)?;
 
-    for row in rows {
-        ids.push(row.get(0));
-        names.push(row.get(1));
-        emails.push(row.get(2));
-        phones.push(row.get(3));
-        addresses.push(row.get(4));
-        ages.push(row.get(5));
-        salaries.push(row.get(6));
-        ssns.push(row.get(7));
-    }
-
-    // Create Polars DataFrame
-    let df = df! {
-        "id" => &ids,
-        "name" => &names,
-        "email" => &emails,
-        "phone" => &phones,
-        "address" => &addresses,
-        "age" => &ages,
-        "salary" => &salaries,
-        "ssn" => &ssns,
-    }?;
+    let Some(first) = chunks.next().transpose()? else {
+        println!("❌ No data in customers table. Run generate_fake_data first!");
+        run.fail("customers table is empty; run generate_fake_data first");
+        return Ok(run.finish());
+    };
 
     println!("\n📊 Original Data Sample:");
-    println!("{}", df.head(Some(3)));
-
-    // Apply anonymization
-    println!("\n🎭 Applying Anonymization...");
+    println!("{}", first.head(Some(3)));
 
-    let anonymized_names: Vec<String> = names.iter()
-        .map(|name| format!("Customer_{}", hash_string(name)))
-        .collect();
+    // Build the new table next to the old one; it is swapped in on success
+    run.stage("write");
+    let staging = staging_table("customers_anonymized");
 
-    let anonymized_emails: Vec<String> = emails.iter()
-        .map(|email| format!("{}@anonymized.local", hash_string(email)))
-        .collect();
-
-    let anonymized_phones: Vec<String> = phones.iter()
-        .map(|_| "***-***-****".to_string())
-        .collect();
-
-    let anonymized_addresses: Vec<String> = addresses.iter()
-        .map(|_| "REDACTED".to_string())
-        .collect();
-
-    let anonymized_ssns: Vec<String> = ssns.iter()
-        .map(|_| "***-**-****".to_string())
-        .collect();
-
-    // Salary buckets
-    let salary_buckets: Vec<String> = salaries.iter()
-        .map(|s| {
-            if *s < 50000 { "< $50k".to_string() }
-            else if *s < 75000 { "$50k-$75k".to_string() }
-            else if *s < 100000 { "$75k-$100k".to_string() }
-            else if *s < 125000 { "$100k-$125k".to_string() }
-            else { "> $125k".to_string() }
-        })
-        .collect();
-
-    let anonymized_df = df! {
-        "id" => &ids,
-        "name_hash" => &anonymized_names,
-        "email_hash" => &anonymized_emails,
-        "phone" => &anonymized_phones,
-        "address" => &anonymized_addresses,
-        "age" => &ages,
-        "salary_bucket" => &salary_buckets,
-        "ssn" => &anonymized_ssns,
-    }?;
-
-    println!("\n📊 Anonymized Data Sample:");
-    println!("{}", anonymized_df.head(Some(3)));
-
-    // Create anonymized table
-    println!("\n💾 Creating customers_anonymized table...");
-    client.execute("DROP TABLE IF EXISTS customers_anonymized", &[])?;
-    client.execute(
-        "CREATE TABLE customers_anonymized (
-            id INTEGER PRIMARY KEY,
-            name_hash VARCHAR(255),
-            email_hash VARCHAR(255),
-            phone VARCHAR(50),
-            address TEXT,
-            age INTEGER,
-            salary_bucket VARCHAR(50),
-            ssn VARCHAR(20),
-            anonymized_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        &[],
-    )?;
-
-    // Create indexes
-    client.execute("CREATE INDEX idx_customers_anon_age ON customers_anonymized(age)", &[])?;
-
-    // Insert anonymized data
-    println!("📥 Inserting {} anonymized records...", total);
-    for i in 0..total {
-        client.execute(
-            "INSERT INTO customers_anonymized
-             (id, name_hash, email_hash, phone, address, age, salary_bucket, ssn)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-            &[
-                &ids[i],
-                &anonymized_names[i],
-                &anonymized_emails[i],
-                &anonymized_phones[i],
-                &anonymized_addresses[i],
-                &ages[i],
-                &salary_buckets[i],
-                &anonymized_ssns[i],
-            ],
-        )?;
-
-        if (i + 1) % 100 == 0 {
-            println!("  ✓ Inserted {}/{}", i + 1, total);
+    // Apply anonymization and insert chunk by chunk
+    println!("\n🎭 Applying Anonymization...");
+    let mut total = 0;
+    let mut next = Some(first);
+    while let Some(df) = next {
+        run.rows_in += df.height();
+        run.stage("anonymize");
+        let anonymized_df = policy.apply(&df, &pseudonymizer)?;
+        if total == 0 {
+            println!("\n📊 Anonymized Data Sample:");
+            println!("{}", anonymized_df.head(Some(3)));
+            run.output(&anonymized_df);
         }
+        run.stage("write");
+        total += if total == 0 {
+            println!("\n💾 Creating customers_anonymized table (staging)...");
+            db::create_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, chunk_size)?
+        } else {
+            db::insert_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, chunk_size)?
+        };
+        println!("  ✓ Anonymized {} records", total);
+        run.stage("read");
+        next = chunks.next().transpose()?;
     }
+    run.stage("privacy");
+    println!("\n🛡️ Checking k-anonymity...");
+    let (privacy, suppressed) = db::enforce_privacy(
+        chunks.transaction(),
+        &staging,
+        &check,
+        suppress.unwrap_or(false),
+    )?;
+    println!("  k = {} over {} groups", privacy.k, privacy.classes);
+    if let Some(l) = privacy.l {
+        println!("  l = {}", l);
+    }
+    if suppressed > 0 {
+        println!("  ⚠️ Suppressed {} rows in {} groups", suppressed, privacy.violating_classes);
+        run.warn(format!(
+            "suppressed {} rows in {} groups below k = {}",
+            suppressed, privacy.violating_classes, check.k
+        ));
+    }
+
+    run.stage("swap");
+    println!("\n🔁 Swapping in customers_anonymized...");
+    db::swap_table(chunks.transaction(), &staging, "customers_anonymized")?;
+    chunks.commit()?;
 
     println!("\n✅ Anonymization Complete!");
 
-    Ok(json!({
-        "status": "success",
-        "database": "shopping",
-        "original_table": "customers",
-        "anonymized_table": "customers_anonymized",
-        "records_processed": total,
-        "anonymization_techniques": {
-            "names": "SHA256 hash → Customer_<hash>",
-            "emails": "SHA256 hash → <hash>@anonymized.local",
-            "phones": "Masked → ***-***-****",
-            "addresses": "Redacted → REDACTED",
-            "ssn": "Masked → ***-**-****",
-            "salaries": "Bucketed into 5 ranges"
-        },
-        "preserved_fields": ["id", "age"],
-        "gdpr_compliant": true,
-        "safe_to_share": true
-    }))
+    run.rows_out = total - suppressed as usize;
+    run.artifact(Artifact::table("customers_anonymized", run.rows_out));
+    run.detail("database", &config.dbname)?;
+    run.detail("policy", &policy)?;
+    run.detail("pseudonym_key_id", pseudonymizer.key_id())?;
+    run.detail("preserved_fields", policy.kept_columns())?;
+    run.detail("privacy", &privacy)?;
+    run.detail("suppressed_rows", suppressed)?;
+    Ok(run.finish())
 }
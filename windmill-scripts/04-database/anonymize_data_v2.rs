@@ -5,203 +5,116 @@
 //!
 //! ```cargo
 //! [dependencies]
-//! postgres = "0.19"
 //! polars = { version = "0.44", features = ["lazy", "strings"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
-//! sha2 = "0.10"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
 //! ```
 
-use postgres::{Client, NoTls};
-use polars::prelude::*;
+use rustetl_core::{PipelineResult, PrivacyCheck, Pseudonymizer, anonymize_customers};
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::db::postgres as db;
+use rustetl_core::db::table::staging_table;
+use rustetl_core::pipeline::Artifact;
 use serde_json::json;
-use sha2::{Sha256, Digest};
-
-fn hash_string(input: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(input.as_bytes());
-    format!("{:x}", hasher.finalize())[..16].to_string()
-}
 
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `pseudonym_key` / `pseudonym_key_id`: HMAC secret and its label; fall
+/// back to the RUSTETL_PSEUDONYM_KEY / RUSTETL_PSEUDONYM_KEY_ID env vars.
+/// `batch_size`: rows per chunk read, anonymized and written (default 10,000).
 fn main(
-    db_host: Option<String>,
+    database: Option<serde_json::Value>,
     mask_percentage: Option<i32>,
-) -> anyhow::Result<serde_json::Value> {
-    let host = db_host.unwrap_or_else(|| "db".to_string());
+    pseudonym_key: Option<String>,
+    pseudonym_key_id: Option<String>,
+    batch_size: Option<usize>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("anonymize_data_v2");
+    let config = PgConfig::resolve(database)?;
+    let pseudonymizer = Pseudonymizer::resolve(pseudonym_key, pseudonym_key_id)?;
     let mask_pct = mask_percentage.unwrap_or(100);
 
     println!("🔐 Starting data anonymization process...");
-    println!("  Database: {}", host);
+    println!("  Database: {}", config);
     println!("  Schema: shopping");
     println!("  Masking: {}% of records", mask_pct);
 
     // Connect to database
-    let connection_string = format!(
-        "host={} user=postgres password=changeme dbname=windmill",
-        host
-    );
-
-    let mut client = Client::connect(&connection_string, NoTls)?;
-
-    // Read data from shopping.customers table
-    println!("\n📖 Reading customer data from shopping.customers...");
-    let rows = client.query(
-        "SELECT id, name, email, phone, address, age, salary, ssn FROM shopping.customers",
-        &[],
-    )?;
-
-    let total = rows.len();
-    println!("  Found {} records", total);
+    let mut client = db::connect(&config)?;
 
-    if total == 0 {
-        return Ok(json!({
-            "status": "error",
-            "message": "No data found in shopping.customers. Run generate_fake_data first!"
-        }));
-    }
-
-    // Convert to Polars DataFrame
-    let mut ids: Vec<i32> = Vec::with_capacity(total);
-    let mut names: Vec<String> = Vec::with_capacity(total);
-    let mut emails: Vec<String> = Vec::with_capacity(total);
-    let mut phones: Vec<String> = Vec::with_capacity(total);
-    let mut addresses: Vec<String> = Vec::with_capacity(total);
-    let mut ages: Vec<i32> = Vec::with_capacity(total);
-    let mut salaries: Vec<i32> = Vec::with_capacity(total);
-    let mut ssns: Vec<String> = Vec::with_capacity(total);
-
-    for row in rows {
-        ids.push(row.get(0));
-        names.push(row.get(1));
-        emails.push(row.get(2));
-        phones.push(row.get(3));
-        addresses.push(row.get(4));
-        ages.push(row.get(5));
-        salaries.push(row.get(6));
-        ssns.push(row.get(7));
-    }
+    // Stream shopping.customers through a cursor; the whole run is one transaction
+    let chunk_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    println!("\n📖 Reading customer data from shopping.customers in chunks of {}...", chunk_size);
+    let mut chunks = db::read_customers_chunked(&mut client, "shopping.customers", chunk_size)?;
 
-    let df = df! {
-        "id" => &ids,
-        "name" => &names,
-        "email" => &emails,
-        "phone" => &phones,
-        "address" => &addresses,
-        "age" => &ages,
-        "salary" => &salaries,
-        "ssn" => &ssns,
-    }?;
+    run.stage("read");
+    let Some(first) = chunks.next().transpose()? else {
+        run.fail("no data found in shopping.customers; run generate_fake_data first");
+        return Ok(run.finish());
+    };
 
     println!("\n📊 Original data sample (first 3 rows):");
-    println!("{}", df.head(Some(3)));
-
-    // Anonymize sensitive data
-    println!("\n🎭 Applying anonymization with Polars...");
+    println!("{}", first.head(Some(3)));
 
-    let anonymized_names: Vec<String> = names.iter()
-        .map(|name| format!("Customer_{}", hash_string(name)))
-        .collect();
+    // Build the new table next to the old one; it is swapped in on success
+    run.stage("write");
+    let staging = staging_table("shopping.customers_anonymized");
 
-    let anonymized_emails: Vec<String> = emails.iter()
-        .map(|email| format!("{}@anonymized.local", hash_string(email)))
-        .collect();
-
-    let anonymized_phones: Vec<String> = phones.iter()
-        .map(|_| "***-***-****".to_string())
-        .collect();
-
-    let anonymized_addresses: Vec<String> = addresses.iter()
-        .map(|_| "REDACTED".to_string())
-        .collect();
-
-    let anonymized_ssns: Vec<String> = ssns.iter()
-        .map(|_| "***-**-****".to_string())
-        .collect();
-
-    // Salary buckets (for privacy)
-    let salary_buckets: Vec<String> = salaries.iter()
-        .map(|s| {
-            if *s < 50000 { "< $50k".to_string() }
-            else if *s < 75000 { "$50k-$75k".to_string() }
-            else if *s < 100000 { "$75k-$100k".to_string() }
-            else if *s < 125000 { "$100k-$125k".to_string() }
-            else { "> $125k".to_string() }
-        })
-        .collect();
-
-    let anonymized_df = df! {
-        "id" => &ids,
-        "name_hash" => &anonymized_names,
-        "email_hash" => &anonymized_emails,
-        "phone" => &anonymized_phones,
-        "address" => &anonymized_addresses,
-        "age" => &ages,  // Keep age for analytics
-        "salary_bucket" => &salary_buckets,
-        "ssn" => &anonymized_ssns,
-    }?;
-
-    println!("\n📊 Anonymized data sample (first 3 rows):");
-    println!("{}", anonymized_df.head(Some(3)));
-
-    // Create anonymized table
-    println!("\n💾 Creating shopping.customers_anonymized table...");
-    client.execute("DROP TABLE IF EXISTS shopping.customers_anonymized", &[])?;
-    client.execute(
-        "CREATE TABLE shopping.customers_anonymized (
-            id INTEGER PRIMARY KEY,
-            name_hash VARCHAR(255),
-            email_hash VARCHAR(255),
-            phone VARCHAR(50),
-            address TEXT,
-            age INTEGER,
-            salary_bucket VARCHAR(50),
-            ssn VARCHAR(20),
-            anonymized_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        &[],
-    )?;
-
-    // Insert anonymized data
-    println!("📥 Inserting {} anonymized records...", total);
-    for i in 0..total {
-        client.execute(
-            "INSERT INTO shopping.customers_anonymized
-             (id, name_hash, email_hash, phone, address, age, salary_bucket, ssn)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-            &[
-                &ids[i],
-                &anonymized_names[i],
-                &anonymized_emails[i],
-                &anonymized_phones[i],
-                &anonymized_addresses[i],
-                &ages[i],
-                &salary_buckets[i],
-                &anonymized_ssns[i],
-            ],
-        )?;
-
-        if (i + 1) % 100 == 0 {
-            println!("  ✓ Inserted {}/{} records", i + 1, total);
+    // Anonymize and insert chunk by chunk
+    println!("\n🎭 Applying anonymization with Polars...");
+    let mut total = 0;
+    let mut next = Some(first);
+    while let Some(df) = next {
+        run.rows_in += df.height();
+        run.stage("anonymize");
+        let anonymized_df = anonymize_customers(&df, &pseudonymizer)?;
+        if total == 0 {
+            println!("\n📊 Anonymized data sample (first 3 rows):");
+            println!("{}", anonymized_df.head(Some(3)));
+            run.output(&anonymized_df);
         }
+        run.stage("write");
+        total += if total == 0 {
+            println!("\n💾 Creating shopping.customers_anonymized table (staging)...");
+            db::create_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, chunk_size)?
+        } else {
+            db::insert_customers_anonymized(chunks.transaction(), &staging, &anonymized_df, chunk_size)?
+        };
+        println!("  ✓ Anonymized {} records", total);
+        run.stage("read");
+        next = chunks.next().transpose()?;
     }
+    // Fails the run, leaving the old table in place, if any age/salary
+    // group has fewer than 5 customers
+    run.stage("privacy");
+    println!("\n🛡️ Checking k-anonymity...");
+    let (privacy, _) =
+        db::enforce_privacy(chunks.transaction(), &staging, &PrivacyCheck::customers(), false)?;
+    println!("  k = {} over {} groups", privacy.k, privacy.classes);
+
+    run.stage("swap");
+    println!("\n🔁 Swapping in shopping.customers_anonymized...");
+    db::swap_table(chunks.transaction(), &staging, "shopping.customers_anonymized")?;
+    chunks.commit()?;
 
     println!("\n✅ Anonymization complete!");
 
-    Ok(json!({
-        "status": "success",
-        "schema": "shopping",
-        "original_table": "shopping.customers",
-        "anonymized_table": "shopping.customers_anonymized",
-        "records_processed": total,
-        "anonymization_applied": [
-            "Names → SHA256 hash prefix",
-            "Emails → SHA256 hash + @anonymized.local",
+    run.rows_out = total;
+    run.artifact(Artifact::table("shopping.customers_anonymized", total));
+    run.detail("pseudonym_key_id", pseudonymizer.key_id())?;
+    run.detail("privacy", &privacy)?;
+    run.detail(
+        "anonymization_applied",
+        json!([
+            "Names → HMAC-SHA256 pseudonym",
+            "Emails → HMAC-SHA256 pseudonym + @anonymized.local",
             "Phones → Masked (***-***-****)",
             "Addresses → REDACTED",
             "SSN → Masked (***-**-****)",
+            "Ages → Ranges (18-24, 25-34, ...)",
             "Salaries → Bucketed into ranges"
-        ],
-        "preserved_fields": ["id", "age (for analytics)"],
-        "note": "✅ Safe to share anonymized table - all PII removed!"
-    }))
+        ]),
+    )?;
+    Ok(run.finish())
 }
@@ -2,117 +2,57 @@
 //!
 //! ```cargo
 //! [dependencies]
-//! postgres = "0.19"
-//! fake = { version = "2.9", features = ["derive"] }
-//! rand = "0.8"
-//! serde = { version = "1.0", features = ["derive"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
 //! ```
 
-use fake::{Fake, Faker};
-use fake::faker::name::en::*;
-use fake::faker::internet::en::*;
-use fake::faker::phone_number::en::*;
-use fake::faker::address::en::*;
-use postgres::{Client, NoTls};
-use serde::{Serialize, Deserialize};
+use rustetl_core::customers;
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::db::postgres as db;
+use rustetl_core::pipeline::{Artifact, PipelineResult};
 use serde_json::json;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Customer {
-    name: String,
-    email: String,
-    phone: String,
-    address: String,
-    age: i32,
-    salary: i32,
-    ssn: String,
-}
-
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `batch_size`: rows per `COPY` round trip (default 10,000).
+/// `seed`: makes the generated customers identical on every run with the
+/// same seed (default: random).
 fn main(
     num_records: Option<i32>,
-    db_host: Option<String>,
-) -> anyhow::Result<serde_json::Value> {
+    database: Option<serde_json::Value>,
+    batch_size: Option<usize>,
+    seed: Option<u64>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("generate_fake_data_shopping_db");
     let num = num_records.unwrap_or(1000);
-    let host = db_host.unwrap_or_else(|| "db".to_string());
+    let batch_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let config = PgConfig::resolve(database)?;
 
     println!("🎲 Generating {} fake customer records...", num);
-    println!("💾 Database: shopping (separate from Windmill)");
+    println!("💾 Database: {}", config);
 
     // Connect to shopping database
-    let connection_string = format!(
-        "host={} user=postgres password=changeme dbname=shopping",
-        host
-    );
-
-    let mut client = Client::connect(&connection_string, NoTls)?;
+    let mut client = db::connect(&config)?;
 
     // Create customers table
+    run.stage("create");
     println!("📋 Creating customers table...");
-    client.execute("DROP TABLE IF EXISTS customers CASCADE", &[])?;
-    client.execute(
-        "CREATE TABLE customers (
-            id SERIAL PRIMARY KEY,
-            name VARCHAR(255) NOT NULL,
-            email VARCHAR(255) NOT NULL,
-            phone VARCHAR(50),
-            address TEXT,
-            age INTEGER CHECK (age >= 18 AND age <= 100),
-            salary INTEGER CHECK (salary >= 0),
-            ssn VARCHAR(20),
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        &[],
-    )?;
-
-    // Create index for better query performance
-    client.execute("CREATE INDEX idx_customers_age ON customers(age)", &[])?;
-    client.execute("CREATE INDEX idx_customers_created_at ON customers(created_at)", &[])?;
+    db::recreate_customers(&mut client, "customers")?;
 
+    run.stage("generate");
+    let customers = customers::generate(num.max(0) as usize, seed);
+    run.stage("insert");
     println!("📥 Inserting {} records...", num);
-
-    let mut inserted = 0;
-    for i in 0..num {
-        let customer = Customer {
-            name: Name().fake(),
-            email: SafeEmail().fake(),
-            phone: PhoneNumber().fake(),
-            address: format!("{}, {}", StreetAddress().fake::<String>(), CityName().fake::<String>()),
-            age: (25..65).fake(),
-            salary: (30000..150000).fake(),
-            ssn: format!("{:03}-{:02}-{:04}",
-                (100..999).fake::<i32>(),
-                (10..99).fake::<i32>(),
-                (1000..9999).fake::<i32>()
-            ),
-        };
-
-        client.execute(
-            "INSERT INTO customers (name, email, phone, address, age, salary, ssn)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)",
-            &[
-                &customer.name,
-                &customer.email,
-                &customer.phone,
-                &customer.address,
-                &customer.age,
-                &customer.salary,
-                &customer.ssn,
-            ],
-        )?;
-
-        inserted += 1;
-
-        if (i + 1) % 100 == 0 {
-            println!("  ✓ Inserted {}/{} records", i + 1, num);
-        }
-    }
+    let progress = |done: usize, total: usize| println!("  ✓ Inserted {}/{} records", done, total);
+    let inserted = db::insert_customers(&mut client, "customers", &customers, batch_size, progress)?;
 
     println!("✅ Successfully inserted {} customers!", inserted);
 
-    // Get statistics
-    let row = client.query_one(
+    // Get statistics (ROUND(AVG(..)) is numeric; query_frame reads it as f64)
+    run.stage("stats");
+    let stats = db::query_frame(
+        &mut client,
         "SELECT
             COUNT(*) as total,
             ROUND(AVG(age)) as avg_age,
@@ -125,21 +65,23 @@ fn main(
         &[],
     )?;
 
-    let count: i64 = row.get(0);
-    let avg_age: Option<f64> = row.get(1);
-    let avg_salary: Option<f64> = row.get(2);
-    let min_age: i32 = row.get(3);
-    let max_age: i32 = row.get(4);
-    let min_salary: i32 = row.get(5);
-    let max_salary: i32 = row.get(6);
-
-    Ok(json!({
-        "status": "success",
-        "database": "shopping",
-        "table": "customers",
-        "records_inserted": inserted,
-        "total_records": count,
-        "statistics": {
+    let count = stats.column("total")?.i64()?.get(0).unwrap_or(0);
+    let avg_age = stats.column("avg_age")?.f64()?.get(0);
+    let avg_salary = stats.column("avg_salary")?.f64()?.get(0);
+    let min_age = stats.column("min_age")?.i32()?.get(0);
+    let max_age = stats.column("max_age")?.i32()?.get(0);
+    let min_salary = stats.column("min_salary")?.i32()?.get(0);
+    let max_salary = stats.column("max_salary")?.i32()?.get(0);
+
+    run.rows_out = inserted;
+    run.artifact(Artifact::table("customers", inserted));
+    run.warn("customers holds unmasked PII (SSN, email, phone); run an anonymize script before sharing it");
+    run.detail("database", &config.dbname)?;
+    run.detail("seed", seed)?;
+    run.detail(
+        "statistics",
+        json!({
+            "total_records": count,
             "age": {
                 "average": avg_age.unwrap_or(0.0),
                 "min": min_age,
@@ -150,8 +92,7 @@ fn main(
                 "min": min_salary,
                 "max": max_salary
             }
-        },
-        "warning": "⚠️  Contains sensitive PII (SSN, email, phone)",
-        "next_step": "Run anonymize script to create GDPR-compliant copy"
-    }))
+        }),
+    )?;
+    Ok(run.finish())
 }
@@ -2,129 +2,76 @@
 //!
 //! ```cargo
 //! [dependencies]
-//! postgres = "0.19"
-//! fake = { version = "2.9", features = ["derive"] }
-//! rand = "0.8"
-//! serde = { version = "1.0", features = ["derive"] }
 //! serde_json = "1.0"
 //! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
 //! ```
 
-use fake::{Fake, Faker};
-use fake::faker::name::en::*;
-use fake::faker::internet::en::*;
-use fake::faker::phone_number::en::*;
-use fake::faker::address::en::*;
-use postgres::{Client, NoTls};
-use serde::{Serialize, Deserialize};
+use rustetl_core::customers;
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::db::postgres as db;
+use rustetl_core::pipeline::{Artifact, PipelineResult};
 use serde_json::json;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Customer {
-    name: String,
-    email: String,
-    phone: String,
-    address: String,
-    age: i32,
-    salary: i32,
-    ssn: String,
-}
-
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `batch_size`: rows per `COPY` round trip (default 10,000).
+/// `seed`: makes the generated customers identical on every run with the
+/// same seed (default: random).
 fn main(
     num_records: Option<i32>,
-    db_host: Option<String>,
-) -> anyhow::Result<serde_json::Value> {
+    database: Option<serde_json::Value>,
+    batch_size: Option<usize>,
+    seed: Option<u64>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("generate_fake_data_v2");
     let num = num_records.unwrap_or(1000);
-    let host = db_host.unwrap_or_else(|| "db".to_string());
+    let batch_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let config = PgConfig::resolve(database)?;
 
     println!("🎲 Generating {} fake customer records...", num);
     println!("📦 Schema: shopping");
 
-    // Connect to Windmill's PostgreSQL
-    let connection_string = format!(
-        "host={} user=postgres password=changeme dbname=windmill",
-        host
-    );
-
-    let mut client = Client::connect(&connection_string, NoTls)?;
+    // Connect to the database
+    let mut client = db::connect(&config)?;
 
     // Create customers table in shopping schema
+    run.stage("create");
     println!("📋 Creating shopping.customers table...");
-    client.execute("DROP TABLE IF EXISTS shopping.customers CASCADE", &[])?;
-    client.execute(
-        "CREATE TABLE shopping.customers (
-            id SERIAL PRIMARY KEY,
-            name VARCHAR(255) NOT NULL,
-            email VARCHAR(255) NOT NULL,
-            phone VARCHAR(50),
-            address TEXT,
-            age INTEGER,
-            salary INTEGER,
-            ssn VARCHAR(20),
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        &[],
-    )?;
+    db::recreate_customers(&mut client, "shopping.customers")?;
 
+    run.stage("generate");
+    let customers = customers::generate(num.max(0) as usize, seed);
+    run.stage("insert");
     println!("📥 Inserting {} records into shopping.customers...", num);
-
-    let mut inserted = 0;
-    for i in 0..num {
-        let customer = Customer {
-            name: Name().fake(),
-            email: SafeEmail().fake(),
-            phone: PhoneNumber().fake(),
-            address: format!("{}, {}", StreetAddress().fake::<String>(), CityName().fake::<String>()),
-            age: (25..65).fake(),
-            salary: (30000..150000).fake(),
-            ssn: format!("{:03}-{:02}-{:04}",
-                (100..999).fake::<i32>(),
-                (10..99).fake::<i32>(),
-                (1000..9999).fake::<i32>()
-            ),
-        };
-
-        client.execute(
-            "INSERT INTO shopping.customers (name, email, phone, address, age, salary, ssn)
-             VALUES ($1, $2, $3, $4, $5, $6, $7)",
-            &[
-                &customer.name,
-                &customer.email,
-                &customer.phone,
-                &customer.address,
-                &customer.age,
-                &customer.salary,
-                &customer.ssn,
-            ],
-        )?;
-
-        inserted += 1;
-
-        if (i + 1) % 100 == 0 {
-            println!("  ✓ Inserted {}/{} records", i + 1, num);
-        }
-    }
+    let progress = |done: usize, total: usize| println!("  ✓ Inserted {}/{} records", done, total);
+    let inserted = db::insert_customers(&mut client, "shopping.customers", &customers, batch_size, progress)?;
 
     println!("✅ Successfully inserted {} customers!", inserted);
 
-    // Get some stats
-    let row = client.query_one(
-        "SELECT COUNT(*), AVG(age), AVG(salary) FROM shopping.customers",
+    // Get some stats (AVG is numeric; query_frame reads it as f64)
+    run.stage("stats");
+    let stats = db::query_frame(
+        &mut client,
+        "SELECT COUNT(*) AS total, AVG(age) AS avg_age, AVG(salary) AS avg_salary FROM shopping.customers",
         &[],
     )?;
 
-    let count: i64 = row.get(0);
-    let avg_age: Option<f64> = row.get(1);
-    let avg_salary: Option<f64> = row.get(2);
-
-    Ok(json!({
-        "status": "success",
-        "schema": "shopping",
-        "table": "customers",
-        "records_inserted": inserted,
-        "total_records": count,
-        "avg_age": avg_age.unwrap_or(0.0).round(),
-        "avg_salary": avg_salary.unwrap_or(0.0).round(),
-        "note": "Data contains sensitive information (SSN) - use anonymization script next!"
-    }))
+    let count = stats.column("total")?.i64()?.get(0).unwrap_or(0);
+    let avg_age = stats.column("avg_age")?.f64()?.get(0);
+    let avg_salary = stats.column("avg_salary")?.f64()?.get(0);
+
+    run.rows_out = inserted;
+    run.artifact(Artifact::table("shopping.customers", inserted));
+    run.warn("shopping.customers holds unmasked PII (SSN, email, phone); anonymize it before sharing");
+    run.detail("seed", seed)?;
+    run.detail(
+        "statistics",
+        json!({
+            "total_records": count,
+            "avg_age": avg_age.unwrap_or(0.0).round(),
+            "avg_salary": avg_salary.unwrap_or(0.0).round(),
+        }),
+    )?;
+    Ok(run.finish())
 }
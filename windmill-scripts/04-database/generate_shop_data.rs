@@ -0,0 +1,93 @@
+//! Generate a Linked Shopping Dataset: Customers, Products, Orders, Items
+//!
+//! ```cargo
+//! [dependencies]
+//! serde_json = "1.0"
+//! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
+//! ```
+
+use rustetl_core::customers;
+use rustetl_core::db::postgres as db;
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::shop::{self, ShopOptions, ShopTables};
+use rustetl_core::pipeline::{Artifact, PipelineResult};
+
+/// `num_customers`: customers to generate (default 1,000).
+/// `num_products`: products to generate (default 200).
+/// `orders_per_customer`: average orders per customer (default 3, at most
+/// 1,000).
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `batch_size`: rows per `COPY` round trip (default 10,000).
+/// `seed`: makes the whole dataset identical on every run with the same
+/// seed (default: random).
+fn main(
+    num_customers: Option<i32>,
+    num_products: Option<i32>,
+    orders_per_customer: Option<f64>,
+    database: Option<serde_json::Value>,
+    batch_size: Option<usize>,
+    seed: Option<u64>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("generate_shop_data");
+    let num_customers = num_customers.unwrap_or(1000).max(0) as usize;
+    let options = ShopOptions {
+        products: num_products.map_or(200, |n| n.max(0) as usize),
+        orders_per_customer: orders_per_customer.unwrap_or(3.0),
+        ..ShopOptions::default()
+    };
+    options.validate()?;
+    let batch_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+    let tables = ShopTables::default();
+    let config = PgConfig::resolve(database)?;
+
+    // Connect to the database
+    let mut client = db::connect(&config)?;
+
+    run.stage("create");
+    println!("📋 Creating customers, products, orders and order_items...");
+    db::recreate_customers(&mut client, &tables.customers)?;
+    db::recreate_shop(&mut client, &tables)?;
+
+    run.stage("customers");
+    println!("👥 Inserting {} customers...", num_customers);
+    let customers = customers::generate(num_customers, seed);
+    let progress = |done: usize, total: usize| println!("  ✓ Inserted {}/{} records", done, total);
+    let inserted_customers = db::insert_customers(&mut client, &tables.customers, &customers, batch_size, progress)?;
+    let customer_ids = db::customer_ids(&mut client, &tables.customers)?;
+
+    run.stage("orders");
+    println!("🛒 Generating products and orders...");
+    let data = shop::generate(&customer_ids, &options, seed)?;
+    let [products, orders, order_items] =
+        db::insert_shop(&mut client, &tables, &data, batch_size)?;
+
+    println!(
+        "✅ Inserted {} products, {} orders and {} order items",
+        products, orders, order_items
+    );
+
+    run.stage("stats");
+    let stats = db::query_frame(
+        &mut client,
+        "SELECT COUNT(DISTINCT customer_id) AS buyers, AVG(total) AS avg_order_total FROM orders",
+        &[],
+    )?;
+    let buyers = stats.column("buyers")?.i64()?.get(0).unwrap_or(0);
+    let avg_order_total = stats.column("avg_order_total")?.f64()?.get(0);
+
+    run.rows_out = inserted_customers + products + orders + order_items;
+    run.artifact(Artifact::table(&tables.customers, inserted_customers));
+    run.artifact(Artifact::table(&tables.products, products));
+    run.artifact(Artifact::table(&tables.orders, orders));
+    run.artifact(Artifact::table(&tables.order_items, order_items));
+    run.warn(format!(
+        "{} holds unmasked PII (SSN, email, phone); anonymize it before sharing",
+        tables.customers
+    ));
+    run.detail("seed", seed)?;
+    run.detail("customers_with_orders", buyers)?;
+    run.detail("avg_order_total", avg_order_total.unwrap_or(0.0))?;
+    Ok(run.finish())
+}
@@ -0,0 +1,61 @@
+//! Generate Synthetic Data for Any Table from a Spec
+//!
+//! ```cargo
+//! [dependencies]
+//! serde_json = "1.0"
+//! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
+//! ```
+
+use rustetl_core::TableSpec;
+use rustetl_core::db::postgres as db;
+use rustetl_core::db::table::{WriteMode, WriteOptions};
+use rustetl_core::db::{DEFAULT_BATCH_SIZE, PgConfig};
+use rustetl_core::pipeline::{Artifact, PipelineResult};
+
+/// `spec`: TOML or JSON table spec (see `specs/customers.toml`).
+/// `table`: target table; created from the spec's types if missing.
+/// `mode`: create, append (default), replace or upsert.
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `batch_size`: rows per `COPY` round trip (default 10,000).
+/// `seed`: makes the generated rows identical on every run with the same
+/// seed (default: random).
+fn main(
+    spec: String,
+    table: String,
+    num_rows: Option<i32>,
+    mode: Option<String>,
+    database: Option<serde_json::Value>,
+    batch_size: Option<usize>,
+    seed: Option<u64>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("generate_synthetic_data");
+    let num = num_rows.unwrap_or(1000).max(0) as usize;
+    let mode: WriteMode = mode.as_deref().unwrap_or("append").parse()?;
+    let spec = TableSpec::parse(&spec)?;
+    let config = PgConfig::resolve(database)?;
+
+    run.stage("generate");
+    println!("🎲 Generating {} synthetic rows for {}...", num, table);
+    let df = spec.generate(num, seed)?;
+
+    let primary_key: Vec<&str> = spec.primary_key.iter().map(String::as_str).collect();
+    let options = WriteOptions::new(mode)
+        .with_primary_key(&primary_key)
+        .with_batch_size(batch_size.unwrap_or(DEFAULT_BATCH_SIZE));
+
+    run.stage("write");
+    println!("📥 Writing to {} ({:?})...", table, mode);
+    let mut client = db::connect(&config)?;
+    let written = db::write_dataframe(&mut client, &table, &df, &options)?;
+
+    println!("✅ Wrote {} rows to {}", written, table);
+
+    run.output(&df);
+    run.rows_out = written;
+    run.artifact(Artifact::table(&table, written));
+    run.detail("mode", mode)?;
+    run.detail("seed", seed)?;
+    Ok(run.finish())
+}
@@ -0,0 +1,104 @@
+//! Release Differentially Private Aggregates
+//!
+//! Runs a group-by over a table and publishes only noisy counts, sums and
+//! means, charging each release to the table's epsilon budget
+//!
+//! ```cargo
+//! [dependencies]
+//! serde_json = "1.0"
+//! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
+//! ```
+
+use rustetl_core::{DpQuery, PipelineResult};
+use rustetl_core::db::PgConfig;
+use rustetl_core::db::budget::PrivacyBudget;
+use rustetl_core::db::postgres as db;
+use rustetl_core::io::{self, FileOptions};
+
+/// `query`: TOML or JSON aggregate query (see `queries/customer_salaries.toml`).
+/// `table`: table or `schema.table` to aggregate (default `customers`).
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `dataset`: budget ledger the release is charged to (default: `table`).
+/// `epsilon_budget`: total epsilon the dataset may ever spend, fixed on its
+/// first release (default 3.0).
+/// `output_path`: also write the released frame to a `.parquet`,
+/// `.arrow`/`.feather` or `.csv` file, tuned by `compression` and
+/// `row_group_size`.
+fn main(
+    query: String,
+    table: Option<String>,
+    database: Option<serde_json::Value>,
+    dataset: Option<String>,
+    epsilon_budget: Option<f64>,
+    output_path: Option<String>,
+    compression: Option<String>,
+    row_group_size: Option<usize>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("release_dp_aggregates");
+    let query = DpQuery::parse(&query)?;
+    let file_options = FileOptions::parse(compression.as_deref(), row_group_size)?;
+    let table = table.unwrap_or_else(|| "customers".to_string());
+    let budget = PrivacyBudget::new(dataset.as_deref().unwrap_or(&table), epsilon_budget.unwrap_or(3.0))?;
+    let config = PgConfig::resolve(database)?;
+
+    println!("🎲 Differentially Private Release");
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("  Database: {}", config);
+    println!("  Table: {}", table);
+    println!("  Epsilon: {} ({:?})", query.epsilon, query.mechanism);
+    println!();
+
+    let mut client = db::connect(&config)?;
+    if !db::table_exists(&mut client, &table)? {
+        println!("❌ {} table not found", table);
+        run.fail(format!("{} table not found", table));
+        return Ok(run.finish());
+    }
+
+    // The budget row stays locked until the release commits
+    let mut tx = client.transaction()?;
+    run.stage("budget");
+    println!("💰 Charging the {} budget...", budget.dataset());
+    let usage = db::spend_budget(&mut tx, &budget, query.epsilon)?;
+    println!("  ✓ {:.3} of {:.3} spent, {:.3} left", usage.spent, usage.total, usage.remaining);
+    if usage.remaining < query.epsilon {
+        run.warn(format!(
+            "only {:.3} epsilon left for {}; the next release of this query will be refused",
+            usage.remaining, usage.dataset
+        ));
+    }
+
+    run.stage("read");
+    println!("📖 Reading {}...", table);
+    let df = db::query_frame(&mut tx, &query.select(&table), &[])?;
+    run.rows_in = df.height();
+
+    run.stage("release");
+    println!("🔊 Adding noise...");
+    let mut released = query.release(&df, None)?;
+    if released.height() == 0 && !query.group_by.is_empty() {
+        run.warn(format!(
+            "every group has a noisy count below the threshold of {}; nothing was released",
+            query.threshold
+        ));
+    }
+    // Write before committing, so a failed write does not spend the budget
+    if let Some(path) = &output_path {
+        run.stage("write");
+        let written = io::write_file(&mut released, path, &file_options)?;
+        println!("💾 Wrote {} rows to {}", written.rows, written.path);
+        run.artifact(written);
+    }
+    tx.commit()?;
+
+    println!("\n📊 Released:");
+    println!("{}", released);
+
+    run.output_with_rows(&released);
+    run.detail("table", &table)?;
+    run.detail("query", &query)?;
+    run.detail("budget", &usage)?;
+    Ok(run.finish())
+}
@@ -0,0 +1,88 @@
+//! Scan a Table for Personal Data and Draft a Policy
+//!
+//! Samples a table, classifies each column by name and values (emails,
+//! phones, SSNs, card numbers, IPs, dates of birth, names, addresses) and
+//! returns a starter anonymization policy to review
+//!
+//! ```cargo
+//! [dependencies]
+//! serde_json = "1.0"
+//! anyhow = "1.0"
+//! rustetl-core = { git = "https://github.com/boynoiz/rustetl", default-features = false, features = ["postgres"] }
+//! ```
+
+use rustetl_core::PipelineResult;
+use rustetl_core::db::PgConfig;
+use rustetl_core::db::postgres as db;
+use rustetl_core::pii::{self, DEFAULT_MIN_CONFIDENCE, DEFAULT_SAMPLE_SIZE};
+use rustetl_core::schema;
+
+/// `table`: table to scan (default `customers`).
+/// `database`: Windmill Postgres resource or `postgres://` URL; falls back
+/// to the DATABASE_URL env var.
+/// `sample_size`: random rows to look at (default 1,000).
+/// `min_confidence`: columns below this are kept as is in the drafted
+/// policy (default 0.5).
+fn main(
+    table: Option<String>,
+    database: Option<serde_json::Value>,
+    sample_size: Option<usize>,
+    min_confidence: Option<f64>,
+) -> anyhow::Result<PipelineResult> {
+    let mut run = PipelineResult::new("scan_pii");
+    let table = table.unwrap_or_else(|| "customers".to_string());
+    let sample_size = sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE);
+    let min_confidence = min_confidence.unwrap_or(DEFAULT_MIN_CONFIDENCE);
+    let config = PgConfig::resolve(database)?;
+
+    println!("🔍 PII Scan");
+    println!("━━━━━━━━━━━");
+    println!("  Database: {}", config);
+    println!("  Table: {}", table);
+    println!();
+
+    let mut client = db::connect(&config)?;
+    if !db::table_exists(&mut client, &table)? {
+        println!("❌ {} table not found", table);
+        run.fail(format!("{} table not found", table));
+        return Ok(run.finish());
+    }
+
+    run.stage("sample");
+    println!("📖 Sampling up to {} rows...", sample_size);
+    let df = db::query_frame(&mut client, &schema::sample_rows(&table, sample_size), &[])?;
+    run.rows_in = df.height();
+
+    run.stage("scan");
+    let report = pii::scan(&df, sample_size)?;
+
+    println!("\n📋 Columns:");
+    for finding in &report.columns {
+        match finding.kind {
+            Some(kind) if finding.confidence >= min_confidence => println!(
+                "  ⚠️ {}: {:?} ({:.0}%)",
+                finding.column,
+                kind,
+                finding.confidence * 100.0
+            ),
+            _ => println!("  ✓ {}", finding.column),
+        }
+    }
+
+    let pii_columns: Vec<&str> = report
+        .pii_columns(min_confidence)
+        .iter()
+        .map(|finding| finding.column.as_str())
+        .collect();
+    println!("\n✅ Found {} columns with personal data", pii_columns.len());
+    if !pii_columns.is_empty() {
+        run.warn(format!("{} holds personal data in: {}", table, pii_columns.join(", ")));
+    }
+
+    run.detail("table", &table)?;
+    run.detail("rows_sampled", report.rows_sampled)?;
+    run.detail("pii_columns", &pii_columns)?;
+    run.detail("columns", &report.columns)?;
+    run.detail("policy", report.policy_toml(min_confidence)?)?;
+    Ok(run.finish())
+}